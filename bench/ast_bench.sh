@@ -0,0 +1,47 @@
+#!/bin/sh
+#
+# Times parsing and evaluating large generated Lox inputs.
+#
+# Usage: bench/ast_bench.sh [binary]
+#
+# With no argument this builds and times the current tree. Pass another
+# build of the interpreter to compare against it on the same inputs.
+
+set -e
+
+cd "$(dirname "$0")/.."
+
+if [ -n "$1" ]; then
+  bin="$1"
+else
+  cargo build --quiet --release
+  bin=target/release/interpreter-starter-rust
+fi
+
+dir=$(mktemp -d)
+trap 'rm -rf "$dir"' EXIT
+
+# One balanced expression with 2^17 leaves, for `parse` and `evaluate`.
+awk 'function gen(d) {
+       if (d == 0) return (n++ % 9) + 1
+       op = substr("+-*", d % 3 + 1, 1)
+       return "(" gen(d - 1) " " op " " gen(d - 1) ")"
+     }
+     BEGIN { print gen(17) }' > "$dir/expr.lox"
+
+# Many small statements, for `run`.
+awk 'BEGIN {
+       for (i = 0; i < 200000; i++)
+         printf "print (%d + 2) * 3 - -%d / 4 == %d;\n", i, i, i
+     }' > "$dir/program.lox"
+
+run() {
+  start=$(date +%s%N)
+  "$bin" "$1" "$2" > /dev/null
+  end=$(date +%s%N)
+  printf '%-10s %6d ms\n' "$1" $(( (end - start) / 1000000 ))
+}
+
+run parse "$dir/expr.lox"
+run evaluate "$dir/expr.lox"
+run run "$dir/program.lox"
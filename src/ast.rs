@@ -1,13 +1,184 @@
 // ast.rs
-use crate::tokenizer::TokenType;
+use crate::tokenizer::{Span, Token};
 use std::fmt;
 
-pub enum Expr {
+/// Index of an expression in its `Ast`. Ids are stable for the life of the
+/// tree, so passes can key side tables by them (or index a parallel `Vec`).
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct ExprId(pub u32);
+
+/// Index of a statement in its `Ast`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct StmtId(pub u32);
+
+/// Owns every node of a parsed program. Nodes refer to their children by id
+/// rather than by pointer, so the whole tree lives in two flat vectors.
+#[derive(Default)]
+pub struct Ast {
+    exprs: Vec<Expr>,
+    stmts: Vec<Stmt>,
+}
+
+pub struct Expr {
+    pub span: Span,
+    pub kind: ExprKind,
+}
+
+pub enum ExprKind {
     Literal(LiteralValue),
     // We'll add more expression types later
-    Grouping(Box<Expr>),
-    Unary(TokenType, Box<Expr>),
-    Binary(Box<Expr>, TokenType, Box<Expr>),
+    Grouping(ExprId),
+    Unary(Token, ExprId),
+    Binary(ExprId, Token, ExprId),
+    /// Short-circuiting `and` / `or`.
+    Logical(ExprId, Token, ExprId),
+    /// `condition ? then_branch : else_branch`
+    Conditional(ExprId, ExprId, ExprId),
+    Variable(Token),
+    /// `name = value`
+    Assign(Token, ExprId),
+    /// `[a, b, c]`
+    List(Vec<ExprId>),
+    /// `{key: value, ...}`, entries in source order. Each entry keeps its
+    /// `:` for reporting errors about the key.
+    Map(Vec<(ExprId, Token, ExprId)>),
+    /// `object[index]`; the token is the `[`, for error reporting.
+    Index(ExprId, Token, ExprId),
+    /// `object[start:end]`, where either bound may be left out.
+    Slice(ExprId, Token, Option<ExprId>, Option<ExprId>),
+    /// `object[index] = value`
+    IndexSet(ExprId, Token, ExprId, ExprId),
+    /// `target op= value`, where the target is a variable or an index.
+    CompoundAssign(ExprId, Token, ExprId),
+    /// `++target` or `target--`; the flag is true for the prefix form.
+    Update(ExprId, Token, bool),
+    /// `object.name`
+    Get(ExprId, Token),
+    /// `callee(arguments)`; the token is the closing `)`.
+    Call(ExprId, Token, Vec<ExprId>),
+    /// `fun (params) { body }` or `(params) => body`, evaluating to a
+    /// closure. A `fun` declaration holds one too, without the `fun`.
+    Function(Vec<Token>, FunctionBody),
+    /// `match (subject) { pattern => value, ... }`, evaluating to the value
+    /// of the first arm that matches; the token is `match`.
+    Match(Token, ExprId, Vec<MatchArm<ExprId>>),
+}
+
+pub enum FunctionBody {
+    /// `{ statements }`, which can `return` a value.
+    Block(Vec<StmtId>),
+    /// The expression after `=>`, whose value is returned.
+    Expression(ExprId),
+}
+
+pub struct Stmt {
+    pub span: Span,
+    pub kind: StmtKind,
+}
+
+pub enum StmtKind {
+    Expression(ExprId),
+    Print(ExprId),
+    /// `var name = initializer;`
+    Var(Token, Option<ExprId>),
+    /// `fun name(params) { body }`; the expression is the `Function` node
+    /// for `(params) { body }`.
+    Function(Token, ExprId),
+    /// `return value;`, only inside a function.
+    Return(Token, Option<ExprId>),
+    /// `{ statements }`, with its own scope.
+    Block(Vec<StmtId>),
+    /// `for (name in iterable) body`; the token is `in`, for errors about
+    /// the iterable.
+    ForIn(Token, Token, ExprId, StmtId),
+    /// `break;`, only inside a loop.
+    Break(Token),
+    /// `continue;`, only inside a loop.
+    Continue(Token),
+    /// `throw value;`
+    Throw(Token, ExprId),
+    /// `try body catch (name) handler finally finalizer`, where the bodies
+    /// are blocks and at least one of the clauses is present.
+    Try(StmtId, Option<(Token, StmtId)>, Option<StmtId>),
+    /// `match (subject) { arms }`, running the first arm that matches; the
+    /// token is `match`, for reporting a value that no arm matches.
+    Match(Token, ExprId, Vec<MatchArm>),
+    /// `import "path" as name;` or `from "path" import names;`; the token
+    /// is the path string.
+    Import(Token, Imports),
+    /// `export var ...;`, only at the top level of a file. Importers can
+    /// only see a module's exported variables.
+    Export(Token, StmtId),
+}
+
+pub enum Imports {
+    /// `import "path" as name;` binds the module itself.
+    Module(Token),
+    /// `from "path" import a, b;` binds each of the module's variables.
+    Names(Vec<Token>),
+}
+
+/// `pattern if guard => body`, one arm of a `match`. The body is a
+/// statement in a `match` statement and an expression in a `match`
+/// expression.
+pub struct MatchArm<Body = StmtId> {
+    pub pattern: Pattern,
+    pub guard: Option<ExprId>,
+    pub body: Body,
+}
+
+pub struct Pattern {
+    pub span: Span,
+    pub kind: PatternKind,
+}
+
+pub enum PatternKind {
+    /// A literal or a negated number, which matches values equal to it.
+    Literal(ExprId),
+    /// A name, which matches anything and binds it. `_` binds nothing.
+    Binding(Token),
+    /// `[first, second, ..rest]`. Without a rest the list must have exactly
+    /// one element per pattern; with one, the remaining elements are bound
+    /// to it as a new list.
+    List(Vec<Pattern>, Option<Token>),
+}
+
+impl Pattern {
+    /// The names the pattern binds, in source order. `_` binds nothing, so
+    /// it is left out.
+    pub fn bindings<'a>(&'a self, out: &mut Vec<&'a Token>) {
+        match &self.kind {
+            PatternKind::Literal(_) => {}
+            PatternKind::Binding(name) if name.lexeme == "_" => {}
+            PatternKind::Binding(name) => out.push(name),
+            PatternKind::List(elements, rest) => {
+                for element in elements {
+                    element.bindings(out);
+                }
+                out.extend(rest.iter().filter(|rest| rest.lexeme != "_"));
+            }
+        }
+    }
+
+    /// The literal expressions in the pattern, in source order.
+    pub fn literals(&self, out: &mut Vec<ExprId>) {
+        match &self.kind {
+            PatternKind::Literal(expr) => out.push(*expr),
+            PatternKind::Binding(_) => {}
+            PatternKind::List(elements, _) => {
+                for element in elements {
+                    element.literals(out);
+                }
+            }
+        }
+    }
+}
+
+/// What a source file parsed to: a single bare expression, as the `parse`
+/// and `evaluate` commands accept, or a program of statements.
+pub enum Root {
+    Expression(ExprId),
+    Program(Vec<StmtId>),
 }
 
 #[derive(Debug, Clone, PartialEq)]
@@ -18,16 +189,253 @@ pub enum LiteralValue {
     Nil,
 }
 
-impl fmt::Display for Expr {
-    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        match self {
-            Expr::Literal(value) => write!(f, "{}", value),
-            Expr::Grouping(expr) => write!(f, "(group {})", expr),
-            Expr::Unary(operator, expr) => {
-                write!(f, "({} {})", operator.to_string_for_parse(), expr)
+/// Operations over expressions, one method per node kind. Each method gets
+/// the tree and the node's own id alongside its fields.
+pub trait ExprVisitor<R> {
+    fn visit_literal(&mut self, ast: &Ast, id: ExprId, value: &LiteralValue) -> R;
+    fn visit_grouping(&mut self, ast: &Ast, id: ExprId, inner: ExprId) -> R;
+    fn visit_unary(&mut self, ast: &Ast, id: ExprId, operator: &Token, right: ExprId) -> R;
+    fn visit_binary(
+        &mut self,
+        ast: &Ast,
+        id: ExprId,
+        left: ExprId,
+        operator: &Token,
+        right: ExprId,
+    ) -> R;
+    fn visit_logical(
+        &mut self,
+        ast: &Ast,
+        id: ExprId,
+        left: ExprId,
+        operator: &Token,
+        right: ExprId,
+    ) -> R;
+    fn visit_conditional(
+        &mut self,
+        ast: &Ast,
+        id: ExprId,
+        condition: ExprId,
+        then_branch: ExprId,
+        else_branch: ExprId,
+    ) -> R;
+    fn visit_variable(&mut self, ast: &Ast, id: ExprId, name: &Token) -> R;
+    fn visit_assign(&mut self, ast: &Ast, id: ExprId, name: &Token, value: ExprId) -> R;
+    fn visit_list(&mut self, ast: &Ast, id: ExprId, elements: &[ExprId]) -> R;
+    fn visit_map(&mut self, ast: &Ast, id: ExprId, entries: &[(ExprId, Token, ExprId)]) -> R;
+    fn visit_index(
+        &mut self,
+        ast: &Ast,
+        id: ExprId,
+        object: ExprId,
+        bracket: &Token,
+        index: ExprId,
+    ) -> R;
+    fn visit_slice(
+        &mut self,
+        ast: &Ast,
+        id: ExprId,
+        object: ExprId,
+        bracket: &Token,
+        start: Option<ExprId>,
+        end: Option<ExprId>,
+    ) -> R;
+    fn visit_index_set(
+        &mut self,
+        ast: &Ast,
+        id: ExprId,
+        object: ExprId,
+        bracket: &Token,
+        index: ExprId,
+        value: ExprId,
+    ) -> R;
+    fn visit_compound_assign(
+        &mut self,
+        ast: &Ast,
+        id: ExprId,
+        target: ExprId,
+        operator: &Token,
+        value: ExprId,
+    ) -> R;
+    fn visit_update(
+        &mut self,
+        ast: &Ast,
+        id: ExprId,
+        target: ExprId,
+        operator: &Token,
+        prefix: bool,
+    ) -> R;
+    fn visit_get(&mut self, ast: &Ast, id: ExprId, object: ExprId, name: &Token) -> R;
+    fn visit_call(
+        &mut self,
+        ast: &Ast,
+        id: ExprId,
+        callee: ExprId,
+        paren: &Token,
+        arguments: &[ExprId],
+    ) -> R;
+    fn visit_function(&mut self, ast: &Ast, id: ExprId, params: &[Token], body: &FunctionBody)
+        -> R;
+    fn visit_match(
+        &mut self,
+        ast: &Ast,
+        id: ExprId,
+        keyword: &Token,
+        subject: ExprId,
+        arms: &[MatchArm<ExprId>],
+    ) -> R;
+}
+
+/// Operations over statements, one method per node kind.
+pub trait StmtVisitor<R> {
+    fn visit_expression_stmt(&mut self, ast: &Ast, id: StmtId, expr: ExprId) -> R;
+    fn visit_print_stmt(&mut self, ast: &Ast, id: StmtId, expr: ExprId) -> R;
+    fn visit_var_stmt(
+        &mut self,
+        ast: &Ast,
+        id: StmtId,
+        name: &Token,
+        initializer: Option<ExprId>,
+    ) -> R;
+    fn visit_function_stmt(&mut self, ast: &Ast, id: StmtId, name: &Token, function: ExprId) -> R;
+    fn visit_return_stmt(
+        &mut self,
+        ast: &Ast,
+        id: StmtId,
+        keyword: &Token,
+        value: Option<ExprId>,
+    ) -> R;
+    fn visit_block_stmt(&mut self, ast: &Ast, id: StmtId, statements: &[StmtId]) -> R;
+    fn visit_for_in_stmt(
+        &mut self,
+        ast: &Ast,
+        id: StmtId,
+        name: &Token,
+        keyword: &Token,
+        iterable: ExprId,
+        body: StmtId,
+    ) -> R;
+    fn visit_break_stmt(&mut self, ast: &Ast, id: StmtId, keyword: &Token) -> R;
+    fn visit_continue_stmt(&mut self, ast: &Ast, id: StmtId, keyword: &Token) -> R;
+    fn visit_throw_stmt(&mut self, ast: &Ast, id: StmtId, keyword: &Token, value: ExprId) -> R;
+    fn visit_try_stmt(
+        &mut self,
+        ast: &Ast,
+        id: StmtId,
+        body: StmtId,
+        catch: Option<&(Token, StmtId)>,
+        finally: Option<StmtId>,
+    ) -> R;
+    fn visit_match_stmt(
+        &mut self,
+        ast: &Ast,
+        id: StmtId,
+        keyword: &Token,
+        subject: ExprId,
+        arms: &[MatchArm],
+    ) -> R;
+    fn visit_import_stmt(&mut self, ast: &Ast, id: StmtId, path: &Token, imports: &Imports) -> R;
+    fn visit_export_stmt(
+        &mut self,
+        ast: &Ast,
+        id: StmtId,
+        keyword: &Token,
+        declaration: StmtId,
+    ) -> R;
+}
+
+impl Ast {
+    pub fn add_expr(&mut self, span: Span, kind: ExprKind) -> ExprId {
+        self.exprs.push(Expr { span, kind });
+        ExprId(self.exprs.len() as u32 - 1)
+    }
+
+    pub fn add_stmt(&mut self, span: Span, kind: StmtKind) -> StmtId {
+        self.stmts.push(Stmt { span, kind });
+        StmtId(self.stmts.len() as u32 - 1)
+    }
+
+    pub fn expr(&self, id: ExprId) -> &Expr {
+        &self.exprs[id.0 as usize]
+    }
+
+    pub fn stmt(&self, id: StmtId) -> &Stmt {
+        &self.stmts[id.0 as usize]
+    }
+
+    pub fn accept_expr<R>(&self, id: ExprId, visitor: &mut impl ExprVisitor<R>) -> R {
+        match &self.expr(id).kind {
+            ExprKind::Literal(value) => visitor.visit_literal(self, id, value),
+            ExprKind::Grouping(inner) => visitor.visit_grouping(self, id, *inner),
+            ExprKind::Unary(operator, right) => visitor.visit_unary(self, id, operator, *right),
+            ExprKind::Binary(left, operator, right) => {
+                visitor.visit_binary(self, id, *left, operator, *right)
+            }
+            ExprKind::Logical(left, operator, right) => {
+                visitor.visit_logical(self, id, *left, operator, *right)
+            }
+            ExprKind::Conditional(condition, then_branch, else_branch) => {
+                visitor.visit_conditional(self, id, *condition, *then_branch, *else_branch)
+            }
+            ExprKind::Variable(name) => visitor.visit_variable(self, id, name),
+            ExprKind::Assign(name, value) => visitor.visit_assign(self, id, name, *value),
+            ExprKind::List(elements) => visitor.visit_list(self, id, elements),
+            ExprKind::Map(entries) => visitor.visit_map(self, id, entries),
+            ExprKind::Index(object, bracket, index) => {
+                visitor.visit_index(self, id, *object, bracket, *index)
+            }
+            ExprKind::Slice(object, bracket, start, end) => {
+                visitor.visit_slice(self, id, *object, bracket, *start, *end)
+            }
+            ExprKind::IndexSet(object, bracket, index, value) => {
+                visitor.visit_index_set(self, id, *object, bracket, *index, *value)
+            }
+            ExprKind::CompoundAssign(target, operator, value) => {
+                visitor.visit_compound_assign(self, id, *target, operator, *value)
+            }
+            ExprKind::Update(target, operator, prefix) => {
+                visitor.visit_update(self, id, *target, operator, *prefix)
+            }
+            ExprKind::Get(object, name) => visitor.visit_get(self, id, *object, name),
+            ExprKind::Call(callee, paren, arguments) => {
+                visitor.visit_call(self, id, *callee, paren, arguments)
+            }
+            ExprKind::Function(params, body) => visitor.visit_function(self, id, params, body),
+            ExprKind::Match(keyword, subject, arms) => {
+                visitor.visit_match(self, id, keyword, *subject, arms)
+            }
+        }
+    }
+
+    pub fn accept_stmt<R>(&self, id: StmtId, visitor: &mut impl StmtVisitor<R>) -> R {
+        match &self.stmt(id).kind {
+            StmtKind::Expression(expr) => visitor.visit_expression_stmt(self, id, *expr),
+            StmtKind::Print(expr) => visitor.visit_print_stmt(self, id, *expr),
+            StmtKind::Var(name, initializer) => {
+                visitor.visit_var_stmt(self, id, name, *initializer)
+            }
+            StmtKind::Function(name, function) => {
+                visitor.visit_function_stmt(self, id, name, *function)
+            }
+            StmtKind::Return(keyword, value) => {
+                visitor.visit_return_stmt(self, id, keyword, *value)
+            }
+            StmtKind::Block(statements) => visitor.visit_block_stmt(self, id, statements),
+            StmtKind::ForIn(name, keyword, iterable, body) => {
+                visitor.visit_for_in_stmt(self, id, name, keyword, *iterable, *body)
+            }
+            StmtKind::Break(keyword) => visitor.visit_break_stmt(self, id, keyword),
+            StmtKind::Continue(keyword) => visitor.visit_continue_stmt(self, id, keyword),
+            StmtKind::Throw(keyword, value) => visitor.visit_throw_stmt(self, id, keyword, *value),
+            StmtKind::Try(body, catch, finally) => {
+                visitor.visit_try_stmt(self, id, *body, catch.as_ref(), *finally)
+            }
+            StmtKind::Match(keyword, subject, arms) => {
+                visitor.visit_match_stmt(self, id, keyword, *subject, arms)
             }
-            Expr::Binary(left, operator, right) => {
-                write!(f, "({} {} {})", operator.to_string_for_parse(), left, right)
+            StmtKind::Import(path, imports) => visitor.visit_import_stmt(self, id, path, imports),
+            StmtKind::Export(keyword, declaration) => {
+                visitor.visit_export_stmt(self, id, keyword, *declaration)
             }
         }
     }
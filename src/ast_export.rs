@@ -0,0 +1,979 @@
+// ast_export.rs
+use crate::ast::{
+    Ast, ExprId, ExprVisitor, FunctionBody, Imports, LiteralValue, MatchArm, Pattern, PatternKind,
+    Root, StmtId, StmtVisitor,
+};
+use crate::ast_printer::AstPrinter;
+use crate::json::Json;
+use crate::tokenizer::{Span, Token};
+use crate::unparser::Unparser;
+use std::fmt::Write;
+
+/// Output formats for `parse --format=...`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Format {
+    /// Parenthesized S-expressions, one statement per line.
+    Sexpr,
+    /// Nested objects with node kinds, spans and literal values.
+    Json,
+    /// A Graphviz digraph.
+    Dot,
+    /// An indented tree, one node per line.
+    Tree,
+    /// Lox source with only the parentheses the tree needs.
+    Lox,
+}
+
+impl Format {
+    pub fn from_name(name: &str) -> Option<Format> {
+        match name {
+            "sexpr" => Some(Format::Sexpr),
+            "json" => Some(Format::Json),
+            "dot" => Some(Format::Dot),
+            "tree" => Some(Format::Tree),
+            "lox" => Some(Format::Lox),
+            _ => None,
+        }
+    }
+}
+
+/// Renders the tree in `format`. Only Lox output can fail, for trees that
+/// have no Lox source.
+pub fn export(ast: &Ast, root: &Root, format: Format) -> Result<String, String> {
+    Ok(match format {
+        Format::Sexpr => match root {
+            Root::Expression(expr) => format!("{}\n", AstPrinter::print(ast, *expr)),
+            Root::Program(statements) => statements
+                .iter()
+                .map(|&stmt| format!("{}\n", AstPrinter::print_stmt(ast, stmt)))
+                .collect(),
+        },
+        Format::Json => format!("{}\n", to_json(&node(ast, root)).to_pretty()),
+        Format::Dot => to_dot(&node(ast, root)),
+        Format::Tree => {
+            let mut out = String::new();
+            write_tree(&node(ast, root), &mut out, "", "");
+            out
+        }
+        Format::Lox => match root {
+            Root::Expression(expr) => format!("{}\n", Unparser::expr(ast, *expr)?),
+            Root::Program(statements) => statements
+                .iter()
+                .map(|&stmt| Unparser::stmt(ast, stmt).map(|text| format!("{}\n", text)))
+                .collect::<Result<_, _>>()?,
+        },
+    })
+}
+
+/// The tree as JSON without spans or line numbers, so that two trees
+/// compare equal exactly when they differ only in layout.
+pub fn structure(ast: &Ast, root: &Root) -> Json {
+    without_positions(to_json(&node(ast, root)))
+}
+
+fn without_positions(json: Json) -> Json {
+    match json {
+        Json::Object(entries) => Json::Object(
+            entries
+                .into_iter()
+                .filter(|(key, _)| key != "span" && key != "line")
+                .map(|(key, value)| (key, without_positions(value)))
+                .collect(),
+        ),
+        Json::Array(items) => Json::Array(items.into_iter().map(without_positions).collect()),
+        scalar => scalar,
+    }
+}
+
+/// A format-neutral view of one AST node that the exporters render.
+struct Node {
+    kind: &'static str,
+    /// Unique within the export, e.g. `e3` for `ExprId(3)`.
+    name: String,
+    span: Option<Span>,
+    /// Scalar fields such as operators and literal values.
+    fields: Vec<(&'static str, Json)>,
+    children: Vec<(&'static str, Children)>,
+}
+
+enum Children {
+    One(Node),
+    Many(Vec<Node>),
+}
+
+impl Node {
+    /// The kind followed by the scalar fields, e.g. `Binary +`.
+    fn label(&self) -> String {
+        let mut label = self.kind.to_string();
+        for (key, value) in &self.fields {
+            match value {
+                _ if *key == "line" => {}
+                Json::String(s) if self.kind != "Literal" => {
+                    let _ = write!(label, " {}", s);
+                }
+                _ => {
+                    let _ = write!(label, " {}", value);
+                }
+            }
+        }
+        label
+    }
+}
+
+fn node(ast: &Ast, root: &Root) -> Node {
+    match root {
+        Root::Expression(expr) => ast.accept_expr(*expr, &mut NodeBuilder),
+        Root::Program(statements) => Node {
+            kind: "Program",
+            name: "program".to_string(),
+            span: None,
+            fields: Vec::new(),
+            children: vec![(
+                "statements",
+                Children::Many(
+                    statements
+                        .iter()
+                        .map(|&stmt| ast.accept_stmt(stmt, &mut NodeBuilder))
+                        .collect(),
+                ),
+            )],
+        },
+    }
+}
+
+struct NodeBuilder;
+
+impl NodeBuilder {
+    fn expr(
+        &mut self,
+        ast: &Ast,
+        id: ExprId,
+        kind: &'static str,
+        fields: Vec<(&'static str, Json)>,
+        children: &[(&'static str, ExprId)],
+    ) -> Node {
+        let children = children
+            .iter()
+            .map(|&(role, child)| (role, Children::One(ast.accept_expr(child, self))))
+            .collect();
+        self.expr_with(ast, id, kind, fields, children)
+    }
+
+    fn expr_with(
+        &mut self,
+        ast: &Ast,
+        id: ExprId,
+        kind: &'static str,
+        fields: Vec<(&'static str, Json)>,
+        children: Vec<(&'static str, Children)>,
+    ) -> Node {
+        Node {
+            kind,
+            name: format!("e{}", id.0),
+            span: Some(ast.expr(id).span),
+            fields,
+            children,
+        }
+    }
+
+    fn many(&mut self, ast: &Ast, exprs: &[ExprId]) -> Children {
+        Children::Many(
+            exprs
+                .iter()
+                .map(|&expr| ast.accept_expr(expr, self))
+                .collect(),
+        )
+    }
+
+    /// The arms of a `match` of either form, named after the `match`.
+    fn arms<Body>(
+        &mut self,
+        ast: &Ast,
+        owner: &str,
+        arms: &[MatchArm<Body>],
+        build_body: impl Fn(&mut Self, &Body) -> Node,
+    ) -> Vec<Node> {
+        arms.iter()
+            .enumerate()
+            .map(|(i, arm)| {
+                let name = format!("{}_{}", owner, i);
+                let mut children = vec![(
+                    "pattern",
+                    Children::One(pattern(ast, &arm.pattern, format!("{}p", name))),
+                )];
+                if let Some(guard) = arm.guard {
+                    children.push(("guard", Children::One(ast.accept_expr(guard, self))));
+                }
+                children.push(("body", Children::One(build_body(self, &arm.body))));
+                Node {
+                    kind: "Arm",
+                    name,
+                    span: None,
+                    fields: Vec::new(),
+                    children,
+                }
+            })
+            .collect()
+    }
+
+    fn stmt(&mut self, ast: &Ast, id: StmtId, kind: &'static str, expr: ExprId) -> Node {
+        self.stmt_with(ast, id, kind, Vec::new(), &[("expression", expr)])
+    }
+
+    fn stmt_with(
+        &mut self,
+        ast: &Ast,
+        id: StmtId,
+        kind: &'static str,
+        fields: Vec<(&'static str, Json)>,
+        children: &[(&'static str, ExprId)],
+    ) -> Node {
+        Node {
+            kind,
+            name: format!("s{}", id.0),
+            span: Some(ast.stmt(id).span),
+            fields,
+            children: children
+                .iter()
+                .map(|&(role, child)| (role, Children::One(ast.accept_expr(child, self))))
+                .collect(),
+        }
+    }
+}
+
+/// A pattern and, for list patterns, its element patterns. `name` is unique
+/// within the export.
+fn pattern(ast: &Ast, pattern: &Pattern, name: String) -> Node {
+    let (kind, fields, children) = match &pattern.kind {
+        PatternKind::Literal(expr) => (
+            "LiteralPattern",
+            Vec::new(),
+            vec![(
+                "value",
+                Children::One(ast.accept_expr(*expr, &mut NodeBuilder)),
+            )],
+        ),
+        PatternKind::Binding(token) => ("BindingPattern", self::name(token), Vec::new()),
+        PatternKind::List(elements, rest) => {
+            let elements = elements
+                .iter()
+                .enumerate()
+                .map(|(i, element)| self::pattern(ast, element, format!("{}_{}", name, i)))
+                .collect();
+            let fields = match rest {
+                Some(rest) => vec![("rest", Json::String(rest.lexeme.clone()))],
+                None => Vec::new(),
+            };
+            (
+                "ListPattern",
+                fields,
+                vec![("elements", Children::Many(elements))],
+            )
+        }
+    };
+    Node {
+        kind,
+        name,
+        span: Some(pattern.span),
+        fields,
+        children,
+    }
+}
+
+fn operator(token: &Token) -> Vec<(&'static str, Json)> {
+    vec![
+        ("operator", Json::String(token.lexeme.clone())),
+        ("line", line(token)),
+    ]
+}
+
+fn name(token: &Token) -> Vec<(&'static str, Json)> {
+    vec![
+        ("name", Json::String(token.lexeme.clone())),
+        ("line", line(token)),
+    ]
+}
+
+/// The line of the token that runtime errors for the node are reported at.
+fn line(token: &Token) -> Json {
+    Json::Number(token.line as f64)
+}
+
+impl ExprVisitor<Node> for NodeBuilder {
+    fn visit_literal(&mut self, ast: &Ast, id: ExprId, value: &LiteralValue) -> Node {
+        let value = match value {
+            LiteralValue::Number(n) => Json::Number(*n),
+            LiteralValue::String(s) => Json::String(s.clone()),
+            LiteralValue::Bool(b) => Json::Bool(*b),
+            LiteralValue::Nil => Json::Null,
+        };
+        self.expr(ast, id, "Literal", vec![("value", value)], &[])
+    }
+
+    fn visit_grouping(&mut self, ast: &Ast, id: ExprId, inner: ExprId) -> Node {
+        self.expr(ast, id, "Grouping", Vec::new(), &[("expression", inner)])
+    }
+
+    fn visit_unary(&mut self, ast: &Ast, id: ExprId, operator: &Token, right: ExprId) -> Node {
+        self.expr(
+            ast,
+            id,
+            "Unary",
+            self::operator(operator),
+            &[("right", right)],
+        )
+    }
+
+    fn visit_binary(
+        &mut self,
+        ast: &Ast,
+        id: ExprId,
+        left: ExprId,
+        operator: &Token,
+        right: ExprId,
+    ) -> Node {
+        self.expr(
+            ast,
+            id,
+            "Binary",
+            self::operator(operator),
+            &[("left", left), ("right", right)],
+        )
+    }
+
+    fn visit_logical(
+        &mut self,
+        ast: &Ast,
+        id: ExprId,
+        left: ExprId,
+        operator: &Token,
+        right: ExprId,
+    ) -> Node {
+        self.expr(
+            ast,
+            id,
+            "Logical",
+            self::operator(operator),
+            &[("left", left), ("right", right)],
+        )
+    }
+
+    fn visit_conditional(
+        &mut self,
+        ast: &Ast,
+        id: ExprId,
+        condition: ExprId,
+        then_branch: ExprId,
+        else_branch: ExprId,
+    ) -> Node {
+        self.expr(
+            ast,
+            id,
+            "Conditional",
+            Vec::new(),
+            &[
+                ("condition", condition),
+                ("then", then_branch),
+                ("else", else_branch),
+            ],
+        )
+    }
+
+    fn visit_variable(&mut self, ast: &Ast, id: ExprId, name: &Token) -> Node {
+        self.expr(ast, id, "Variable", self::name(name), &[])
+    }
+
+    fn visit_assign(&mut self, ast: &Ast, id: ExprId, name: &Token, value: ExprId) -> Node {
+        self.expr(ast, id, "Assign", self::name(name), &[("value", value)])
+    }
+
+    fn visit_list(&mut self, ast: &Ast, id: ExprId, elements: &[ExprId]) -> Node {
+        let elements = self.many(ast, elements);
+        self.expr_with(ast, id, "List", Vec::new(), vec![("elements", elements)])
+    }
+
+    fn visit_map(&mut self, ast: &Ast, id: ExprId, entries: &[(ExprId, Token, ExprId)]) -> Node {
+        let entries = entries
+            .iter()
+            .enumerate()
+            .map(|(i, &(key, ref colon, value))| Node {
+                kind: "Entry",
+                name: format!("e{}_{}", id.0, i),
+                span: None,
+                fields: vec![("line", line(colon))],
+                children: vec![
+                    ("key", Children::One(ast.accept_expr(key, self))),
+                    ("value", Children::One(ast.accept_expr(value, self))),
+                ],
+            })
+            .collect();
+        self.expr_with(
+            ast,
+            id,
+            "Map",
+            Vec::new(),
+            vec![("entries", Children::Many(entries))],
+        )
+    }
+
+    fn visit_index(
+        &mut self,
+        ast: &Ast,
+        id: ExprId,
+        object: ExprId,
+        bracket: &Token,
+        index: ExprId,
+    ) -> Node {
+        self.expr(
+            ast,
+            id,
+            "Index",
+            vec![("line", line(bracket))],
+            &[("object", object), ("index", index)],
+        )
+    }
+
+    fn visit_slice(
+        &mut self,
+        ast: &Ast,
+        id: ExprId,
+        object: ExprId,
+        bracket: &Token,
+        start: Option<ExprId>,
+        end: Option<ExprId>,
+    ) -> Node {
+        let mut children = vec![("object", object)];
+        children.extend(start.map(|start| ("start", start)));
+        children.extend(end.map(|end| ("end", end)));
+        self.expr(ast, id, "Slice", vec![("line", line(bracket))], &children)
+    }
+
+    fn visit_index_set(
+        &mut self,
+        ast: &Ast,
+        id: ExprId,
+        object: ExprId,
+        bracket: &Token,
+        index: ExprId,
+        value: ExprId,
+    ) -> Node {
+        self.expr(
+            ast,
+            id,
+            "IndexSet",
+            vec![("line", line(bracket))],
+            &[("object", object), ("index", index), ("value", value)],
+        )
+    }
+
+    fn visit_compound_assign(
+        &mut self,
+        ast: &Ast,
+        id: ExprId,
+        target: ExprId,
+        operator: &Token,
+        value: ExprId,
+    ) -> Node {
+        self.expr(
+            ast,
+            id,
+            "CompoundAssign",
+            self::operator(operator),
+            &[("target", target), ("value", value)],
+        )
+    }
+
+    fn visit_update(
+        &mut self,
+        ast: &Ast,
+        id: ExprId,
+        target: ExprId,
+        operator: &Token,
+        prefix: bool,
+    ) -> Node {
+        let mut fields = self::operator(operator);
+        fields.push(("prefix", Json::Bool(prefix)));
+        self.expr(ast, id, "Update", fields, &[("target", target)])
+    }
+
+    fn visit_get(&mut self, ast: &Ast, id: ExprId, object: ExprId, name: &Token) -> Node {
+        self.expr(ast, id, "Get", self::name(name), &[("object", object)])
+    }
+
+    fn visit_call(
+        &mut self,
+        ast: &Ast,
+        id: ExprId,
+        callee: ExprId,
+        paren: &Token,
+        arguments: &[ExprId],
+    ) -> Node {
+        let callee = Children::One(ast.accept_expr(callee, self));
+        let arguments = self.many(ast, arguments);
+        self.expr_with(
+            ast,
+            id,
+            "Call",
+            vec![("line", line(paren))],
+            vec![("callee", callee), ("arguments", arguments)],
+        )
+    }
+
+    fn visit_function(
+        &mut self,
+        ast: &Ast,
+        id: ExprId,
+        params: &[Token],
+        body: &FunctionBody,
+    ) -> Node {
+        let params = Json::Array(
+            params
+                .iter()
+                .map(|param| Json::String(param.lexeme.clone()))
+                .collect(),
+        );
+        let (kind, body) = match body {
+            FunctionBody::Block(statements) => (
+                "Function",
+                Children::Many(
+                    statements
+                        .iter()
+                        .map(|&stmt| ast.accept_stmt(stmt, self))
+                        .collect(),
+                ),
+            ),
+            FunctionBody::Expression(expr) => {
+                ("Arrow", Children::One(ast.accept_expr(*expr, self)))
+            }
+        };
+        self.expr_with(
+            ast,
+            id,
+            kind,
+            vec![("params", params)],
+            vec![("body", body)],
+        )
+    }
+
+    fn visit_match(
+        &mut self,
+        ast: &Ast,
+        id: ExprId,
+        keyword: &Token,
+        subject: ExprId,
+        arms: &[MatchArm<ExprId>],
+    ) -> Node {
+        let name = format!("e{}", id.0);
+        let arms = self.arms(ast, &name, arms, |builder, body| {
+            ast.accept_expr(*body, builder)
+        });
+        let subject = ast.accept_expr(subject, self);
+        self.expr_with(
+            ast,
+            id,
+            "Match",
+            vec![("line", line(keyword))],
+            vec![
+                ("subject", Children::One(subject)),
+                ("arms", Children::Many(arms)),
+            ],
+        )
+    }
+}
+
+impl StmtVisitor<Node> for NodeBuilder {
+    fn visit_expression_stmt(&mut self, ast: &Ast, id: StmtId, expr: ExprId) -> Node {
+        self.stmt(ast, id, "Expression", expr)
+    }
+
+    fn visit_print_stmt(&mut self, ast: &Ast, id: StmtId, expr: ExprId) -> Node {
+        self.stmt(ast, id, "Print", expr)
+    }
+
+    fn visit_var_stmt(
+        &mut self,
+        ast: &Ast,
+        id: StmtId,
+        name: &Token,
+        initializer: Option<ExprId>,
+    ) -> Node {
+        let children: Vec<_> = initializer
+            .map(|initializer| ("initializer", initializer))
+            .into_iter()
+            .collect();
+        self.stmt_with(ast, id, "Var", self::name(name), &children)
+    }
+
+    fn visit_function_stmt(
+        &mut self,
+        ast: &Ast,
+        id: StmtId,
+        name: &Token,
+        function: ExprId,
+    ) -> Node {
+        self.stmt_with(ast, id, "Fun", self::name(name), &[("function", function)])
+    }
+
+    fn visit_return_stmt(
+        &mut self,
+        ast: &Ast,
+        id: StmtId,
+        keyword: &Token,
+        value: Option<ExprId>,
+    ) -> Node {
+        let children: Vec<_> = value.map(|value| ("value", value)).into_iter().collect();
+        self.stmt_with(ast, id, "Return", vec![("line", line(keyword))], &children)
+    }
+
+    fn visit_for_in_stmt(
+        &mut self,
+        ast: &Ast,
+        id: StmtId,
+        name: &Token,
+        _keyword: &Token,
+        iterable: ExprId,
+        body: StmtId,
+    ) -> Node {
+        // `line` is the loop variable's, which `in` normally shares.
+        Node {
+            kind: "ForIn",
+            name: format!("s{}", id.0),
+            span: Some(ast.stmt(id).span),
+            fields: self::name(name),
+            children: vec![
+                ("iterable", Children::One(ast.accept_expr(iterable, self))),
+                ("body", Children::One(ast.accept_stmt(body, self))),
+            ],
+        }
+    }
+
+    fn visit_break_stmt(&mut self, ast: &Ast, id: StmtId, keyword: &Token) -> Node {
+        self.stmt_with(ast, id, "Break", vec![("line", line(keyword))], &[])
+    }
+
+    fn visit_continue_stmt(&mut self, ast: &Ast, id: StmtId, keyword: &Token) -> Node {
+        self.stmt_with(ast, id, "Continue", vec![("line", line(keyword))], &[])
+    }
+
+    fn visit_throw_stmt(&mut self, ast: &Ast, id: StmtId, keyword: &Token, value: ExprId) -> Node {
+        self.stmt_with(
+            ast,
+            id,
+            "Throw",
+            vec![("line", line(keyword))],
+            &[("value", value)],
+        )
+    }
+
+    fn visit_try_stmt(
+        &mut self,
+        ast: &Ast,
+        id: StmtId,
+        body: StmtId,
+        catch: Option<&(Token, StmtId)>,
+        finally: Option<StmtId>,
+    ) -> Node {
+        let mut fields = Vec::new();
+        let mut children = vec![("body", Children::One(ast.accept_stmt(body, self)))];
+        if let Some((name, handler)) = catch {
+            fields = self::name(name);
+            children.push(("catch", Children::One(ast.accept_stmt(*handler, self))));
+        }
+        if let Some(finally) = finally {
+            children.push(("finally", Children::One(ast.accept_stmt(finally, self))));
+        }
+        Node {
+            kind: "Try",
+            name: format!("s{}", id.0),
+            span: Some(ast.stmt(id).span),
+            fields,
+            children,
+        }
+    }
+
+    fn visit_match_stmt(
+        &mut self,
+        ast: &Ast,
+        id: StmtId,
+        keyword: &Token,
+        subject: ExprId,
+        arms: &[MatchArm],
+    ) -> Node {
+        let name = format!("s{}", id.0);
+        let arms = self.arms(ast, &name, arms, |builder, body| {
+            ast.accept_stmt(*body, builder)
+        });
+        Node {
+            kind: "Match",
+            name,
+            span: Some(ast.stmt(id).span),
+            fields: vec![("line", line(keyword))],
+            children: vec![
+                ("subject", Children::One(ast.accept_expr(subject, self))),
+                ("arms", Children::Many(arms)),
+            ],
+        }
+    }
+
+    fn visit_import_stmt(
+        &mut self,
+        ast: &Ast,
+        id: StmtId,
+        path: &Token,
+        imports: &Imports,
+    ) -> Node {
+        let mut fields = vec![
+            ("path", Json::String(path.literal.clone())),
+            ("line", line(path)),
+        ];
+        match imports {
+            Imports::Module(name) => fields.push(("alias", Json::String(name.lexeme.clone()))),
+            Imports::Names(names) => fields.push((
+                "names",
+                Json::Array(
+                    names
+                        .iter()
+                        .map(|name| Json::String(name.lexeme.clone()))
+                        .collect(),
+                ),
+            )),
+        }
+        self.stmt_with(ast, id, "Import", fields, &[])
+    }
+
+    fn visit_export_stmt(
+        &mut self,
+        ast: &Ast,
+        id: StmtId,
+        keyword: &Token,
+        declaration: StmtId,
+    ) -> Node {
+        Node {
+            kind: "Export",
+            name: format!("s{}", id.0),
+            span: Some(ast.stmt(id).span),
+            fields: vec![("line", line(keyword))],
+            children: vec![(
+                "declaration",
+                Children::One(ast.accept_stmt(declaration, self)),
+            )],
+        }
+    }
+
+    fn visit_block_stmt(&mut self, ast: &Ast, id: StmtId, statements: &[StmtId]) -> Node {
+        let statements = statements
+            .iter()
+            .map(|&stmt| ast.accept_stmt(stmt, self))
+            .collect();
+        Node {
+            kind: "Block",
+            name: format!("s{}", id.0),
+            span: Some(ast.stmt(id).span),
+            fields: Vec::new(),
+            children: vec![("statements", Children::Many(statements))],
+        }
+    }
+}
+
+fn to_json(node: &Node) -> Json {
+    let mut entries = vec![("kind".to_string(), Json::String(node.kind.to_string()))];
+    if let Some(span) = node.span {
+        entries.push((
+            "span".to_string(),
+            Json::Array(vec![
+                Json::Number(span.start as f64),
+                Json::Number(span.end as f64),
+            ]),
+        ));
+    }
+    for (key, value) in &node.fields {
+        entries.push((key.to_string(), value.clone()));
+    }
+    for (role, children) in &node.children {
+        let value = match children {
+            Children::One(child) => to_json(child),
+            Children::Many(children) => Json::Array(children.iter().map(to_json).collect()),
+        };
+        entries.push((role.to_string(), value));
+    }
+    Json::Object(entries)
+}
+
+fn to_dot(root: &Node) -> String {
+    fn walk(node: &Node, out: &mut String) {
+        let _ = writeln!(
+            out,
+            "  {} [label={}];",
+            node.name,
+            Json::String(node.label())
+        );
+        for (role, children) in &node.children {
+            let children = match children {
+                Children::One(child) => std::slice::from_ref(child),
+                Children::Many(children) => children.as_slice(),
+            };
+            for child in children {
+                let _ = writeln!(
+                    out,
+                    "  {} -> {} [label=\"{}\"];",
+                    node.name, child.name, role
+                );
+                walk(child, out);
+            }
+        }
+    }
+
+    let mut out = String::from("digraph ast {\n  node [shape=box, fontname=\"monospace\"];\n");
+    walk(root, &mut out);
+    out.push_str("}\n");
+    out
+}
+
+/// Writes `node` and its descendants with box-drawing guides. `prefix`
+/// starts the node's own line and `indent` starts its children's lines.
+fn write_tree(node: &Node, out: &mut String, prefix: &str, indent: &str) {
+    let _ = write!(out, "{}{}", prefix, node.label());
+    if let Some(span) = node.span {
+        let _ = write!(out, " @{}..{}", span.start, span.end);
+    }
+    out.push('\n');
+
+    let children: Vec<(&str, &Node)> = node
+        .children
+        .iter()
+        .flat_map(|(role, children)| match children {
+            Children::One(child) => vec![(*role, child)],
+            Children::Many(children) => children.iter().map(|child| (*role, child)).collect(),
+        })
+        .collect();
+    for (i, (role, child)) in children.iter().enumerate() {
+        let last = i + 1 == children.len();
+        let branch = if last { "└── " } else { "├── " };
+        let guide = if last { "    " } else { "│   " };
+        write_tree(
+            child,
+            out,
+            &format!("{}{}{}: ", indent, branch, role),
+            &format!("{}{}", indent, guide),
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parser::Parser;
+
+    const SOURCE: &str = "var a = 1;\nprint \"1\" == nil;\nprint -a;\n";
+
+    fn export_source(format: Format) -> String {
+        let mut parser = Parser::new(SOURCE);
+        let statements = parser.parse_program().unwrap();
+        export(&parser.into_ast(), &Root::Program(statements), format).unwrap()
+    }
+
+    #[test]
+    fn exports_json() {
+        let expected = r#"
+{
+  "kind": "Program",
+  "statements": [
+    {
+      "kind": "Var",
+      "span": [0, 10],
+      "name": "a",
+      "line": 1,
+      "initializer": {
+        "kind": "Literal",
+        "span": [8, 9],
+        "value": 1
+      }
+    },
+    {
+      "kind": "Print",
+      "span": [11, 28],
+      "expression": {
+        "kind": "Binary",
+        "span": [17, 27],
+        "operator": "==",
+        "line": 2,
+        "left": {
+          "kind": "Literal",
+          "span": [17, 20],
+          "value": "1"
+        },
+        "right": {
+          "kind": "Literal",
+          "span": [24, 27],
+          "value": null
+        }
+      }
+    },
+    {
+      "kind": "Print",
+      "span": [29, 38],
+      "expression": {
+        "kind": "Unary",
+        "span": [35, 37],
+        "operator": "-",
+        "line": 3,
+        "right": {
+          "kind": "Variable",
+          "span": [36, 37],
+          "name": "a",
+          "line": 3
+        }
+      }
+    }
+  ]
+}
+"#;
+        assert_eq!(export_source(Format::Json), &expected[1..]);
+    }
+
+    #[test]
+    fn exports_dot() {
+        let expected = r#"
+digraph ast {
+  node [shape=box, fontname="monospace"];
+  program [label="Program"];
+  program -> s0 [label="statements"];
+  s0 [label="Var a"];
+  s0 -> e0 [label="initializer"];
+  e0 [label="Literal 1"];
+  program -> s1 [label="statements"];
+  s1 [label="Print"];
+  s1 -> e3 [label="expression"];
+  e3 [label="Binary =="];
+  e3 -> e1 [label="left"];
+  e1 [label="Literal \"1\""];
+  e3 -> e2 [label="right"];
+  e2 [label="Literal null"];
+  program -> s2 [label="statements"];
+  s2 [label="Print"];
+  s2 -> e5 [label="expression"];
+  e5 [label="Unary -"];
+  e5 -> e4 [label="right"];
+  e4 [label="Variable a"];
+}
+"#;
+        assert_eq!(export_source(Format::Dot), &expected[1..]);
+    }
+
+    #[test]
+    fn exports_tree() {
+        let expected = r#"
+Program
+├── statements: Var a @0..10
+│   └── initializer: Literal 1 @8..9
+├── statements: Print @11..28
+│   └── expression: Binary == @17..27
+│       ├── left: Literal "1" @17..20
+│       └── right: Literal null @24..27
+└── statements: Print @29..38
+    └── expression: Unary - @35..37
+        └── right: Variable a @36..37
+"#;
+        assert_eq!(export_source(Format::Tree), &expected[1..]);
+    }
+}
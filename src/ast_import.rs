@@ -0,0 +1,764 @@
+// ast_import.rs
+use crate::ast::{
+    Ast, ExprId, ExprKind, FunctionBody, Imports, LiteralValue, MatchArm, Pattern, PatternKind,
+    Root, StmtId, StmtKind,
+};
+use crate::json::Json;
+use crate::tokenizer::{Span, Token, TokenType};
+
+/// Builds an AST from the JSON that `parse --format=json` produces, so other
+/// tools can hand us programs without generating Lox source.
+///
+/// `span` and `line` are optional; everything else is validated and errors
+/// name the path to the offending node.
+pub fn import(text: &str) -> Result<(Ast, Root), String> {
+    let json = Json::parse(text)?;
+    let mut importer = Importer {
+        ast: Ast::default(),
+        loop_depth: 0,
+        function_depth: 0,
+    };
+    let root = if kind(&json, "$")? == "Program" {
+        Root::Program(
+            array(&json, "statements", "$")?
+                .iter()
+                .enumerate()
+                .map(|(i, stmt)| importer.top_level(stmt, &format!("$.statements[{}]", i)))
+                .collect::<Result<_, String>>()?,
+        )
+    } else {
+        Root::Expression(importer.expr(&json, "$")?)
+    };
+    Ok((importer.ast, root))
+}
+
+struct Importer {
+    ast: Ast,
+    /// Loops enclosing the statement being imported, as in the parser.
+    loop_depth: usize,
+    /// Function bodies enclosing the statement being imported.
+    function_depth: usize,
+}
+
+impl Importer {
+    /// A statement directly in the program, which may be an export.
+    fn top_level(&mut self, json: &Json, path: &str) -> Result<StmtId, String> {
+        if kind(json, path)? != "Export" {
+            return self.stmt(json, path);
+        }
+        let span = span(json, path)?;
+        let keyword = punctuation(json, path, TokenType::Export, "export")?;
+        let declaration = field(json, "declaration", path)?;
+        let path = format!("{}.declaration", path);
+        if !matches!(kind(declaration, &path)?, "Var" | "Fun") {
+            return Err(invalid(&path, "expected a \"Var\" or \"Fun\" node"));
+        }
+        let declaration = self.stmt(declaration, &path)?;
+        Ok(self
+            .ast
+            .add_stmt(span, StmtKind::Export(keyword, declaration)))
+    }
+
+    fn stmt(&mut self, json: &Json, path: &str) -> Result<StmtId, String> {
+        let span = span(json, path)?;
+        let kind = match kind(json, path)? {
+            "Expression" => StmtKind::Expression(self.child(json, "expression", path)?),
+            "Print" => StmtKind::Print(self.child(json, "expression", path)?),
+            "Var" => StmtKind::Var(
+                name(json, path)?,
+                self.optional_child(json, "initializer", path)?,
+            ),
+            "Fun" => {
+                let name = name(json, path)?;
+                let function = field(json, "function", path)?;
+                let function_path = format!("{}.function", path);
+                if kind(function, &function_path)? != "Function" {
+                    return Err(invalid(&function_path, "expected a \"Function\" node"));
+                }
+                StmtKind::Function(name, self.expr(function, &function_path)?)
+            }
+            "Return" if self.function_depth == 0 => {
+                return Err(invalid(path, "\"return\" must be inside a function"))
+            }
+            "Return" => StmtKind::Return(
+                punctuation(json, path, TokenType::Return, "return")?,
+                self.optional_child(json, "value", path)?,
+            ),
+            "Block" => StmtKind::Block(self.statements(json, "statements", path)?),
+            "ForIn" => {
+                let name = name(json, path)?;
+                let keyword = punctuation(json, path, TokenType::In, "in")?;
+                let iterable = self.child(json, "iterable", path)?;
+                self.loop_depth += 1;
+                let body = self.stmt(field(json, "body", path)?, &format!("{}.body", path));
+                self.loop_depth -= 1;
+                StmtKind::ForIn(name, keyword, iterable, body?)
+            }
+            "Throw" => StmtKind::Throw(
+                punctuation(json, path, TokenType::Throw, "throw")?,
+                self.child(json, "value", path)?,
+            ),
+            "Try" => {
+                let body = self.block(json, "body", path)?;
+                let catch = match json.get("catch") {
+                    Some(_) => Some((name(json, path)?, self.block(json, "catch", path)?)),
+                    None => None,
+                };
+                let finally = match json.get("finally") {
+                    Some(_) => Some(self.block(json, "finally", path)?),
+                    None => None,
+                };
+                if catch.is_none() && finally.is_none() {
+                    return Err(invalid(path, "\"Try\" needs \"catch\" or \"finally\""));
+                }
+                StmtKind::Try(body, catch, finally)
+            }
+            "Match" => {
+                let keyword = punctuation(json, path, TokenType::Match, "match")?;
+                let subject = self.child(json, "subject", path)?;
+                let arms =
+                    self.arms(json, path, |importer, body, path| importer.stmt(body, path))?;
+                StmtKind::Match(keyword, subject, arms)
+            }
+            "Import" => {
+                let module = match field(json, "path", path)? {
+                    Json::String(module) => module,
+                    other => {
+                        return Err(invalid(
+                            path,
+                            &format!("\"path\" must be a string, found {}", other.type_name()),
+                        ))
+                    }
+                };
+                let token = Token {
+                    token_type: TokenType::String,
+                    lexeme: format!("\"{}\"", module),
+                    literal: module.clone(),
+                    line: line(json, path)?,
+                    span,
+                };
+                let imports = match (json.get("alias"), json.get("names")) {
+                    (Some(_), None) => Imports::Module(identifier(json, "alias", path)?),
+                    (None, Some(_)) if array(json, "names", path)?.is_empty() => {
+                        return Err(invalid(path, "\"names\" must not be empty"))
+                    }
+                    (None, Some(_)) => Imports::Names(
+                        array(json, "names", path)?
+                            .iter()
+                            .enumerate()
+                            .map(|(i, name)| {
+                                let path = format!("{}.names[{}]", path, i);
+                                match name {
+                                    Json::String(name) if is_identifier(name) => Ok(Token {
+                                        token_type: TokenType::Identifier,
+                                        lexeme: name.clone(),
+                                        literal: "null".to_string(),
+                                        line: token.line,
+                                        span,
+                                    }),
+                                    _ => Err(invalid(&path, "expected an identifier")),
+                                }
+                            })
+                            .collect::<Result<_, String>>()?,
+                    ),
+                    _ => {
+                        return Err(invalid(
+                            path,
+                            "\"Import\" needs exactly one of \"alias\" and \"names\"",
+                        ))
+                    }
+                };
+                StmtKind::Import(token, imports)
+            }
+            "Export" => return Err(invalid(path, "exports must be top-level statements")),
+            "Break" | "Continue" if self.loop_depth == 0 => {
+                return Err(invalid(
+                    path,
+                    "\"break\" and \"continue\" must be inside a loop",
+                ))
+            }
+            "Break" => StmtKind::Break(punctuation(json, path, TokenType::Break, "break")?),
+            "Continue" => {
+                StmtKind::Continue(punctuation(json, path, TokenType::Continue, "continue")?)
+            }
+            other => {
+                return Err(invalid(
+                    path,
+                    &format!("unknown statement kind {:?}", other),
+                ))
+            }
+        };
+        Ok(self.ast.add_stmt(span, kind))
+    }
+
+    fn expr(&mut self, json: &Json, path: &str) -> Result<ExprId, String> {
+        let span = span(json, path)?;
+        let kind = match kind(json, path)? {
+            "Literal" => ExprKind::Literal(literal(json, path)?),
+            "Grouping" => ExprKind::Grouping(self.child(json, "expression", path)?),
+            "Unary" => {
+                let operator = operator(json, path, &["-", "!"])?;
+                ExprKind::Unary(operator, self.child(json, "right", path)?)
+            }
+            "Binary" => {
+                let operator = operator(
+                    json,
+                    path,
+                    &[
+                        "+", "-", "*", "/", "%", "**", "==", "!=", "<", "<=", ">", ">=", "..",
+                        "..=", ",",
+                    ],
+                )?;
+                let left = self.child(json, "left", path)?;
+                ExprKind::Binary(left, operator, self.child(json, "right", path)?)
+            }
+            "Logical" => {
+                let operator = operator(json, path, &["and", "or"])?;
+                let left = self.child(json, "left", path)?;
+                ExprKind::Logical(left, operator, self.child(json, "right", path)?)
+            }
+            "Conditional" => ExprKind::Conditional(
+                self.child(json, "condition", path)?,
+                self.child(json, "then", path)?,
+                self.child(json, "else", path)?,
+            ),
+            "Variable" => ExprKind::Variable(name(json, path)?),
+            "Assign" => ExprKind::Assign(name(json, path)?, self.child(json, "value", path)?),
+            "List" => ExprKind::List(self.children(json, "elements", path)?),
+            "Map" => ExprKind::Map(
+                array(json, "entries", path)?
+                    .iter()
+                    .enumerate()
+                    .map(|(i, entry)| {
+                        let path = format!("{}.entries[{}]", path, i);
+                        Ok((
+                            self.child(entry, "key", &path)?,
+                            punctuation(entry, &path, TokenType::Colon, ":")?,
+                            self.child(entry, "value", &path)?,
+                        ))
+                    })
+                    .collect::<Result<_, String>>()?,
+            ),
+            "Index" => ExprKind::Index(
+                self.child(json, "object", path)?,
+                punctuation(json, path, TokenType::LeftBracket, "[")?,
+                self.child(json, "index", path)?,
+            ),
+            "Slice" => ExprKind::Slice(
+                self.child(json, "object", path)?,
+                punctuation(json, path, TokenType::LeftBracket, "[")?,
+                self.optional_child(json, "start", path)?,
+                self.optional_child(json, "end", path)?,
+            ),
+            "IndexSet" => ExprKind::IndexSet(
+                self.child(json, "object", path)?,
+                punctuation(json, path, TokenType::LeftBracket, "[")?,
+                self.child(json, "index", path)?,
+                self.child(json, "value", path)?,
+            ),
+            "CompoundAssign" => {
+                let operator = operator(json, path, &["+=", "-=", "*=", "/=", "%="])?;
+                let target = self.target(json, path)?;
+                ExprKind::CompoundAssign(target, operator, self.child(json, "value", path)?)
+            }
+            "Update" => {
+                let operator = operator(json, path, &["++", "--"])?;
+                let prefix = match field(json, "prefix", path)? {
+                    Json::Bool(prefix) => *prefix,
+                    other => {
+                        return Err(invalid(
+                            path,
+                            &format!("\"prefix\" must be a boolean, found {}", other.type_name()),
+                        ))
+                    }
+                };
+                ExprKind::Update(self.target(json, path)?, operator, prefix)
+            }
+            "Get" => ExprKind::Get(self.child(json, "object", path)?, name(json, path)?),
+            "Call" => ExprKind::Call(
+                self.child(json, "callee", path)?,
+                punctuation(json, path, TokenType::RightParen, ")")?,
+                self.children(json, "arguments", path)?,
+            ),
+            "Function" => {
+                let params = params(json, path)?;
+                // Loops outside the function don't enclose its body.
+                let loop_depth = std::mem::replace(&mut self.loop_depth, 0);
+                self.function_depth += 1;
+                let body = self.statements(json, "body", path);
+                self.function_depth -= 1;
+                self.loop_depth = loop_depth;
+                ExprKind::Function(params, FunctionBody::Block(body?))
+            }
+            "Arrow" => ExprKind::Function(
+                params(json, path)?,
+                FunctionBody::Expression(self.child(json, "body", path)?),
+            ),
+            "Match" => {
+                let keyword = punctuation(json, path, TokenType::Match, "match")?;
+                let subject = self.child(json, "subject", path)?;
+                let arms =
+                    self.arms(json, path, |importer, body, path| importer.expr(body, path))?;
+                ExprKind::Match(keyword, subject, arms)
+            }
+            other => {
+                return Err(invalid(
+                    path,
+                    &format!("unknown expression kind {:?}", other),
+                ))
+            }
+        };
+        Ok(self.ast.add_expr(span, kind))
+    }
+
+    /// The arms of a `match` of either form, whose bodies `body` imports.
+    fn arms<Body>(
+        &mut self,
+        json: &Json,
+        path: &str,
+        body: impl Fn(&mut Self, &Json, &str) -> Result<Body, String>,
+    ) -> Result<Vec<MatchArm<Body>>, String> {
+        array(json, "arms", path)?
+            .iter()
+            .enumerate()
+            .map(|(i, arm)| {
+                let path = format!("{}.arms[{}]", path, i);
+                let pattern_path = format!("{}.pattern", path);
+                let pattern = self.pattern(field(arm, "pattern", &path)?, &pattern_path)?;
+                let mut names = Vec::new();
+                pattern.bindings(&mut names);
+                for (j, name) in names.iter().enumerate() {
+                    if names[..j].iter().any(|bound| bound.lexeme == name.lexeme) {
+                        let message = format!("duplicate binding {:?}", name.lexeme);
+                        return Err(invalid(&pattern_path, &message));
+                    }
+                }
+                let guard = self.optional_child(arm, "guard", &path)?;
+                let body = body(self, field(arm, "body", &path)?, &format!("{}.body", path))?;
+                Ok(MatchArm {
+                    pattern,
+                    guard,
+                    body,
+                })
+            })
+            .collect()
+    }
+
+    fn pattern(&mut self, json: &Json, path: &str) -> Result<Pattern, String> {
+        let span = span(json, path)?;
+        let kind = match kind(json, path)? {
+            "LiteralPattern" => {
+                let value = self.child(json, "value", path)?;
+                let is_literal = match &self.ast.expr(value).kind {
+                    ExprKind::Literal(_) => true,
+                    ExprKind::Unary(operator, number) => {
+                        operator.token_type == TokenType::Minus
+                            && matches!(
+                                self.ast.expr(*number).kind,
+                                ExprKind::Literal(LiteralValue::Number(_))
+                            )
+                    }
+                    _ => false,
+                };
+                if !is_literal {
+                    return Err(invalid(
+                        path,
+                        "\"value\" must be a literal or a negated number",
+                    ));
+                }
+                PatternKind::Literal(value)
+            }
+            "BindingPattern" => PatternKind::Binding(name(json, path)?),
+            "ListPattern" => {
+                let elements = array(json, "elements", path)?
+                    .iter()
+                    .enumerate()
+                    .map(|(i, element)| self.pattern(element, &format!("{}.elements[{}]", path, i)))
+                    .collect::<Result<_, String>>()?;
+                let rest = match json.get("rest") {
+                    Some(_) => Some(identifier(json, "rest", path)?),
+                    None => None,
+                };
+                PatternKind::List(elements, rest)
+            }
+            other => return Err(invalid(path, &format!("unknown pattern kind {:?}", other))),
+        };
+        Ok(Pattern { span, kind })
+    }
+
+    fn statements(&mut self, json: &Json, key: &str, path: &str) -> Result<Vec<StmtId>, String> {
+        array(json, key, path)?
+            .iter()
+            .enumerate()
+            .map(|(i, stmt)| self.stmt(stmt, &format!("{}.{}[{}]", path, key, i)))
+            .collect()
+    }
+
+    /// Imports the statement under `key`, which must be a block.
+    fn block(&mut self, json: &Json, key: &str, path: &str) -> Result<StmtId, String> {
+        let path = format!("{}.{}", path, key);
+        let block = field(json, key, &path)?;
+        if kind(block, &path)? != "Block" {
+            return Err(invalid(&path, "expected a \"Block\" node"));
+        }
+        self.stmt(block, &path)
+    }
+
+    fn child(&mut self, json: &Json, key: &str, path: &str) -> Result<ExprId, String> {
+        let child = field(json, key, path)?;
+        self.expr(child, &format!("{}.{}", path, key))
+    }
+
+    /// The `target` of a compound assignment or update, which must be a
+    /// variable or an index, as the parser requires.
+    fn target(&mut self, json: &Json, path: &str) -> Result<ExprId, String> {
+        let target = self.child(json, "target", path)?;
+        match self.ast.expr(target).kind {
+            ExprKind::Variable(_) | ExprKind::Index(..) => Ok(target),
+            _ => Err(invalid(path, "\"target\" must be a Variable or an Index")),
+        }
+    }
+
+    fn optional_child(
+        &mut self,
+        json: &Json,
+        key: &str,
+        path: &str,
+    ) -> Result<Option<ExprId>, String> {
+        match json.get(key) {
+            Some(_) => self.child(json, key, path).map(Some),
+            None => Ok(None),
+        }
+    }
+
+    fn children(&mut self, json: &Json, key: &str, path: &str) -> Result<Vec<ExprId>, String> {
+        array(json, key, path)?
+            .iter()
+            .enumerate()
+            .map(|(i, child)| self.expr(child, &format!("{}.{}[{}]", path, key, i)))
+            .collect()
+    }
+}
+
+fn invalid(path: &str, message: &str) -> String {
+    format!("Invalid AST at {}: {}.", path, message)
+}
+
+fn field<'a>(json: &'a Json, key: &str, path: &str) -> Result<&'a Json, String> {
+    match json {
+        Json::Object(_) => json
+            .get(key)
+            .ok_or_else(|| invalid(path, &format!("missing field {:?}", key))),
+        other => Err(invalid(
+            path,
+            &format!("expected an object, found {}", other.type_name()),
+        )),
+    }
+}
+
+fn kind<'a>(json: &'a Json, path: &str) -> Result<&'a str, String> {
+    match field(json, "kind", path)? {
+        Json::String(kind) => Ok(kind),
+        other => Err(invalid(
+            path,
+            &format!("\"kind\" must be a string, found {}", other.type_name()),
+        )),
+    }
+}
+
+fn array<'a>(json: &'a Json, key: &str, path: &str) -> Result<&'a [Json], String> {
+    match field(json, key, path)? {
+        Json::Array(items) => Ok(items),
+        other => Err(invalid(
+            path,
+            &format!("{:?} must be an array, found {}", key, other.type_name()),
+        )),
+    }
+}
+
+/// Reads the optional `[start, end]` byte span.
+fn span(json: &Json, path: &str) -> Result<Span, String> {
+    match json.get("span") {
+        None => Ok(Span::default()),
+        Some(Json::Array(bounds)) => match bounds.as_slice() {
+            [Json::Number(start), Json::Number(end)]
+                if is_offset(*start) && is_offset(*end) && start <= end =>
+            {
+                Ok(Span::new(*start as usize, *end as usize))
+            }
+            _ => Err(invalid(path, "\"span\" must be [start, end] byte offsets")),
+        },
+        Some(_) => Err(invalid(path, "\"span\" must be [start, end] byte offsets")),
+    }
+}
+
+fn is_offset(n: f64) -> bool {
+    n >= 0.0 && n.fract() == 0.0 && n <= u32::MAX as f64
+}
+
+fn literal(json: &Json, path: &str) -> Result<LiteralValue, String> {
+    match field(json, "value", path)? {
+        Json::Number(n) => Ok(LiteralValue::Number(*n)),
+        Json::String(s) => Ok(LiteralValue::String(s.clone())),
+        Json::Bool(b) => Ok(LiteralValue::Bool(*b)),
+        Json::Null => Ok(LiteralValue::Nil),
+        other => Err(invalid(
+            path,
+            &format!(
+                "literal value must be a scalar, found {}",
+                other.type_name()
+            ),
+        )),
+    }
+}
+
+/// Reads `operator` (one of `allowed`) and the optional `line` into a token.
+fn operator(json: &Json, path: &str, allowed: &[&str]) -> Result<Token, String> {
+    let lexeme = match field(json, "operator", path)? {
+        Json::String(op) if allowed.contains(&op.as_str()) => op,
+        Json::String(op) => {
+            return Err(invalid(
+                path,
+                &format!(
+                    "unknown operator {:?} (expected one of {})",
+                    op,
+                    allowed.join(" ")
+                ),
+            ))
+        }
+        other => {
+            return Err(invalid(
+                path,
+                &format!("\"operator\" must be a string, found {}", other.type_name()),
+            ))
+        }
+    };
+    Ok(Token {
+        token_type: operator_type(lexeme),
+        lexeme: lexeme.clone(),
+        literal: lexeme.clone(),
+        line: line(json, path)?,
+        span: span(json, path)?,
+    })
+}
+
+/// Reads `name` and the optional `line` into an identifier token.
+fn name(json: &Json, path: &str) -> Result<Token, String> {
+    identifier(json, "name", path)
+}
+
+/// The identifier stored as a string under `key`.
+fn identifier(json: &Json, key: &str, path: &str) -> Result<Token, String> {
+    let name = match field(json, key, path)? {
+        Json::String(name) if is_identifier(name) => name,
+        Json::String(name) => {
+            return Err(invalid(path, &format!("{:?} is not an identifier", name)))
+        }
+        other => {
+            return Err(invalid(
+                path,
+                &format!("{:?} must be a string, found {}", key, other.type_name()),
+            ))
+        }
+    };
+    Ok(Token {
+        token_type: TokenType::Identifier,
+        lexeme: name.clone(),
+        literal: "null".to_string(),
+        line: line(json, path)?,
+        span: span(json, path)?,
+    })
+}
+
+/// The distinct parameter names of a function.
+fn params(json: &Json, path: &str) -> Result<Vec<Token>, String> {
+    let mut params: Vec<Token> = Vec::new();
+    for (i, param) in array(json, "params", path)?.iter().enumerate() {
+        let path = format!("{}.params[{}]", path, i);
+        match param {
+            Json::String(name) if params.iter().any(|param| param.lexeme == *name) => {
+                return Err(invalid(&path, &format!("duplicate parameter {:?}", name)))
+            }
+            Json::String(name) if is_identifier(name) => params.push(Token {
+                token_type: TokenType::Identifier,
+                lexeme: name.clone(),
+                literal: "null".to_string(),
+                line: line(json, &path)?,
+                span: Span::default(),
+            }),
+            _ => return Err(invalid(&path, "expected an identifier")),
+        }
+    }
+    Ok(params)
+}
+
+fn is_identifier(name: &str) -> bool {
+    let mut chars = name.chars();
+    chars.next().is_some_and(|c| c.is_alphabetic() || c == '_')
+        && chars.all(|c| c.is_alphanumeric() || c == '_')
+}
+
+/// A token for the bracket, paren or keyword that errors in the node point
+/// at.
+fn punctuation(
+    json: &Json,
+    path: &str,
+    token_type: TokenType,
+    lexeme: &str,
+) -> Result<Token, String> {
+    Ok(Token {
+        token_type,
+        lexeme: lexeme.to_string(),
+        literal: "null".to_string(),
+        line: line(json, path)?,
+        span: span(json, path)?,
+    })
+}
+
+fn line(json: &Json, path: &str) -> Result<usize, String> {
+    match json.get("line") {
+        None => Ok(1),
+        Some(Json::Number(n)) if *n >= 1.0 && is_offset(*n) => Ok(*n as usize),
+        Some(_) => Err(invalid(
+            path,
+            &format!("\"line\" must be an integer from 1 to {}", u32::MAX),
+        )),
+    }
+}
+
+fn operator_type(lexeme: &str) -> TokenType {
+    match lexeme {
+        "+" => TokenType::Plus,
+        "-" => TokenType::Minus,
+        "*" => TokenType::Star,
+        "/" => TokenType::Slash,
+        "%" => TokenType::Percent,
+        "+=" => TokenType::PlusEqual,
+        "-=" => TokenType::MinusEqual,
+        "*=" => TokenType::StarEqual,
+        "/=" => TokenType::SlashEqual,
+        "%=" => TokenType::PercentEqual,
+        "++" => TokenType::PlusPlus,
+        "--" => TokenType::MinusMinus,
+        "**" => TokenType::StarStar,
+        "==" => TokenType::EqualEqual,
+        "!=" => TokenType::BangEqual,
+        "<" => TokenType::Less,
+        "<=" => TokenType::LessEqual,
+        ">" => TokenType::Greater,
+        ">=" => TokenType::GreaterEqual,
+        ".." => TokenType::DotDot,
+        "..=" => TokenType::DotDotEqual,
+        "," => TokenType::Comma,
+        "!" => TokenType::Bang,
+        "and" => TokenType::And,
+        "or" => TokenType::Or,
+        _ => unreachable!("operators are checked against an allow list first"),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ast_printer::AstPrinter;
+
+    fn error(text: &str) -> String {
+        match import(text) {
+            Ok(_) => panic!("imported {}", text),
+            Err(e) => e,
+        }
+    }
+
+    #[test]
+    fn imports_a_program() {
+        let text = r#"{"kind": "Program", "statements": [
+            {"kind": "Print", "expression": {"kind": "Binary", "operator": "+",
+                "left": {"kind": "Literal", "value": 1},
+                "right": {"kind": "Literal", "value": "1"}}}
+        ]}"#;
+        let (ast, root) = import(text).unwrap();
+        let Root::Program(statements) = root else {
+            panic!("expected a program");
+        };
+        assert_eq!(
+            AstPrinter::print_stmt(&ast, statements[0]),
+            "(print (+ 1.0 1))"
+        );
+    }
+
+    #[test]
+    fn rejects_unknown_kinds() {
+        assert_eq!(
+            error(r#"{"kind": "Program", "statements": [{"kind": "While"}]}"#),
+            "Invalid AST at $.statements[0]: unknown statement kind \"While\"."
+        );
+        assert_eq!(
+            error(r#"{"kind": "Lambda"}"#),
+            "Invalid AST at $: unknown expression kind \"Lambda\"."
+        );
+    }
+
+    #[test]
+    fn rejects_missing_fields() {
+        assert_eq!(
+            error(r#"{"kind": "Unary", "operator": "-"}"#),
+            "Invalid AST at $: missing field \"right\"."
+        );
+        assert_eq!(
+            error(r#"{"value": 1}"#),
+            "Invalid AST at $: missing field \"kind\"."
+        );
+    }
+
+    #[test]
+    fn rejects_bad_operators() {
+        let unary = r#"{"kind": "Unary", "operator": "+",
+            "right": {"kind": "Literal", "value": 1}}"#;
+        assert_eq!(
+            error(unary),
+            "Invalid AST at $: unknown operator \"+\" (expected one of - !)."
+        );
+        let binary = r#"{"kind": "Binary", "operator": 1,
+            "left": {"kind": "Literal", "value": 1},
+            "right": {"kind": "Literal", "value": 1}}"#;
+        assert_eq!(
+            error(binary),
+            "Invalid AST at $: \"operator\" must be a string, found number."
+        );
+    }
+
+    #[test]
+    fn rejects_duplicate_bindings() {
+        let pattern = |name: &str| {
+            format!(
+                r#"{{"kind": "Match", "subject": {{"kind": "Literal", "value": 1}}, "arms": [
+                    {{"pattern": {{"kind": "ListPattern", "elements": [
+                        {{"kind": "BindingPattern", "name": "{0}"}},
+                        {{"kind": "BindingPattern", "name": "{0}"}}]}},
+                    "body": {{"kind": "Literal", "value": 1}}}}]}}"#,
+                name
+            )
+        };
+        assert_eq!(
+            error(&pattern("x")),
+            "Invalid AST at $.arms[0].pattern: duplicate binding \"x\"."
+        );
+        assert!(import(&pattern("_")).is_ok());
+    }
+
+    #[test]
+    fn rejects_out_of_range_lines() {
+        assert_eq!(
+            error(r#"{"kind": "Variable", "name": "x", "line": 1e30}"#),
+            "Invalid AST at $: \"line\" must be an integer from 1 to 4294967295."
+        );
+    }
+
+    #[test]
+    fn reports_malformed_json() {
+        assert_eq!(
+            error(r#"{"kind": "Literal", "value": }"#),
+            "Invalid JSON at line 1, column 30: Expected a JSON value."
+        );
+    }
+}
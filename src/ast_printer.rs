@@ -1,20 +1,435 @@
-use crate::ast::Expr;
+use crate::ast::{
+    Ast, ExprId, ExprKind, ExprVisitor, FunctionBody, Imports, LiteralValue, MatchArm, Pattern,
+    PatternKind, StmtId, StmtVisitor,
+};
+use crate::tokenizer::Token;
 
+/// Renders the AST as parenthesized S-expressions, e.g. `(+ 1.0 (group 2.0))`.
 pub struct AstPrinter;
 
 impl AstPrinter {
-    pub fn print(expr: &Expr) -> String {
-        match expr {
-            Expr::Literal(value) => format!("{}", value),
-            Expr::Grouping(inner) => format!("(group {})", Self::print(inner)),
-            Expr::Unary(operator, right) => format!(
-                "({} {})",
-                operator.to_string_for_parse(),
-                Self::print(right)
-            ),
-            Expr::Binary(left, operator, right) => {
-                format!("({} {} {})", operator.to_string_for_parse(), left, right)
+    pub fn print(ast: &Ast, expr: ExprId) -> String {
+        ast.accept_expr(expr, &mut AstPrinter)
+    }
+
+    pub fn print_stmt(ast: &Ast, stmt: StmtId) -> String {
+        ast.accept_stmt(stmt, &mut AstPrinter)
+    }
+
+    fn parenthesize(&mut self, ast: &Ast, name: &str, exprs: &[ExprId]) -> String {
+        let mut out = format!("({}", name);
+        for &expr in exprs {
+            out.push(' ');
+            out.push_str(&ast.accept_expr(expr, self));
+        }
+        out.push(')');
+        out
+    }
+
+    /// `(fun name (params) statements...)` for a block body, or
+    /// `(=> (params) body)` for an arrow function.
+    fn function(
+        &mut self,
+        ast: &Ast,
+        name: Option<&str>,
+        params: &[Token],
+        body: &FunctionBody,
+    ) -> String {
+        let params: Vec<&str> = params.iter().map(|param| param.lexeme.as_str()).collect();
+        let mut out = match body {
+            FunctionBody::Block(_) => String::from("(fun"),
+            FunctionBody::Expression(_) => String::from("(=>"),
+        };
+        if let Some(name) = name {
+            out.push_str(&format!(" {}", name));
+        }
+        out.push_str(&format!(" ({})", params.join(" ")));
+        match body {
+            FunctionBody::Block(statements) => {
+                for &stmt in statements {
+                    out.push(' ');
+                    out.push_str(&ast.accept_stmt(stmt, self));
+                }
+            }
+            FunctionBody::Expression(expr) => {
+                out.push(' ');
+                out.push_str(&ast.accept_expr(*expr, self));
             }
         }
+        out.push(')');
+        out
+    }
+
+    /// Patterns print like the expressions they resemble, e.g.
+    /// `(list a (.. rest))` for `[a, ..rest]`.
+    /// `(match subject (arm pattern (if guard) body)...)`, for both forms of
+    /// `match`.
+    fn match_arms<Body>(
+        &mut self,
+        ast: &Ast,
+        subject: ExprId,
+        arms: &[MatchArm<Body>],
+        print_body: impl Fn(&mut Self, &Body) -> String,
+    ) -> String {
+        let mut out = format!("(match {}", ast.accept_expr(subject, self));
+        for arm in arms {
+            out.push_str(&format!(" (arm {}", self.pattern(ast, &arm.pattern)));
+            if let Some(guard) = arm.guard {
+                out.push_str(&format!(" (if {})", ast.accept_expr(guard, self)));
+            }
+            out.push_str(&format!(" {})", print_body(self, &arm.body)));
+        }
+        out.push(')');
+        out
+    }
+
+    fn pattern(&mut self, ast: &Ast, pattern: &Pattern) -> String {
+        match &pattern.kind {
+            PatternKind::Literal(expr) => ast.accept_expr(*expr, self),
+            PatternKind::Binding(name) => name.lexeme.clone(),
+            PatternKind::List(elements, rest) => {
+                let mut out = String::from("(list");
+                for element in elements {
+                    out.push(' ');
+                    out.push_str(&self.pattern(ast, element));
+                }
+                if let Some(rest) = rest {
+                    out.push_str(&format!(" (.. {})", rest.lexeme));
+                }
+                out.push(')');
+                out
+            }
+        }
+    }
+}
+
+impl ExprVisitor<String> for AstPrinter {
+    fn visit_literal(&mut self, _ast: &Ast, _id: ExprId, value: &LiteralValue) -> String {
+        format!("{}", value)
+    }
+
+    fn visit_grouping(&mut self, ast: &Ast, _id: ExprId, inner: ExprId) -> String {
+        self.parenthesize(ast, "group", &[inner])
+    }
+
+    fn visit_unary(&mut self, ast: &Ast, _id: ExprId, operator: &Token, right: ExprId) -> String {
+        self.parenthesize(ast, &operator.token_type.to_string_for_parse(), &[right])
+    }
+
+    fn visit_binary(
+        &mut self,
+        ast: &Ast,
+        _id: ExprId,
+        left: ExprId,
+        operator: &Token,
+        right: ExprId,
+    ) -> String {
+        self.parenthesize(
+            ast,
+            &operator.token_type.to_string_for_parse(),
+            &[left, right],
+        )
+    }
+
+    fn visit_logical(
+        &mut self,
+        ast: &Ast,
+        _id: ExprId,
+        left: ExprId,
+        operator: &Token,
+        right: ExprId,
+    ) -> String {
+        self.parenthesize(
+            ast,
+            &operator.token_type.to_string_for_parse(),
+            &[left, right],
+        )
+    }
+
+    fn visit_conditional(
+        &mut self,
+        ast: &Ast,
+        _id: ExprId,
+        condition: ExprId,
+        then_branch: ExprId,
+        else_branch: ExprId,
+    ) -> String {
+        self.parenthesize(ast, "?:", &[condition, then_branch, else_branch])
+    }
+
+    fn visit_variable(&mut self, _ast: &Ast, _id: ExprId, name: &Token) -> String {
+        name.lexeme.clone()
+    }
+
+    fn visit_assign(&mut self, ast: &Ast, _id: ExprId, name: &Token, value: ExprId) -> String {
+        self.parenthesize(ast, &format!("= {}", name.lexeme), &[value])
+    }
+
+    fn visit_list(&mut self, ast: &Ast, _id: ExprId, elements: &[ExprId]) -> String {
+        self.parenthesize(ast, "list", elements)
+    }
+
+    fn visit_map(&mut self, ast: &Ast, _id: ExprId, entries: &[(ExprId, Token, ExprId)]) -> String {
+        let mut out = String::from("(map");
+        for &(key, _, value) in entries {
+            out.push(' ');
+            out.push_str(&self.parenthesize(ast, ":", &[key, value]));
+        }
+        out.push(')');
+        out
+    }
+
+    fn visit_index(
+        &mut self,
+        ast: &Ast,
+        _id: ExprId,
+        object: ExprId,
+        _bracket: &Token,
+        index: ExprId,
+    ) -> String {
+        self.parenthesize(ast, "index", &[object, index])
+    }
+
+    fn visit_slice(
+        &mut self,
+        ast: &Ast,
+        _id: ExprId,
+        object: ExprId,
+        _bracket: &Token,
+        start: Option<ExprId>,
+        end: Option<ExprId>,
+    ) -> String {
+        let bound = |printer: &mut Self, bound: Option<ExprId>| match bound {
+            Some(expr) => ast.accept_expr(expr, printer),
+            None => "_".to_string(),
+        };
+        let object = ast.accept_expr(object, self);
+        let start = bound(self, start);
+        let end = bound(self, end);
+        format!("(slice {} {} {})", object, start, end)
+    }
+
+    fn visit_index_set(
+        &mut self,
+        ast: &Ast,
+        _id: ExprId,
+        object: ExprId,
+        _bracket: &Token,
+        index: ExprId,
+        value: ExprId,
+    ) -> String {
+        self.parenthesize(ast, "index=", &[object, index, value])
+    }
+
+    fn visit_compound_assign(
+        &mut self,
+        ast: &Ast,
+        _id: ExprId,
+        target: ExprId,
+        operator: &Token,
+        value: ExprId,
+    ) -> String {
+        self.parenthesize(ast, &operator.lexeme, &[target, value])
+    }
+
+    /// `(pre++ x)` for `++x` and `(post++ x)` for `x++`.
+    fn visit_update(
+        &mut self,
+        ast: &Ast,
+        _id: ExprId,
+        target: ExprId,
+        operator: &Token,
+        prefix: bool,
+    ) -> String {
+        let fixity = if prefix { "pre" } else { "post" };
+        self.parenthesize(ast, &format!("{}{}", fixity, operator.lexeme), &[target])
+    }
+
+    fn visit_get(&mut self, ast: &Ast, _id: ExprId, object: ExprId, name: &Token) -> String {
+        self.parenthesize(ast, &format!(". {}", name.lexeme), &[object])
+    }
+
+    fn visit_call(
+        &mut self,
+        ast: &Ast,
+        _id: ExprId,
+        callee: ExprId,
+        _paren: &Token,
+        arguments: &[ExprId],
+    ) -> String {
+        let mut exprs = vec![callee];
+        exprs.extend_from_slice(arguments);
+        self.parenthesize(ast, "call", &exprs)
+    }
+
+    fn visit_function(
+        &mut self,
+        ast: &Ast,
+        _id: ExprId,
+        params: &[Token],
+        body: &FunctionBody,
+    ) -> String {
+        self.function(ast, None, params, body)
+    }
+
+    fn visit_match(
+        &mut self,
+        ast: &Ast,
+        _id: ExprId,
+        _keyword: &Token,
+        subject: ExprId,
+        arms: &[MatchArm<ExprId>],
+    ) -> String {
+        self.match_arms(ast, subject, arms, |printer, body| {
+            ast.accept_expr(*body, printer)
+        })
+    }
+}
+
+impl StmtVisitor<String> for AstPrinter {
+    fn visit_expression_stmt(&mut self, ast: &Ast, _id: StmtId, expr: ExprId) -> String {
+        self.parenthesize(ast, ";", &[expr])
+    }
+
+    fn visit_print_stmt(&mut self, ast: &Ast, _id: StmtId, expr: ExprId) -> String {
+        self.parenthesize(ast, "print", &[expr])
+    }
+
+    fn visit_var_stmt(
+        &mut self,
+        ast: &Ast,
+        _id: StmtId,
+        name: &Token,
+        initializer: Option<ExprId>,
+    ) -> String {
+        let name = format!("var {}", name.lexeme);
+        self.parenthesize(ast, &name, initializer.as_slice())
+    }
+
+    fn visit_function_stmt(
+        &mut self,
+        ast: &Ast,
+        _id: StmtId,
+        name: &Token,
+        function: ExprId,
+    ) -> String {
+        let ExprKind::Function(params, body) = &ast.expr(function).kind else {
+            unreachable!("function declarations hold a Function node");
+        };
+        self.function(ast, Some(&name.lexeme), params, body)
+    }
+
+    fn visit_return_stmt(
+        &mut self,
+        ast: &Ast,
+        _id: StmtId,
+        _keyword: &Token,
+        value: Option<ExprId>,
+    ) -> String {
+        self.parenthesize(ast, "return", value.as_slice())
+    }
+
+    fn visit_for_in_stmt(
+        &mut self,
+        ast: &Ast,
+        _id: StmtId,
+        name: &Token,
+        _keyword: &Token,
+        iterable: ExprId,
+        body: StmtId,
+    ) -> String {
+        format!(
+            "(for-in {} {} {})",
+            name.lexeme,
+            ast.accept_expr(iterable, self),
+            ast.accept_stmt(body, self)
+        )
+    }
+
+    fn visit_break_stmt(&mut self, _ast: &Ast, _id: StmtId, _keyword: &Token) -> String {
+        "(break)".to_string()
+    }
+
+    fn visit_continue_stmt(&mut self, _ast: &Ast, _id: StmtId, _keyword: &Token) -> String {
+        "(continue)".to_string()
+    }
+
+    fn visit_throw_stmt(
+        &mut self,
+        ast: &Ast,
+        _id: StmtId,
+        _keyword: &Token,
+        value: ExprId,
+    ) -> String {
+        self.parenthesize(ast, "throw", &[value])
+    }
+
+    fn visit_try_stmt(
+        &mut self,
+        ast: &Ast,
+        _id: StmtId,
+        body: StmtId,
+        catch: Option<&(Token, StmtId)>,
+        finally: Option<StmtId>,
+    ) -> String {
+        let mut out = format!("(try {}", ast.accept_stmt(body, self));
+        if let Some((name, handler)) = catch {
+            let handler = ast.accept_stmt(*handler, self);
+            out.push_str(&format!(" (catch {} {})", name.lexeme, handler));
+        }
+        if let Some(finally) = finally {
+            out.push_str(&format!(" (finally {})", ast.accept_stmt(finally, self)));
+        }
+        out.push(')');
+        out
+    }
+
+    fn visit_match_stmt(
+        &mut self,
+        ast: &Ast,
+        _id: StmtId,
+        _keyword: &Token,
+        subject: ExprId,
+        arms: &[MatchArm],
+    ) -> String {
+        self.match_arms(ast, subject, arms, |printer, body| {
+            ast.accept_stmt(*body, printer)
+        })
+    }
+
+    fn visit_import_stmt(
+        &mut self,
+        _ast: &Ast,
+        _id: StmtId,
+        path: &Token,
+        imports: &Imports,
+    ) -> String {
+        match imports {
+            Imports::Module(name) => format!("(import {} as {})", path.literal, name.lexeme),
+            Imports::Names(names) => {
+                let names: Vec<&str> = names.iter().map(|name| name.lexeme.as_str()).collect();
+                format!("(from {} import {})", path.literal, names.join(" "))
+            }
+        }
+    }
+
+    fn visit_export_stmt(
+        &mut self,
+        ast: &Ast,
+        _id: StmtId,
+        _keyword: &Token,
+        declaration: StmtId,
+    ) -> String {
+        format!("(export {})", ast.accept_stmt(declaration, self))
+    }
+
+    fn visit_block_stmt(&mut self, ast: &Ast, _id: StmtId, statements: &[StmtId]) -> String {
+        let mut out = String::from("(block");
+        for &stmt in statements {
+            out.push(' ');
+            out.push_str(&ast.accept_stmt(stmt, self));
+        }
+        out.push(')');
+        out
     }
 }
@@ -0,0 +1,625 @@
+// cst.rs
+use crate::ast::{
+    Ast, ExprId, ExprVisitor, FunctionBody, Imports, LiteralValue, MatchArm, StmtId, StmtVisitor,
+};
+use crate::parser::Parser;
+use crate::tokenizer::{Scanner, Span, Token, TokenType, Trivia};
+use std::fmt::{self, Write};
+use std::iter::Peekable;
+use std::vec;
+
+/// A lossless concrete syntax tree. Every byte of the source belongs to
+/// exactly one token or trivia, so printing the tree with `Display`
+/// reproduces the source exactly.
+pub struct CstNode {
+    pub kind: SyntaxKind,
+    /// The span of the corresponding AST node, not including the leading
+    /// trivia of its first token.
+    pub span: Span,
+    pub children: Vec<CstElement>,
+}
+
+pub enum CstElement {
+    Node(CstNode),
+    Token(CstToken),
+}
+
+pub struct CstToken {
+    /// Whitespace, comments and skipped characters since the previous token.
+    pub leading_trivia: Vec<Trivia>,
+    pub token_type: TokenType,
+    pub text: String,
+    pub span: Span,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SyntaxKind {
+    Program,
+    ExpressionStmt,
+    PrintStmt,
+    VarStmt,
+    /// `fun name` and the `Function` node for the rest.
+    FunctionStmt,
+    ReturnStmt,
+    Block,
+    ForInStmt,
+    BreakStmt,
+    ContinueStmt,
+    ThrowStmt,
+    TryStmt,
+    MatchStmt,
+    /// A pattern, its guard and its body; the pattern's own tokens belong
+    /// directly to the arm.
+    MatchArm,
+    ImportStmt,
+    ExportStmt,
+    Literal,
+    Grouping,
+    Unary,
+    Binary,
+    Logical,
+    Conditional,
+    Variable,
+    Assign,
+    List,
+    Map,
+    Index,
+    Slice,
+    IndexSet,
+    CompoundAssign,
+    Update,
+    Get,
+    Call,
+    /// A function expression, or the parameters and body of a declaration.
+    Function,
+    /// A `match` expression, whose arms are `MatchArm` nodes.
+    Match,
+}
+
+/// Parses `source` into a lossless tree. The program must scan and parse;
+/// the tree mirrors the AST, with each node owning the tokens between its
+/// children.
+pub fn parse_lossless(source: &str) -> Result<CstNode, String> {
+    let mut scanner = Scanner::lossless(source);
+    scanner.scan_tokens();
+    if scanner.has_errors {
+        // The scanner has already reported them.
+        return Err("Cannot build a syntax tree for a file with syntax errors.".to_string());
+    }
+    let (tokens, trivia) = scanner.into_tokens_with_trivia();
+    let mut parser = Parser::from_tokens(tokens.clone());
+    let statements = parser.parse_program()?;
+    let ast = parser.into_ast();
+
+    let mut builder = CstBuilder {
+        tokens: attach_trivia(source, tokens, trivia).into_iter().peekable(),
+    };
+
+    let mut children = Vec::new();
+    for &stmt in &statements {
+        builder.take_tokens_before(ast.stmt(stmt).span.start, &mut children);
+        children.push(CstElement::Node(ast.accept_stmt(stmt, &mut builder)));
+    }
+    // Whatever is left ends with EOF, which carries the trailing trivia.
+    children.extend(builder.tokens.map(CstElement::Token));
+
+    Ok(CstNode {
+        kind: SyntaxKind::Program,
+        span: Span::new(0, source.len()),
+        children,
+    })
+}
+
+/// Gives each token the trivia that precedes it.
+fn attach_trivia(source: &str, tokens: Vec<Token>, trivia: Vec<Trivia>) -> Vec<CstToken> {
+    let mut trivia = trivia.into_iter().peekable();
+    tokens
+        .into_iter()
+        .map(|token| {
+            let mut leading_trivia = Vec::new();
+            while let Some(t) = trivia.next_if(|t| t.span.start < token.span.start) {
+                leading_trivia.push(t);
+            }
+            CstToken {
+                leading_trivia,
+                token_type: token.token_type,
+                text: source[token.span.start..token.span.end].to_string(),
+                span: token.span,
+            }
+        })
+        .collect()
+}
+
+#[derive(Clone, Copy)]
+enum Child {
+    Expr(ExprId),
+    Stmt(StmtId),
+}
+
+impl Child {
+    fn span(self, ast: &Ast) -> Span {
+        match self {
+            Child::Expr(expr) => ast.expr(expr).span,
+            Child::Stmt(stmt) => ast.stmt(stmt).span,
+        }
+    }
+}
+
+struct CstBuilder {
+    tokens: Peekable<vec::IntoIter<CstToken>>,
+}
+
+impl CstBuilder {
+    fn take_tokens_before(&mut self, end: usize, out: &mut Vec<CstElement>) {
+        while let Some(token) = self.tokens.next_if(|token| token.span.start < end) {
+            out.push(CstElement::Token(token));
+        }
+    }
+
+    /// Builds a node for an AST node spanning `span` whose child
+    /// expressions are `children`, in source order.
+    fn node(&mut self, ast: &Ast, kind: SyntaxKind, span: Span, children: &[ExprId]) -> CstNode {
+        let children: Vec<Child> = children.iter().map(|&expr| Child::Expr(expr)).collect();
+        self.node_with(ast, kind, span, &children)
+    }
+
+    /// A `match` of either form, with a `MatchArm` node per arm.
+    fn match_node<Body: Copy>(
+        &mut self,
+        ast: &Ast,
+        kind: SyntaxKind,
+        span: Span,
+        subject: ExprId,
+        arms: &[MatchArm<Body>],
+        body: impl Fn(Body) -> Child,
+    ) -> CstNode {
+        let mut elements = Vec::new();
+        self.take_tokens_before(ast.expr(subject).span.start, &mut elements);
+        elements.push(CstElement::Node(ast.accept_expr(subject, self)));
+        for arm in arms {
+            self.take_tokens_before(arm.pattern.span.start, &mut elements);
+            let mut literals = Vec::new();
+            arm.pattern.literals(&mut literals);
+            let mut children: Vec<Child> = literals.into_iter().map(Child::Expr).collect();
+            children.extend(arm.guard.map(Child::Expr));
+            children.push(body(arm.body));
+            let arm_span = arm.pattern.span.to(body(arm.body).span(ast));
+            let node = self.node_with(ast, SyntaxKind::MatchArm, arm_span, &children);
+            elements.push(CstElement::Node(node));
+        }
+        self.take_tokens_before(span.end, &mut elements);
+        CstNode {
+            kind,
+            span,
+            children: elements,
+        }
+    }
+
+    /// Like `node`, for nodes that also have statements as children.
+    fn node_with(
+        &mut self,
+        ast: &Ast,
+        kind: SyntaxKind,
+        span: Span,
+        children: &[Child],
+    ) -> CstNode {
+        let mut elements = Vec::new();
+        for &child in children {
+            self.take_tokens_before(child.span(ast).start, &mut elements);
+            let node = match child {
+                Child::Expr(expr) => ast.accept_expr(expr, self),
+                Child::Stmt(stmt) => ast.accept_stmt(stmt, self),
+            };
+            elements.push(CstElement::Node(node));
+        }
+        self.take_tokens_before(span.end, &mut elements);
+        CstNode {
+            kind,
+            span,
+            children: elements,
+        }
+    }
+}
+
+impl ExprVisitor<CstNode> for CstBuilder {
+    fn visit_literal(&mut self, ast: &Ast, id: ExprId, _value: &LiteralValue) -> CstNode {
+        self.node(ast, SyntaxKind::Literal, ast.expr(id).span, &[])
+    }
+
+    fn visit_grouping(&mut self, ast: &Ast, id: ExprId, inner: ExprId) -> CstNode {
+        self.node(ast, SyntaxKind::Grouping, ast.expr(id).span, &[inner])
+    }
+
+    fn visit_unary(&mut self, ast: &Ast, id: ExprId, _operator: &Token, right: ExprId) -> CstNode {
+        self.node(ast, SyntaxKind::Unary, ast.expr(id).span, &[right])
+    }
+
+    fn visit_binary(
+        &mut self,
+        ast: &Ast,
+        id: ExprId,
+        left: ExprId,
+        _operator: &Token,
+        right: ExprId,
+    ) -> CstNode {
+        self.node(ast, SyntaxKind::Binary, ast.expr(id).span, &[left, right])
+    }
+
+    fn visit_logical(
+        &mut self,
+        ast: &Ast,
+        id: ExprId,
+        left: ExprId,
+        _operator: &Token,
+        right: ExprId,
+    ) -> CstNode {
+        self.node(ast, SyntaxKind::Logical, ast.expr(id).span, &[left, right])
+    }
+
+    fn visit_conditional(
+        &mut self,
+        ast: &Ast,
+        id: ExprId,
+        condition: ExprId,
+        then_branch: ExprId,
+        else_branch: ExprId,
+    ) -> CstNode {
+        self.node(
+            ast,
+            SyntaxKind::Conditional,
+            ast.expr(id).span,
+            &[condition, then_branch, else_branch],
+        )
+    }
+
+    fn visit_variable(&mut self, ast: &Ast, id: ExprId, _name: &Token) -> CstNode {
+        self.node(ast, SyntaxKind::Variable, ast.expr(id).span, &[])
+    }
+
+    fn visit_assign(&mut self, ast: &Ast, id: ExprId, _name: &Token, value: ExprId) -> CstNode {
+        self.node(ast, SyntaxKind::Assign, ast.expr(id).span, &[value])
+    }
+
+    fn visit_list(&mut self, ast: &Ast, id: ExprId, elements: &[ExprId]) -> CstNode {
+        self.node(ast, SyntaxKind::List, ast.expr(id).span, elements)
+    }
+
+    fn visit_map(&mut self, ast: &Ast, id: ExprId, entries: &[(ExprId, Token, ExprId)]) -> CstNode {
+        let children: Vec<ExprId> = entries
+            .iter()
+            .flat_map(|&(key, _, value)| [key, value])
+            .collect();
+        self.node(ast, SyntaxKind::Map, ast.expr(id).span, &children)
+    }
+
+    fn visit_index(
+        &mut self,
+        ast: &Ast,
+        id: ExprId,
+        object: ExprId,
+        _bracket: &Token,
+        index: ExprId,
+    ) -> CstNode {
+        self.node(ast, SyntaxKind::Index, ast.expr(id).span, &[object, index])
+    }
+
+    fn visit_slice(
+        &mut self,
+        ast: &Ast,
+        id: ExprId,
+        object: ExprId,
+        _bracket: &Token,
+        start: Option<ExprId>,
+        end: Option<ExprId>,
+    ) -> CstNode {
+        let children: Vec<ExprId> = [Some(object), start, end].into_iter().flatten().collect();
+        self.node(ast, SyntaxKind::Slice, ast.expr(id).span, &children)
+    }
+
+    fn visit_index_set(
+        &mut self,
+        ast: &Ast,
+        id: ExprId,
+        object: ExprId,
+        _bracket: &Token,
+        index: ExprId,
+        value: ExprId,
+    ) -> CstNode {
+        self.node(
+            ast,
+            SyntaxKind::IndexSet,
+            ast.expr(id).span,
+            &[object, index, value],
+        )
+    }
+
+    fn visit_compound_assign(
+        &mut self,
+        ast: &Ast,
+        id: ExprId,
+        target: ExprId,
+        _operator: &Token,
+        value: ExprId,
+    ) -> CstNode {
+        let span = ast.expr(id).span;
+        self.node(ast, SyntaxKind::CompoundAssign, span, &[target, value])
+    }
+
+    fn visit_update(
+        &mut self,
+        ast: &Ast,
+        id: ExprId,
+        target: ExprId,
+        _operator: &Token,
+        _prefix: bool,
+    ) -> CstNode {
+        self.node(ast, SyntaxKind::Update, ast.expr(id).span, &[target])
+    }
+
+    fn visit_get(&mut self, ast: &Ast, id: ExprId, object: ExprId, _name: &Token) -> CstNode {
+        self.node(ast, SyntaxKind::Get, ast.expr(id).span, &[object])
+    }
+
+    fn visit_call(
+        &mut self,
+        ast: &Ast,
+        id: ExprId,
+        callee: ExprId,
+        _paren: &Token,
+        arguments: &[ExprId],
+    ) -> CstNode {
+        let mut children = vec![callee];
+        children.extend_from_slice(arguments);
+        self.node(ast, SyntaxKind::Call, ast.expr(id).span, &children)
+    }
+
+    fn visit_function(
+        &mut self,
+        ast: &Ast,
+        id: ExprId,
+        _params: &[Token],
+        body: &FunctionBody,
+    ) -> CstNode {
+        let children: Vec<Child> = match body {
+            FunctionBody::Block(statements) => {
+                statements.iter().map(|&stmt| Child::Stmt(stmt)).collect()
+            }
+            FunctionBody::Expression(expr) => vec![Child::Expr(*expr)],
+        };
+        self.node_with(ast, SyntaxKind::Function, ast.expr(id).span, &children)
+    }
+
+    fn visit_match(
+        &mut self,
+        ast: &Ast,
+        id: ExprId,
+        _keyword: &Token,
+        subject: ExprId,
+        arms: &[MatchArm<ExprId>],
+    ) -> CstNode {
+        let span = ast.expr(id).span;
+        self.match_node(ast, SyntaxKind::Match, span, subject, arms, Child::Expr)
+    }
+}
+
+impl StmtVisitor<CstNode> for CstBuilder {
+    fn visit_expression_stmt(&mut self, ast: &Ast, id: StmtId, expr: ExprId) -> CstNode {
+        self.node(ast, SyntaxKind::ExpressionStmt, ast.stmt(id).span, &[expr])
+    }
+
+    fn visit_print_stmt(&mut self, ast: &Ast, id: StmtId, expr: ExprId) -> CstNode {
+        self.node(ast, SyntaxKind::PrintStmt, ast.stmt(id).span, &[expr])
+    }
+
+    fn visit_var_stmt(
+        &mut self,
+        ast: &Ast,
+        id: StmtId,
+        _name: &Token,
+        initializer: Option<ExprId>,
+    ) -> CstNode {
+        self.node(
+            ast,
+            SyntaxKind::VarStmt,
+            ast.stmt(id).span,
+            initializer.as_slice(),
+        )
+    }
+
+    fn visit_function_stmt(
+        &mut self,
+        ast: &Ast,
+        id: StmtId,
+        _name: &Token,
+        function: ExprId,
+    ) -> CstNode {
+        self.node(
+            ast,
+            SyntaxKind::FunctionStmt,
+            ast.stmt(id).span,
+            &[function],
+        )
+    }
+
+    fn visit_return_stmt(
+        &mut self,
+        ast: &Ast,
+        id: StmtId,
+        _keyword: &Token,
+        value: Option<ExprId>,
+    ) -> CstNode {
+        self.node(
+            ast,
+            SyntaxKind::ReturnStmt,
+            ast.stmt(id).span,
+            value.as_slice(),
+        )
+    }
+
+    fn visit_block_stmt(&mut self, ast: &Ast, id: StmtId, statements: &[StmtId]) -> CstNode {
+        let children: Vec<Child> = statements.iter().map(|&stmt| Child::Stmt(stmt)).collect();
+        self.node_with(ast, SyntaxKind::Block, ast.stmt(id).span, &children)
+    }
+
+    fn visit_break_stmt(&mut self, ast: &Ast, id: StmtId, _keyword: &Token) -> CstNode {
+        self.node(ast, SyntaxKind::BreakStmt, ast.stmt(id).span, &[])
+    }
+
+    fn visit_continue_stmt(&mut self, ast: &Ast, id: StmtId, _keyword: &Token) -> CstNode {
+        self.node(ast, SyntaxKind::ContinueStmt, ast.stmt(id).span, &[])
+    }
+
+    fn visit_throw_stmt(
+        &mut self,
+        ast: &Ast,
+        id: StmtId,
+        _keyword: &Token,
+        value: ExprId,
+    ) -> CstNode {
+        self.node(ast, SyntaxKind::ThrowStmt, ast.stmt(id).span, &[value])
+    }
+
+    fn visit_try_stmt(
+        &mut self,
+        ast: &Ast,
+        id: StmtId,
+        body: StmtId,
+        catch: Option<&(Token, StmtId)>,
+        finally: Option<StmtId>,
+    ) -> CstNode {
+        let children: Vec<Child> = [Some(body), catch.map(|(_, handler)| *handler), finally]
+            .into_iter()
+            .flatten()
+            .map(Child::Stmt)
+            .collect();
+        self.node_with(ast, SyntaxKind::TryStmt, ast.stmt(id).span, &children)
+    }
+
+    fn visit_match_stmt(
+        &mut self,
+        ast: &Ast,
+        id: StmtId,
+        _keyword: &Token,
+        subject: ExprId,
+        arms: &[MatchArm],
+    ) -> CstNode {
+        self.match_node(
+            ast,
+            SyntaxKind::MatchStmt,
+            ast.stmt(id).span,
+            subject,
+            arms,
+            Child::Stmt,
+        )
+    }
+
+    fn visit_import_stmt(
+        &mut self,
+        ast: &Ast,
+        id: StmtId,
+        _path: &Token,
+        _imports: &Imports,
+    ) -> CstNode {
+        self.node(ast, SyntaxKind::ImportStmt, ast.stmt(id).span, &[])
+    }
+
+    fn visit_export_stmt(
+        &mut self,
+        ast: &Ast,
+        id: StmtId,
+        _keyword: &Token,
+        declaration: StmtId,
+    ) -> CstNode {
+        let children = [Child::Stmt(declaration)];
+        self.node_with(ast, SyntaxKind::ExportStmt, ast.stmt(id).span, &children)
+    }
+
+    fn visit_for_in_stmt(
+        &mut self,
+        ast: &Ast,
+        id: StmtId,
+        _name: &Token,
+        _keyword: &Token,
+        iterable: ExprId,
+        body: StmtId,
+    ) -> CstNode {
+        self.node_with(
+            ast,
+            SyntaxKind::ForInStmt,
+            ast.stmt(id).span,
+            &[Child::Expr(iterable), Child::Stmt(body)],
+        )
+    }
+}
+
+impl CstNode {
+    /// An indented dump of the tree with byte ranges, one element per line.
+    pub fn dump(&self) -> String {
+        let mut out = String::new();
+        self.dump_into(&mut out, 0);
+        out
+    }
+
+    fn dump_into(&self, out: &mut String, depth: usize) {
+        let indent = "  ".repeat(depth);
+        let _ = writeln!(
+            out,
+            "{}{:?}@{}..{}",
+            indent, self.kind, self.span.start, self.span.end
+        );
+        for child in &self.children {
+            match child {
+                CstElement::Node(node) => node.dump_into(out, depth + 1),
+                CstElement::Token(token) => {
+                    for trivia in &token.leading_trivia {
+                        let _ = writeln!(
+                            out,
+                            "{}  {:?}@{}..{} {:?}",
+                            indent, trivia.kind, trivia.span.start, trivia.span.end, trivia.text
+                        );
+                    }
+                    let _ = writeln!(
+                        out,
+                        "{}  {:?}@{}..{} {:?}",
+                        indent, token.token_type, token.span.start, token.span.end, token.text
+                    );
+                }
+            }
+        }
+    }
+}
+
+/// Prints the exact source text the tree was built from.
+impl fmt::Display for CstNode {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for child in &self.children {
+            match child {
+                CstElement::Node(node) => write!(f, "{}", node)?,
+                CstElement::Token(token) => {
+                    for trivia in &token.leading_trivia {
+                        f.write_str(&trivia.text)?;
+                    }
+                    f.write_str(&token.text)?;
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::{self, Rng};
+
+    #[test]
+    fn tree_prints_back_to_its_source() {
+        let mut rng = Rng::new(0x2545_f491_4f6c_dd1d);
+        for _ in 0..1000 {
+            let count = 1 + rng.below(4);
+            let source = testing::program(&mut rng, count);
+            let tree = parse_lossless(&source).unwrap_or_else(|e| panic!("{}\n{}", e, source));
+            assert_eq!(tree.to_string(), source);
+        }
+    }
+}
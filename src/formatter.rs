@@ -0,0 +1,577 @@
+// formatter.rs
+use crate::ast::Root;
+use crate::ast_export;
+use crate::json::Json;
+use crate::parser::Parser;
+use crate::tokenizer::{Scanner, Token, TokenType, Trivia, TriviaKind};
+
+const INDENT: &str = "  ";
+const MAX_WIDTH: usize = 80;
+
+/// Re-emits a Lox program in canonical style, keeping its comments.
+///
+/// Fails on programs that don't scan or parse, and refuses to return output
+/// that parses to a different AST than the input.
+pub fn format_source(source: &str) -> Result<String, String> {
+    let mut scanner = Scanner::new(source);
+    scanner.scan_tokens();
+    if scanner.has_errors {
+        return Err("Cannot format a file with syntax errors.".to_string());
+    }
+    let (tokens, trivia) = scanner.into_tokens_with_trivia();
+
+    let before = program_shape(source)?;
+
+    let items = merge(&tokens, &trivia);
+    let mut formatter = Formatter::default();
+    for (i, item) in items.iter().enumerate() {
+        formatter.item(*item, items.get(i + 1).copied());
+    }
+    let output = formatter.finish();
+
+    if program_shape(&output)? != before {
+        return Err("Formatting would change the program; leaving it untouched.".to_string());
+    }
+    Ok(output)
+}
+
+/// The program's tree without positions, for comparing ASTs.
+fn program_shape(source: &str) -> Result<Json, String> {
+    let mut parser = Parser::new(source);
+    let statements = parser.parse_program()?;
+    let ast = parser.into_ast();
+    Ok(ast_export::structure(&ast, &Root::Program(statements)))
+}
+
+#[derive(Clone, Copy)]
+enum Item<'a> {
+    Token(&'a Token),
+    Comment(&'a Trivia),
+}
+
+impl Item<'_> {
+    fn start_line(&self) -> usize {
+        match self {
+            // A token's line is where it ends, which matters for multi-line strings.
+            Item::Token(token) => token.line - token.lexeme.matches('\n').count(),
+            Item::Comment(comment) => comment.line,
+        }
+    }
+
+    fn end_line(&self) -> usize {
+        match self {
+            Item::Token(token) => token.line,
+            Item::Comment(comment) => comment.line + comment.text.matches('\n').count(),
+        }
+    }
+}
+
+/// Interleaves tokens (minus EOF) and comments in source order.
+fn merge<'a>(tokens: &'a [Token], trivia: &'a [Trivia]) -> Vec<Item<'a>> {
+    let mut items: Vec<Item> = tokens
+        .iter()
+        .filter(|token| token.token_type != TokenType::Eof)
+        .map(Item::Token)
+        .chain(trivia.iter().map(Item::Comment))
+        .collect();
+    items.sort_by_key(|item| match item {
+        Item::Token(token) => token.span.start,
+        Item::Comment(comment) => comment.span.start,
+    });
+    items
+}
+
+struct Piece {
+    text: String,
+    /// `None` for comments.
+    token_type: Option<TokenType>,
+    space_before: bool,
+}
+
+struct Line {
+    indent: usize,
+    pieces: Vec<Piece>,
+}
+
+/// What an indented `{` holds.
+#[derive(Clone, Copy, Default, PartialEq)]
+enum Brace {
+    #[default]
+    Block,
+    /// The arms of a `match` statement.
+    Match,
+    /// The arms of a `match` expression, one per line, ended by commas.
+    MatchExpression,
+}
+
+#[derive(Default)]
+struct Formatter {
+    lines: Vec<Line>,
+    current: Vec<Piece>,
+    /// Indentation of the line being built, fixed by its first piece.
+    current_indent: usize,
+    indent: usize,
+    /// Open `(` and `[` since the innermost enclosing `{`.
+    depth: usize,
+    /// For each open `(`, `[` and map `{`, the opening token and how many
+    /// `?` in it still wait for their `:`. A `:` with none waiting separates
+    /// slice bounds or a map key from its value.
+    groups: Vec<(TokenType, usize)>,
+    /// Saved `depth` for each enclosing `{`, and what it holds.
+    braces: Vec<(usize, Brace)>,
+    /// For each `match` whose subject hasn't closed, how many groups were
+    /// open before it and its kind of brace.
+    matches: Vec<(usize, Brace)>,
+    /// What the next indented `{` holds, set when a `match` subject closes.
+    next_brace: Brace,
+    /// The last token emitted, for spacing decisions.
+    prev: Option<TokenType>,
+    /// Whether `prev` was used as a prefix operator.
+    prev_unary: bool,
+    /// A `}` was just emitted; the line ends unless the next token continues
+    /// the same expression.
+    after_brace: bool,
+    /// Source line on which the previous item ended.
+    last_line: usize,
+}
+
+impl Formatter {
+    fn item(&mut self, item: Item, next: Option<Item>) {
+        if self.after_brace {
+            self.after_brace = false;
+            let continues = matches!(
+                item,
+                Item::Token(token) if continues_after_brace(&token.token_type)
+            );
+            if !continues {
+                self.end_line();
+            }
+        }
+
+        if self.current.is_empty()
+            && self.depth == 0
+            && !self.lines.is_empty()
+            && item.start_line() > self.last_line + 1
+        {
+            self.blank_line();
+        }
+
+        match item {
+            Item::Token(token) => self.token(token),
+            Item::Comment(comment) => self.comment(comment, item, next),
+        }
+        self.last_line = item.end_line();
+    }
+
+    fn token(&mut self, token: &Token) {
+        match token.token_type {
+            TokenType::LeftBrace if self.opens_map() => {
+                // Map braces lay out exactly like list brackets.
+                self.push_as(token, TokenType::LeftBracket, false);
+                self.depth += 1;
+                self.groups.push((TokenType::LeftBrace, 0));
+            }
+            TokenType::RightBrace
+                if matches!(self.groups.last(), Some((TokenType::LeftBrace, _))) =>
+            {
+                self.depth = self.depth.saturating_sub(1);
+                self.groups.pop();
+                self.push_as(token, TokenType::RightBracket, false);
+            }
+            TokenType::LeftBrace => {
+                self.push_token(token, false);
+                self.end_line();
+                self.indent += 1;
+                self.braces
+                    .push((self.depth, std::mem::take(&mut self.next_brace)));
+                self.depth = 0;
+            }
+            TokenType::RightBrace => {
+                self.end_line();
+                if self.lines.last().is_some_and(|line| line.pieces.is_empty()) {
+                    self.lines.pop();
+                }
+                self.indent = self.indent.saturating_sub(1);
+                let (depth, brace) = self.braces.pop().unwrap_or_default();
+                self.depth = depth;
+                self.push_token(token, false);
+                // A `match` expression goes on like any other operand.
+                self.after_brace = brace != Brace::MatchExpression;
+            }
+            TokenType::Semicolon => {
+                self.push_token(token, false);
+                if self.depth == 0 {
+                    self.end_line();
+                }
+            }
+            TokenType::LeftParen | TokenType::LeftBracket => {
+                self.push_token(token, false);
+                self.depth += 1;
+                self.groups.push((token.token_type.clone(), 0));
+            }
+            TokenType::RightParen | TokenType::RightBracket => {
+                self.depth = self.depth.saturating_sub(1);
+                self.groups.pop();
+                if let Some(&(groups, brace)) = self.matches.last() {
+                    if groups == self.groups.len() {
+                        self.matches.pop();
+                        self.next_brace = brace;
+                    }
+                }
+                self.push_token(token, false);
+            }
+            TokenType::Match => {
+                let brace = if self.starts_statement() {
+                    Brace::Match
+                } else {
+                    Brace::MatchExpression
+                };
+                self.matches.push((self.groups.len(), brace));
+                self.push_token(token, false);
+            }
+            TokenType::Comma
+                if self.depth == 0
+                    && matches!(self.braces.last(), Some((_, Brace::MatchExpression))) =>
+            {
+                self.push_token(token, false);
+                self.end_line();
+            }
+            TokenType::Question => {
+                if let Some((_, questions)) = self.groups.last_mut() {
+                    *questions += 1;
+                }
+                self.push_token(token, false);
+            }
+            TokenType::Colon => match self.groups.last_mut() {
+                Some((_, questions)) if *questions > 0 => {
+                    *questions -= 1;
+                    self.push_token(token, false);
+                }
+                Some((TokenType::LeftBracket, _)) => {
+                    // `xs[a:b]` is written tight, like an index.
+                    self.push(token.lexeme.clone(), Some(TokenType::Colon), false);
+                    self.prev = Some(TokenType::Colon);
+                    self.prev_unary = true;
+                }
+                Some((TokenType::LeftBrace, _)) => {
+                    // `{key: value}`
+                    self.push(token.lexeme.clone(), Some(TokenType::Colon), false);
+                    self.prev = Some(TokenType::Colon);
+                    self.prev_unary = false;
+                }
+                _ => self.push_token(token, false),
+            },
+            TokenType::Minus | TokenType::Bang | TokenType::PlusPlus | TokenType::MinusMinus => {
+                let unary = !self.prev.as_ref().is_some_and(ends_operand);
+                self.push_token(token, unary);
+            }
+            _ => self.push_token(token, false),
+        }
+    }
+
+    /// Whether a `{` here starts a map literal rather than a block.
+    fn opens_map(&self) -> bool {
+        match &self.prev {
+            // The value of a `match` expression arm.
+            Some(TokenType::EqualGreater) => {
+                self.depth == 0 && matches!(self.braces.last(), Some((_, Brace::MatchExpression)))
+            }
+            prev => prev.as_ref().is_some_and(|prev| !before_block(prev)),
+        }
+    }
+
+    /// Whether the next token starts a statement rather than continuing an
+    /// expression, which tells the two forms of `match` apart.
+    fn starts_statement(&self) -> bool {
+        use TokenType::*;
+        match &self.prev {
+            None | Some(Semicolon | LeftBrace | RightBrace | RightParen | Else) => true,
+            // An arm of a `match` statement runs a statement.
+            Some(EqualGreater) => {
+                self.depth == 0 && matches!(self.braces.last(), Some((_, Brace::Match)))
+            }
+            Some(_) => false,
+        }
+    }
+
+    fn comment(&mut self, comment: &Trivia, item: Item, next: Option<Item>) {
+        let trailing = item.start_line() == self.last_line;
+        let reopen = trailing && self.current.is_empty();
+        if reopen {
+            // Reopen the line just ended so the comment stays at its end.
+            if let Some(line) = self.lines.pop() {
+                self.current_indent = line.indent;
+                self.current = line.pieces;
+            }
+        }
+        let space = !self.current.is_empty();
+        let ends_line = match comment.kind {
+            TriviaKind::LineComment => true,
+            // The formatter's scanner only records comments.
+            _ => next.is_none_or(|next| next.start_line() > item.end_line()),
+        };
+
+        if !trailing && ends_line {
+            // A comment on lines of its own.
+            self.end_line();
+            self.push(comment.text.clone(), None, false);
+        } else {
+            self.push(comment.text.clone(), None, space);
+        }
+        if ends_line || reopen {
+            self.end_line();
+        }
+    }
+
+    fn push_token(&mut self, token: &Token, unary: bool) {
+        self.push_as(token, token.token_type.clone(), unary);
+    }
+
+    /// Pushes `token` spaced and wrapped as if it were a `token_type`.
+    fn push_as(&mut self, token: &Token, token_type: TokenType, unary: bool) {
+        let space = match self.current.last() {
+            None => false,
+            Some(last) => match &last.token_type {
+                None => true,
+                Some(prev) => space_between(prev, self.prev_unary, &token_type),
+            },
+        };
+        self.push(token.lexeme.clone(), Some(token_type.clone()), space);
+        self.prev = Some(token_type);
+        self.prev_unary = unary;
+    }
+
+    fn push(&mut self, text: String, token_type: Option<TokenType>, space_before: bool) {
+        if self.current.is_empty() {
+            // Lines that start inside parentheses are continuations, except for
+            // the line that closes them.
+            let closes = matches!(
+                token_type,
+                Some(TokenType::RightParen | TokenType::RightBracket | TokenType::RightBrace)
+            );
+            self.current_indent = self.indent + usize::from(self.depth > 0 && !closes);
+        }
+        self.current.push(Piece {
+            text,
+            token_type,
+            space_before,
+        });
+    }
+
+    fn end_line(&mut self) {
+        if !self.current.is_empty() {
+            let line = Line {
+                indent: self.current_indent,
+                pieces: std::mem::take(&mut self.current),
+            };
+            self.lines.extend(wrap(line));
+        }
+    }
+
+    fn blank_line(&mut self) {
+        if self
+            .lines
+            .last()
+            .is_some_and(|line| !line.pieces.is_empty())
+        {
+            self.lines.push(Line {
+                indent: 0,
+                pieces: Vec::new(),
+            });
+        }
+    }
+
+    fn finish(mut self) -> String {
+        self.end_line();
+        let mut out = String::new();
+        for line in &self.lines {
+            out.push_str(&render(line));
+            out.push('\n');
+        }
+        out
+    }
+}
+
+fn render(line: &Line) -> String {
+    let mut out = String::new();
+    if !line.pieces.is_empty() {
+        out.push_str(&INDENT.repeat(line.indent));
+    }
+    for piece in &line.pieces {
+        if piece.space_before {
+            out.push(' ');
+        }
+        out.push_str(&piece.text);
+    }
+    out
+}
+
+/// Splits a line that is too wide at the first parenthesized or bracketed
+/// group containing top-level commas, one element per line, then wraps the
+/// pieces again.
+fn wrap(line: Line) -> Vec<Line> {
+    if render(&line).chars().count() <= MAX_WIDTH {
+        return vec![line];
+    }
+    let Some((open, close, commas)) = find_list(&line.pieces) else {
+        return vec![line];
+    };
+
+    let indent = line.indent;
+    let mut pieces = line.pieces;
+    let tail: Vec<Piece> = pieces.split_off(close);
+    let mut inner: Vec<Piece> = pieces.split_off(open + 1);
+
+    let mut lines = wrap(Line { indent, pieces });
+    for &comma in commas.iter().rev() {
+        let rest = inner.split_off(comma + 1 - (open + 1));
+        push_element(&mut lines, indent, rest);
+    }
+    push_element(&mut lines, indent, inner);
+    // Elements were split off back to front.
+    let first_element = lines.len() - commas.len() - 1;
+    lines[first_element..].reverse();
+    let mut tail = tail;
+    tail[0].space_before = false;
+    lines.extend(wrap(Line {
+        indent,
+        pieces: tail,
+    }));
+    lines
+}
+
+fn push_element(lines: &mut Vec<Line>, indent: usize, mut pieces: Vec<Piece>) {
+    if let Some(first) = pieces.first_mut() {
+        first.space_before = false;
+        lines.push(Line {
+            indent: indent + 1,
+            pieces,
+        });
+    }
+}
+
+/// Finds the first `(` or `[` whose matching close is on this line and which
+/// directly contains commas. Returns the open and close indices and the
+/// indices of those commas.
+fn find_list(pieces: &[Piece]) -> Option<(usize, usize, Vec<usize>)> {
+    use TokenType::*;
+    for (open, piece) in pieces.iter().enumerate() {
+        if !matches!(piece.token_type, Some(LeftParen | LeftBracket)) {
+            continue;
+        }
+        let mut depth = 0;
+        let mut commas = Vec::new();
+        for (i, piece) in pieces.iter().enumerate().skip(open) {
+            match piece.token_type {
+                Some(LeftParen | LeftBracket | LeftBrace) => depth += 1,
+                Some(RightParen | RightBracket | RightBrace) => {
+                    depth -= 1;
+                    if depth == 0 {
+                        if !commas.is_empty() {
+                            return Some((open, i, commas));
+                        }
+                        break;
+                    }
+                }
+                Some(Comma) if depth == 1 => commas.push(i),
+                _ => {}
+            }
+        }
+    }
+    None
+}
+
+/// Whether a token can end an operand, making a following `-` binary and a
+/// following `(` or `[` a call or index. A `++` or `--` that ends an operand
+/// is postfix.
+fn ends_operand(token_type: &TokenType) -> bool {
+    matches!(
+        token_type,
+        TokenType::Number
+            | TokenType::String
+            | TokenType::Identifier
+            | TokenType::True
+            | TokenType::False
+            | TokenType::Nil
+            | TokenType::This
+            | TokenType::Super
+            | TokenType::RightParen
+            | TokenType::RightBracket
+            | TokenType::PlusPlus
+            | TokenType::MinusMinus
+    )
+}
+
+/// Whether a `{` after this token starts a block rather than a map literal.
+fn before_block(token_type: &TokenType) -> bool {
+    matches!(
+        token_type,
+        TokenType::Semicolon
+            | TokenType::LeftBrace
+            | TokenType::RightBrace
+            | TokenType::RightParen
+            | TokenType::Else
+            | TokenType::Try
+            | TokenType::Finally
+            | TokenType::EqualGreater
+    )
+}
+
+fn continues_after_brace(token_type: &TokenType) -> bool {
+    matches!(
+        token_type,
+        TokenType::Semicolon
+            | TokenType::Comma
+            | TokenType::Dot
+            | TokenType::RightParen
+            | TokenType::RightBracket
+            | TokenType::Else
+            | TokenType::Catch
+            | TokenType::Finally
+    )
+}
+
+fn space_between(prev: &TokenType, prev_unary: bool, next: &TokenType) -> bool {
+    use TokenType::*;
+    if prev_unary {
+        // `- -x` and `- --x` would scan as different tokens if joined.
+        return matches!((prev, next), (Minus | MinusMinus, Minus | MinusMinus));
+    }
+    if matches!(prev, LeftParen | LeftBracket | Dot | DotDot | DotDotEqual) {
+        return false;
+    }
+    match next {
+        RightParen | RightBracket | Comma | Semicolon | Dot => false,
+        PlusPlus | MinusMinus => !ends_operand(prev),
+        // Tight as an operator, but a rest pattern follows a comma.
+        DotDot | DotDotEqual => *prev == Comma,
+        LeftParen | LeftBracket => !ends_operand(prev),
+        _ => true,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::{self, Rng};
+
+    #[test]
+    fn formatting_is_idempotent() {
+        let mut rng = Rng::new(0x94d0_49bb_1331_11eb);
+        for _ in 0..500 {
+            let count = 1 + rng.below(4);
+            let source = testing::program(&mut rng, count);
+            let once = format_source(&source).unwrap_or_else(|e| panic!("{}\n{}", e, source));
+            let twice = format_source(&once).unwrap_or_else(|e| panic!("{}\n{}", e, once));
+            assert_eq!(once, twice, "formatting {}", source);
+        }
+    }
+
+    #[test]
+    fn shape_tells_strings_from_other_literals() {
+        let shape = |source| program_shape(source).unwrap();
+        assert_ne!(shape("print a;"), shape("print \"a\";"));
+        assert_ne!(shape("print 1;"), shape("print \"1.0\";"));
+        assert_eq!(shape("print (1 +\n 2);"), shape("print (1 + 2);"));
+    }
+}
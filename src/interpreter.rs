@@ -1,82 +1,1275 @@
 // interpreter.rs
-use crate::ast::{Expr, LiteralValue};
+use crate::ast::{
+    Ast, ExprId, ExprKind, ExprVisitor, FunctionBody, Imports, LiteralValue, MatchArm, Pattern,
+    PatternKind, StmtId, StmtVisitor,
+};
+use crate::list;
+use crate::map::{self, Key, Map};
+use crate::module::{self, Module};
+use crate::parser::Parser;
+use crate::range::{self, Range};
+use crate::tokenizer::{Span, Token, TokenType};
+use crate::value::{ErrorObject, Function, Scope, Value};
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::fmt;
+use std::fs;
+use std::ops::RangeInclusive;
+use std::path::{Path, PathBuf};
+use std::rc::Rc;
 
-pub struct Interpreter;
+#[derive(Debug)]
+pub struct RuntimeError {
+    pub message: String,
+    pub line: usize,
+    /// The whole sub-expression that failed, for highlighting.
+    pub span: Span,
+    /// The value given to `throw`, if this error came from one.
+    pub thrown: Option<Value>,
+}
+
+impl RuntimeError {
+    fn new(token: &Token, span: Span, message: &str) -> Self {
+        RuntimeError {
+            message: message.to_string(),
+            line: token.line,
+            span,
+            thrown: None,
+        }
+    }
+
+    /// The value a `catch` clause in `file` receives: whatever was thrown,
+    /// or an error object describing a built-in failure.
+    fn into_value(self, file: Option<&Path>) -> Value {
+        self.thrown.unwrap_or_else(|| {
+            Value::Error(Rc::new(ErrorObject {
+                message: self.message,
+                line: self.line,
+                span: self.span,
+                file: file.map(Path::to_path_buf),
+            }))
+        })
+    }
+}
+
+impl fmt::Display for RuntimeError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}\n[line {}]", self.message, self.line)
+    }
+}
+
+type Result<T> = std::result::Result<T, RuntimeError>;
+
+/// How a statement finished. `break` and `continue` unwind to the innermost
+/// loop, and `return` to the innermost function call, as ordinary results
+/// rather than as errors.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Flow {
+    Normal,
+    Break,
+    Continue,
+    Return(Value),
+}
+
+/// How deeply calls may nest before the interpreter reports a stack
+/// overflow rather than running out of native stack. A call can take up to
+/// about 30 KiB of native stack in a debug build, which `main` sizes the
+/// interpreter's thread for.
+const MAX_CALL_DEPTH: usize = 10_000;
+
+/// Where compound assignment and `++`/`--` read and then write. An
+/// element keeps its evaluated object and index, so that neither is
+/// evaluated twice.
+enum Place<'a> {
+    Variable(&'a Token),
+    Element(Value, &'a Token, Value),
+}
+
+pub struct Interpreter {
+    /// Variables of each enclosing scope; the first holds the globals.
+    scopes: Vec<Scope>,
+    /// The tree being run, which functions created from it keep alive.
+    ast: Rc<Ast>,
+    /// Calls to Lox functions in progress.
+    call_depth: usize,
+    /// The file being run, which relative imports are resolved against.
+    file: Option<PathBuf>,
+    /// Modules that have finished running, by canonical path.
+    modules: HashMap<PathBuf, Rc<Module>>,
+    /// Canonical paths of the files being run, outermost first, for
+    /// reporting import cycles.
+    loading: Vec<PathBuf>,
+}
 
 impl Interpreter {
-    pub fn evaluate(&self, expr: Expr) -> LiteralValue {
-        match expr {
-            Expr::Literal(val) => val,
-            Expr::Grouping(inner) => self.evaluate(*inner),
-            Expr::Unary(op, right) => {
-                let right_val = self.evaluate(*right);
-                match op {
-                    crate::tokenizer::TokenType::Minus => {
-                        if let LiteralValue::Number(n) = right_val {
-                            LiteralValue::Number(-n)
-                        } else {
-                            panic!("Operand must be a number")
-                        }
+    pub fn new() -> Self {
+        Interpreter {
+            scopes: vec![globals()],
+            ast: Rc::default(),
+            call_depth: 0,
+            file: None,
+            modules: HashMap::new(),
+            loading: Vec::new(),
+        }
+    }
+
+    /// An interpreter for the program in `path`.
+    pub fn for_file(path: &Path) -> Self {
+        let mut interpreter = Interpreter::new();
+        interpreter.file = Some(path.to_path_buf());
+        interpreter.loading.extend(fs::canonicalize(path));
+        interpreter
+    }
+
+    pub fn interpret(&mut self, ast: Rc<Ast>, statements: &[StmtId]) -> Result<()> {
+        self.ast = Rc::clone(&ast);
+        // `break` and `continue` are only accepted inside loops, and
+        // `return` inside functions.
+        self.execute(&ast, statements).map(|_| ())
+    }
+
+    /// Evaluates a bare expression, as the `evaluate` command does.
+    pub fn evaluate_expression(&mut self, ast: Rc<Ast>, expr: ExprId) -> Result<Value> {
+        self.ast = Rc::clone(&ast);
+        self.evaluate(&ast, expr)
+    }
+
+    /// Runs `statements` until one of them breaks or continues a loop, or
+    /// returns.
+    fn execute(&mut self, ast: &Ast, statements: &[StmtId]) -> Result<Flow> {
+        for &stmt in statements {
+            match ast.accept_stmt(stmt, self)? {
+                Flow::Normal => {}
+                flow => return Ok(flow),
+            }
+        }
+        Ok(Flow::Normal)
+    }
+
+    fn evaluate(&mut self, ast: &Ast, expr: ExprId) -> Result<Value> {
+        ast.accept_expr(expr, self)
+    }
+
+    /// Runs `statements` in a new scope, which is discarded afterwards even
+    /// if they fail.
+    fn execute_block(&mut self, ast: &Ast, statements: &[StmtId]) -> Result<Flow> {
+        self.execute_in(HashMap::new(), |interpreter| {
+            interpreter.execute(ast, statements)
+        })
+    }
+
+    /// Runs `body` in a new scope holding `variables`, which is discarded
+    /// afterwards even if it fails.
+    fn execute_in<T>(
+        &mut self,
+        variables: HashMap<String, Value>,
+        body: impl FnOnce(&mut Self) -> Result<T>,
+    ) -> Result<T> {
+        self.scopes.push(Rc::new(RefCell::new(variables)));
+        let result = body(self);
+        self.scopes.pop();
+        result
+    }
+
+    /// Defines `name` in the innermost scope.
+    fn define(&mut self, name: &str, value: Value) {
+        self.scopes
+            .last()
+            .expect("the global scope is never popped")
+            .borrow_mut()
+            .insert(name.to_string(), value);
+    }
+
+    /// Runs the module that `path` (a string token) names, or returns it if
+    /// it has already run.
+    fn import(&mut self, path: &Token) -> Result<Rc<Module>> {
+        let error = |message: String| RuntimeError::new(path, path.span, &message);
+        let name = &path.literal;
+        let file = module::resolve(name, self.file.as_deref())
+            .ok_or_else(|| error(format!("Can't find module \"{}\".", name)))?;
+        let canonical = fs::canonicalize(&file)
+            .map_err(|e| error(format!("Can't read module \"{}\": {}.", name, e)))?;
+        if let Some(module) = self.modules.get(&canonical) {
+            return Ok(Rc::clone(module));
+        }
+        if let Some(start) = self.loading.iter().position(|p| *p == canonical) {
+            let cycle: Vec<String> = self.loading[start..]
+                .iter()
+                .chain([&canonical])
+                .map(|p| module::display_name(p))
+                .collect();
+            return Err(error(format!("Import cycle: {}.", cycle.join(" -> "))));
+        }
+
+        let source = fs::read_to_string(&file)
+            .map_err(|e| error(format!("Can't read module \"{}\": {}.", name, e)))?;
+        let mut parser = Parser::new(&source);
+        let statements = parser.parse_program();
+        if parser.has_scan_errors {
+            // The scanner has already reported them.
+            return Err(error(format!("Module \"{}\" has syntax errors.", name)));
+        }
+        let ast = Rc::new(parser.into_ast());
+        let statements = statements
+            .and_then(|statements| {
+                module::check_imports(&ast, &statements, Some(&file)).map(|()| statements)
+            })
+            .map_err(|e| error(format!("In module \"{}\": {}", name, e)))?;
+
+        // The module runs with its own globals, and resolves its own imports
+        // against its own directory.
+        let scopes = std::mem::replace(&mut self.scopes, vec![globals()]);
+        let importer_ast = std::mem::replace(&mut self.ast, Rc::clone(&ast));
+        let importer = self.file.replace(file.clone());
+        self.loading.push(canonical.clone());
+        let result = self.execute(&ast, &statements);
+        self.loading.pop();
+        self.file = importer;
+        self.ast = importer_ast;
+        let module_scopes = std::mem::replace(&mut self.scopes, scopes);
+
+        if let Err(e) = result {
+            // Keep the thrown value so that `catch` still receives it.
+            return Err(RuntimeError {
+                thrown: e.thrown,
+                ..error(format!(
+                    "In module \"{}\" at line {}: {}",
+                    name, e.line, e.message
+                ))
+            });
+        }
+        let exports = module::exports(&ast, &statements);
+        let variables = module_scopes[0]
+            .borrow()
+            .iter()
+            .filter(|(name, _)| exports.contains(*name))
+            .map(|(name, value)| (name.clone(), value.clone()))
+            .collect();
+        let module = Rc::new(Module::new(&file, variables));
+        self.modules.insert(canonical, Rc::clone(&module));
+        Ok(module)
+    }
+
+    /// The innermost scope with a variable called `name`.
+    fn lookup(&self, name: &str) -> Option<&Scope> {
+        self.scopes
+            .iter()
+            .rev()
+            .find(|scope| scope.borrow().contains_key(name))
+    }
+
+    /// Sets the innermost variable called `name`, if there is one.
+    fn assign(&mut self, name: &str, value: Value) -> bool {
+        match self.lookup(name) {
+            Some(scope) => {
+                scope.borrow_mut().insert(name.to_string(), value);
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Calls a Lox function with arguments that fit its parameters. Its body
+    /// runs in the scopes it was created in, plus one for the parameters.
+    fn call(
+        &mut self,
+        function: &Function,
+        arguments: Vec<Value>,
+        paren: &Token,
+        span: Span,
+    ) -> Result<Value> {
+        let ExprKind::Function(params, body) = &function.ast.expr(function.node).kind else {
+            unreachable!("functions are made from Function nodes");
+        };
+        check_arity(&(params.len()..=params.len()), arguments.len(), paren, span)?;
+        if self.call_depth == MAX_CALL_DEPTH {
+            return Err(RuntimeError::new(paren, span, "Stack overflow."));
+        }
+        let parameters = params
+            .iter()
+            .map(|param| param.lexeme.clone())
+            .zip(arguments)
+            .collect();
+        let mut scopes = function.closure.clone();
+        scopes.push(Rc::new(RefCell::new(parameters)));
+
+        let caller_scopes = std::mem::replace(&mut self.scopes, scopes);
+        let caller_ast = std::mem::replace(&mut self.ast, Rc::clone(&function.ast));
+        let caller_file = std::mem::replace(&mut self.file, function.file.clone());
+        self.call_depth += 1;
+        let result = match body {
+            FunctionBody::Block(statements) => {
+                self.execute(&function.ast, statements)
+                    .map(|flow| match flow {
+                        Flow::Return(value) => value,
+                        _ => Value::Nil,
+                    })
+            }
+            FunctionBody::Expression(expr) => self.evaluate(&function.ast, *expr),
+        };
+        self.call_depth -= 1;
+        self.file = caller_file;
+        self.ast = caller_ast;
+        self.scopes = caller_scopes;
+
+        if function.file == self.file {
+            return result;
+        }
+        // The error's line is in another file, so report it at the call.
+        result.map_err(|e| {
+            let file = function
+                .file
+                .as_deref()
+                .map_or_else(String::new, module::display_name);
+            RuntimeError {
+                thrown: e.thrown,
+                ..RuntimeError::new(
+                    paren,
+                    span,
+                    &format!("In {} at line {}: {}", file, e.line, e.message),
+                )
+            }
+        })
+    }
+
+    /// A function for the `Function` node `node` in the tree being run,
+    /// closing over the current scopes.
+    fn closure(&self, name: Option<String>, node: ExprId) -> Value {
+        Value::Function(Rc::new(Function {
+            name,
+            ast: Rc::clone(&self.ast),
+            node,
+            closure: self.scopes.clone(),
+            file: self.file.clone(),
+        }))
+    }
+
+    /// Whether `value` matches `pattern`, collecting the names it binds.
+    fn matches(
+        &mut self,
+        ast: &Ast,
+        pattern: &Pattern,
+        value: &Value,
+        bindings: &mut HashMap<String, Value>,
+    ) -> Result<bool> {
+        match &pattern.kind {
+            PatternKind::Literal(expr) => Ok(self.evaluate(ast, *expr)? == *value),
+            PatternKind::Binding(name) => {
+                if name.lexeme != "_" {
+                    bindings.insert(name.lexeme.clone(), value.clone());
+                }
+                Ok(true)
+            }
+            PatternKind::List(elements, rest) => {
+                let Value::List(list) = value else {
+                    return Ok(false);
+                };
+                // Copied so that guards and bodies can't change what the
+                // rest of the pattern sees.
+                let list = list.borrow().clone();
+                let fits = match rest {
+                    Some(_) => list.len() >= elements.len(),
+                    None => list.len() == elements.len(),
+                };
+                if !fits {
+                    return Ok(false);
+                }
+                for (element, item) in elements.iter().zip(&list) {
+                    if !self.matches(ast, element, item, bindings)? {
+                        return Ok(false);
                     }
-                    crate::tokenizer::TokenType::Bang => LiteralValue::Bool(!is_truthy(right_val)),
-                    _ => panic!("Unknown unary operator"),
                 }
+                if let Some(rest) = rest.as_ref().filter(|rest| rest.lexeme != "_") {
+                    let remaining = list[elements.len()..].to_vec();
+                    bindings.insert(
+                        rest.lexeme.clone(),
+                        Value::List(Rc::new(RefCell::new(remaining))),
+                    );
+                }
+                Ok(true)
+            }
+        }
+    }
+
+    /// Runs the first arm of a `match` of either form whose pattern and
+    /// guard accept the subject, with `run` giving the result of its body.
+    fn run_match<Body, T>(
+        &mut self,
+        ast: &Ast,
+        keyword: &Token,
+        subject: ExprId,
+        arms: &[MatchArm<Body>],
+        run: impl Fn(&mut Self, &Body) -> Result<T>,
+    ) -> Result<T> {
+        let value = self.evaluate(ast, subject)?;
+        for arm in arms {
+            let mut bindings = HashMap::new();
+            if !self.matches(ast, &arm.pattern, &value, &mut bindings)? {
+                continue;
             }
-            Expr::Binary(left, op, right) => {
-                let left_val = self.evaluate(*left);
-                let right_val = self.evaluate(*right);
-
-                use crate::tokenizer::TokenType::*;
-                match op {
-                    Plus => match (left_val, right_val) {
-                        (LiteralValue::Number(a), LiteralValue::Number(b)) => {
-                            LiteralValue::Number(a + b)
-                        }
-                        (LiteralValue::String(a), LiteralValue::String(b)) => {
-                            LiteralValue::String(a + &b)
-                        }
-                        _ => panic!("Operands must be two numbers or two strings"),
-                    },
-                    Minus => bin_op_numeric(left_val, right_val, |a, b| a - b),
-                    Star => bin_op_numeric(left_val, right_val, |a, b| a * b),
-                    Slash => bin_op_numeric(left_val, right_val, |a, b| a / b),
-                    EqualEqual => LiteralValue::Bool(left_val == right_val),
-                    BangEqual => LiteralValue::Bool(left_val != right_val),
-                    Greater => bin_op_numeric_bool(left_val, right_val, |a, b| a > b),
-                    GreaterEqual => bin_op_numeric_bool(left_val, right_val, |a, b| a >= b),
-                    Less => bin_op_numeric_bool(left_val, right_val, |a, b| a < b),
-                    LessEqual => bin_op_numeric_bool(left_val, right_val, |a, b| a <= b),
-                    _ => panic!("Unknown binary operator"),
+            let result = self.execute_in(bindings, |interpreter| {
+                if let Some(guard) = arm.guard {
+                    if !interpreter.evaluate(ast, guard)?.is_truthy() {
+                        return Ok(None);
+                    }
                 }
+                run(interpreter, &arm.body).map(Some)
+            });
+            if let Some(result) = result? {
+                return Ok(result);
+            }
+        }
+        let message = match value {
+            Value::String(s) => format!("No match arm matches \"{}\".", s),
+            value => format!("No match arm matches {}.", value),
+        };
+        Err(RuntimeError::new(keyword, ast.expr(subject).span, &message))
+    }
+
+    /// Evaluates the object of an index expression, which must be a list or
+    /// a map.
+    fn indexable(&mut self, ast: &Ast, id: ExprId, expr: ExprId, bracket: &Token) -> Result<Value> {
+        match self.evaluate(ast, expr)? {
+            object @ (Value::List(_) | Value::Map(_)) => Ok(object),
+            _ => Err(RuntimeError::new(
+                bracket,
+                ast.expr(id).span,
+                "Only lists and maps can be indexed.",
+            )),
+        }
+    }
+
+    /// Evaluates the object and index of an assignment target.
+    fn place<'a>(&mut self, ast: &'a Ast, target: ExprId) -> Result<Place<'a>> {
+        match &ast.expr(target).kind {
+            ExprKind::Variable(name) => Ok(Place::Variable(name)),
+            ExprKind::Index(object, bracket, index) => {
+                let object = self.indexable(ast, target, *object, bracket)?;
+                let index = self.evaluate(ast, *index)?;
+                Ok(Place::Element(object, bracket, index))
             }
+            _ => unreachable!("the parser only allows variables and indexes as targets"),
+        }
+    }
+
+    fn read(&mut self, ast: &Ast, target: ExprId, place: &Place) -> Result<Value> {
+        match place {
+            Place::Variable(name) => self.visit_variable(ast, target, name),
+            Place::Element(object, bracket, index) => element(object, index)
+                .map_err(|message| RuntimeError::new(bracket, ast.expr(target).span, &message)),
         }
     }
+
+    fn write(&mut self, ast: &Ast, target: ExprId, place: &Place, value: Value) -> Result<()> {
+        match place {
+            Place::Variable(name) => {
+                // `read` has already checked that the variable exists.
+                self.assign(&name.lexeme, value);
+                Ok(())
+            }
+            Place::Element(object, bracket, index) => set_element(object, index, value)
+                .map_err(|message| RuntimeError::new(bracket, ast.expr(target).span, &message)),
+        }
+    }
+}
+
+/// The variables every file starts with.
+fn globals() -> Scope {
+    Rc::new(RefCell::new(HashMap::from([(
+        "range".to_string(),
+        range::function(),
+    )])))
 }
 
-fn is_truthy(val: LiteralValue) -> bool {
-    match val {
-        LiteralValue::Nil => false,
-        LiteralValue::Bool(b) => b,
-        _ => true,
+impl Default for Interpreter {
+    fn default() -> Self {
+        Interpreter::new()
     }
 }
 
-fn bin_op_numeric<F: Fn(f64, f64) -> f64>(a: LiteralValue, b: LiteralValue, op: F) -> LiteralValue {
-    if let (LiteralValue::Number(a), LiteralValue::Number(b)) = (a, b) {
-        LiteralValue::Number(op(a, b))
+impl ExprVisitor<Result<Value>> for Interpreter {
+    fn visit_literal(&mut self, _ast: &Ast, _id: ExprId, value: &LiteralValue) -> Result<Value> {
+        Ok(Value::from(value))
+    }
+
+    fn visit_grouping(&mut self, ast: &Ast, _id: ExprId, inner: ExprId) -> Result<Value> {
+        self.evaluate(ast, inner)
+    }
+
+    fn visit_unary(
+        &mut self,
+        ast: &Ast,
+        id: ExprId,
+        operator: &Token,
+        right: ExprId,
+    ) -> Result<Value> {
+        let right_val = self.evaluate(ast, right)?;
+        match operator.token_type {
+            TokenType::Minus => {
+                if let Value::Number(n) = right_val {
+                    Ok(Value::Number(-n))
+                } else {
+                    Err(RuntimeError::new(
+                        operator,
+                        ast.expr(id).span,
+                        "Operand must be a number.",
+                    ))
+                }
+            }
+            TokenType::Bang => Ok(Value::Bool(!right_val.is_truthy())),
+            _ => unreachable!("parser only produces '-' and '!' unary operators"),
+        }
+    }
+
+    fn visit_binary(
+        &mut self,
+        ast: &Ast,
+        id: ExprId,
+        left: ExprId,
+        operator: &Token,
+        right: ExprId,
+    ) -> Result<Value> {
+        let left_val = self.evaluate(ast, left)?;
+        let right_val = self.evaluate(ast, right)?;
+
+        use crate::tokenizer::TokenType::*;
+        let result = match operator.token_type {
+            Plus | Minus | Star | Slash | Percent => {
+                return arithmetic(&operator.token_type, left_val, right_val)
+                    .map_err(|message| RuntimeError::new(operator, ast.expr(id).span, message));
+            }
+            StarStar => bin_op_numeric(left_val, right_val, f64::powf),
+            EqualEqual => Some(Value::Bool(left_val == right_val)),
+            BangEqual => Some(Value::Bool(left_val != right_val)),
+            Greater => bin_op_numeric_bool(left_val, right_val, |a, b| a > b),
+            GreaterEqual => bin_op_numeric_bool(left_val, right_val, |a, b| a >= b),
+            Less => bin_op_numeric_bool(left_val, right_val, |a, b| a < b),
+            LessEqual => bin_op_numeric_bool(left_val, right_val, |a, b| a <= b),
+            DotDot | DotDotEqual => {
+                let inclusive = operator.token_type == DotDotEqual;
+                let range = Range::new(&left_val, &right_val, inclusive)
+                    .map_err(|message| RuntimeError::new(operator, ast.expr(id).span, &message))?;
+                Some(Value::Range(range))
+            }
+            // Both sides have already been evaluated, in order.
+            Comma => Some(right_val),
+            _ => unreachable!("parser only produces known binary operators"),
+        };
+        result.ok_or_else(|| {
+            RuntimeError::new(operator, ast.expr(id).span, "Operands must be numbers.")
+        })
+    }
+
+    fn visit_logical(
+        &mut self,
+        ast: &Ast,
+        _id: ExprId,
+        left: ExprId,
+        operator: &Token,
+        right: ExprId,
+    ) -> Result<Value> {
+        let left_val = self.evaluate(ast, left)?;
+        let short_circuits = match operator.token_type {
+            TokenType::Or => left_val.is_truthy(),
+            _ => !left_val.is_truthy(),
+        };
+        if short_circuits {
+            Ok(left_val)
+        } else {
+            self.evaluate(ast, right)
+        }
+    }
+
+    fn visit_conditional(
+        &mut self,
+        ast: &Ast,
+        _id: ExprId,
+        condition: ExprId,
+        then_branch: ExprId,
+        else_branch: ExprId,
+    ) -> Result<Value> {
+        // Only the taken branch is evaluated.
+        if self.evaluate(ast, condition)?.is_truthy() {
+            self.evaluate(ast, then_branch)
+        } else {
+            self.evaluate(ast, else_branch)
+        }
+    }
+
+    fn visit_variable(&mut self, ast: &Ast, id: ExprId, name: &Token) -> Result<Value> {
+        let scope = self.lookup(&name.lexeme);
+        let value = scope.and_then(|scope| scope.borrow().get(&name.lexeme).cloned());
+        value.ok_or_else(|| {
+            RuntimeError::new(
+                name,
+                ast.expr(id).span,
+                &format!("Undefined variable '{}'.", name.lexeme),
+            )
+        })
+    }
+
+    fn visit_assign(
+        &mut self,
+        ast: &Ast,
+        id: ExprId,
+        name: &Token,
+        value: ExprId,
+    ) -> Result<Value> {
+        let value = self.evaluate(ast, value)?;
+        if self.assign(&name.lexeme, value.clone()) {
+            Ok(value)
+        } else {
+            Err(RuntimeError::new(
+                name,
+                ast.expr(id).span,
+                &format!("Undefined variable '{}'.", name.lexeme),
+            ))
+        }
+    }
+
+    fn visit_list(&mut self, ast: &Ast, _id: ExprId, elements: &[ExprId]) -> Result<Value> {
+        let elements = elements
+            .iter()
+            .map(|&element| self.evaluate(ast, element))
+            .collect::<Result<Vec<_>>>()?;
+        Ok(Value::List(Rc::new(RefCell::new(elements))))
+    }
+
+    fn visit_map(
+        &mut self,
+        ast: &Ast,
+        _id: ExprId,
+        entries: &[(ExprId, Token, ExprId)],
+    ) -> Result<Value> {
+        let mut map = Map::default();
+        for (key, colon, value) in entries {
+            let span = ast.expr(*key).span;
+            let key = Key::new(&self.evaluate(ast, *key)?)
+                .map_err(|message| RuntimeError::new(colon, span, &message))?;
+            let value = self.evaluate(ast, *value)?;
+            map.insert(key, value);
+        }
+        Ok(Value::Map(Rc::new(RefCell::new(map))))
+    }
+
+    fn visit_index(
+        &mut self,
+        ast: &Ast,
+        id: ExprId,
+        object: ExprId,
+        bracket: &Token,
+        index: ExprId,
+    ) -> Result<Value> {
+        let object = self.indexable(ast, id, object, bracket)?;
+        let index = self.evaluate(ast, index)?;
+        element(&object, &index)
+            .map_err(|message| RuntimeError::new(bracket, ast.expr(id).span, &message))
+    }
+
+    fn visit_slice(
+        &mut self,
+        ast: &Ast,
+        id: ExprId,
+        object: ExprId,
+        bracket: &Token,
+        start: Option<ExprId>,
+        end: Option<ExprId>,
+    ) -> Result<Value> {
+        let Value::List(list) = self.evaluate(ast, object)? else {
+            return Err(RuntimeError::new(
+                bracket,
+                ast.expr(id).span,
+                "Only lists can be sliced.",
+            ));
+        };
+        let start = start.map(|start| self.evaluate(ast, start)).transpose()?;
+        let end = end.map(|end| self.evaluate(ast, end)).transpose()?;
+        let elements = list.borrow();
+        let (start, end) = list::slice_bounds(start.as_ref(), end.as_ref(), elements.len())
+            .map_err(|message| RuntimeError::new(bracket, ast.expr(id).span, &message))?;
+        Ok(Value::List(Rc::new(RefCell::new(
+            elements[start..end].to_vec(),
+        ))))
+    }
+
+    fn visit_index_set(
+        &mut self,
+        ast: &Ast,
+        id: ExprId,
+        object: ExprId,
+        bracket: &Token,
+        index: ExprId,
+        value: ExprId,
+    ) -> Result<Value> {
+        let object = self.indexable(ast, id, object, bracket)?;
+        let index = self.evaluate(ast, index)?;
+        let value = self.evaluate(ast, value)?;
+        set_element(&object, &index, value.clone())
+            .map_err(|message| RuntimeError::new(bracket, ast.expr(id).span, &message))?;
+        Ok(value)
+    }
+
+    fn visit_compound_assign(
+        &mut self,
+        ast: &Ast,
+        id: ExprId,
+        target: ExprId,
+        operator: &Token,
+        value: ExprId,
+    ) -> Result<Value> {
+        let place = self.place(ast, target)?;
+        let current = self.read(ast, target, &place)?;
+        let value = self.evaluate(ast, value)?;
+        let result = arithmetic(&operator.token_type, current, value)
+            .map_err(|message| RuntimeError::new(operator, ast.expr(id).span, message))?;
+        self.write(ast, target, &place, result.clone())?;
+        Ok(result)
+    }
+
+    fn visit_update(
+        &mut self,
+        ast: &Ast,
+        id: ExprId,
+        target: ExprId,
+        operator: &Token,
+        prefix: bool,
+    ) -> Result<Value> {
+        let place = self.place(ast, target)?;
+        let Value::Number(old) = self.read(ast, target, &place)? else {
+            return Err(RuntimeError::new(
+                operator,
+                ast.expr(id).span,
+                "Operand must be a number.",
+            ));
+        };
+        let new = match operator.token_type {
+            TokenType::PlusPlus => old + 1.0,
+            _ => old - 1.0,
+        };
+        self.write(ast, target, &place, Value::Number(new))?;
+        Ok(Value::Number(if prefix { new } else { old }))
+    }
+
+    fn visit_get(&mut self, ast: &Ast, id: ExprId, object: ExprId, name: &Token) -> Result<Value> {
+        let object = self.evaluate(ast, object)?;
+        let method = match &object {
+            Value::List(_) => list::method(&object, &name.lexeme),
+            Value::Map(_) => map::method(&object, &name.lexeme),
+            Value::Range(_) => range::method(&object, &name.lexeme),
+            Value::Module(module) => {
+                return module
+                    .get(&name.lexeme)
+                    .map_err(|message| RuntimeError::new(name, ast.expr(id).span, &message))
+            }
+            Value::Error(error) => match name.lexeme.as_str() {
+                "message" => Some(Value::String(error.message.as_str().into())),
+                "line" => Some(Value::Number(error.line as f64)),
+                _ => None,
+            },
+            _ => {
+                return Err(RuntimeError::new(
+                    name,
+                    ast.expr(id).span,
+                    "Only lists, maps, ranges, errors and modules have properties.",
+                ))
+            }
+        };
+        method.ok_or_else(|| {
+            RuntimeError::new(
+                name,
+                ast.expr(id).span,
+                &format!("Undefined property '{}'.", name.lexeme),
+            )
+        })
+    }
+
+    fn visit_call(
+        &mut self,
+        ast: &Ast,
+        id: ExprId,
+        callee: ExprId,
+        paren: &Token,
+        arguments: &[ExprId],
+    ) -> Result<Value> {
+        let callee = self.evaluate(ast, callee)?;
+        let arguments = arguments
+            .iter()
+            .map(|&argument| self.evaluate(ast, argument))
+            .collect::<Result<Vec<_>>>()?;
+        let span = ast.expr(id).span;
+        match callee {
+            Value::Native(method) => {
+                check_arity(&method.arity, arguments.len(), paren, span)?;
+                (method.function)(&method.receiver, arguments)
+                    .map_err(|message| RuntimeError::new(paren, span, &message))
+            }
+            Value::Function(function) => self.call(&function, arguments, paren, span),
+            _ => Err(RuntimeError::new(
+                paren,
+                span,
+                "Can only call functions and classes.",
+            )),
+        }
+    }
+
+    fn visit_function(
+        &mut self,
+        _ast: &Ast,
+        id: ExprId,
+        _params: &[Token],
+        _body: &FunctionBody,
+    ) -> Result<Value> {
+        Ok(self.closure(None, id))
+    }
+
+    fn visit_match(
+        &mut self,
+        ast: &Ast,
+        _id: ExprId,
+        keyword: &Token,
+        subject: ExprId,
+        arms: &[MatchArm<ExprId>],
+    ) -> Result<Value> {
+        self.run_match(ast, keyword, subject, arms, |interpreter, body| {
+            interpreter.evaluate(ast, *body)
+        })
+    }
+}
+
+impl StmtVisitor<Result<Flow>> for Interpreter {
+    fn visit_expression_stmt(&mut self, ast: &Ast, _id: StmtId, expr: ExprId) -> Result<Flow> {
+        self.evaluate(ast, expr)?;
+        Ok(Flow::Normal)
+    }
+
+    fn visit_print_stmt(&mut self, ast: &Ast, _id: StmtId, expr: ExprId) -> Result<Flow> {
+        let value = self.evaluate(ast, expr)?;
+        println!("{}", value);
+        Ok(Flow::Normal)
+    }
+
+    fn visit_var_stmt(
+        &mut self,
+        ast: &Ast,
+        _id: StmtId,
+        name: &Token,
+        initializer: Option<ExprId>,
+    ) -> Result<Flow> {
+        let value = match initializer {
+            Some(initializer) => self.evaluate(ast, initializer)?,
+            None => Value::Nil,
+        };
+        self.define(&name.lexeme, value);
+        Ok(Flow::Normal)
+    }
+
+    fn visit_function_stmt(
+        &mut self,
+        _ast: &Ast,
+        _id: StmtId,
+        name: &Token,
+        function: ExprId,
+    ) -> Result<Flow> {
+        // Defined after the closure is made, which still sees it: the
+        // closure shares this scope.
+        let function = self.closure(Some(name.lexeme.clone()), function);
+        self.define(&name.lexeme, function);
+        Ok(Flow::Normal)
+    }
+
+    fn visit_return_stmt(
+        &mut self,
+        ast: &Ast,
+        _id: StmtId,
+        _keyword: &Token,
+        value: Option<ExprId>,
+    ) -> Result<Flow> {
+        let value = match value {
+            Some(value) => self.evaluate(ast, value)?,
+            None => Value::Nil,
+        };
+        Ok(Flow::Return(value))
+    }
+
+    fn visit_block_stmt(&mut self, ast: &Ast, _id: StmtId, statements: &[StmtId]) -> Result<Flow> {
+        self.execute_block(ast, statements)
+    }
+
+    fn visit_for_in_stmt(
+        &mut self,
+        ast: &Ast,
+        _id: StmtId,
+        name: &Token,
+        keyword: &Token,
+        iterable: ExprId,
+        body: StmtId,
+    ) -> Result<Flow> {
+        let items = self.evaluate(ast, iterable)?.iterate().ok_or_else(|| {
+            RuntimeError::new(
+                keyword,
+                ast.expr(iterable).span,
+                "Can only iterate over lists, maps, strings and ranges.",
+            )
+        })?;
+        for item in items {
+            // Each iteration gets a fresh variable.
+            let variables = HashMap::from([(name.lexeme.clone(), item)]);
+            match self.execute_in(variables, |interpreter| ast.accept_stmt(body, interpreter))? {
+                Flow::Break => break,
+                Flow::Return(value) => return Ok(Flow::Return(value)),
+                Flow::Normal | Flow::Continue => {}
+            }
+        }
+        Ok(Flow::Normal)
+    }
+
+    fn visit_throw_stmt(
+        &mut self,
+        ast: &Ast,
+        _id: StmtId,
+        keyword: &Token,
+        value: ExprId,
+    ) -> Result<Flow> {
+        let error = match self.evaluate(ast, value)? {
+            // Rethrowing a caught error reports it where it first happened,
+            // unless that was in another file.
+            Value::Error(error) if error.file == self.file => RuntimeError {
+                message: error.message.clone(),
+                line: error.line,
+                span: error.span,
+                thrown: Some(Value::Error(error)),
+            },
+            Value::Error(error) => RuntimeError {
+                message: error.message.clone(),
+                line: keyword.line,
+                span: ast.expr(value).span,
+                thrown: Some(Value::Error(error)),
+            },
+            thrown => RuntimeError {
+                message: format!("Uncaught exception: {}", thrown),
+                line: keyword.line,
+                span: ast.expr(value).span,
+                thrown: Some(thrown),
+            },
+        };
+        Err(error)
+    }
+
+    fn visit_try_stmt(
+        &mut self,
+        ast: &Ast,
+        _id: StmtId,
+        body: StmtId,
+        catch: Option<&(Token, StmtId)>,
+        finally: Option<StmtId>,
+    ) -> Result<Flow> {
+        let result = match (ast.accept_stmt(body, self), catch) {
+            (Err(error), Some((name, handler))) => {
+                let error = error.into_value(self.file.as_deref());
+                let variables = HashMap::from([(name.lexeme.clone(), error)]);
+                self.execute_in(variables, |interpreter| {
+                    ast.accept_stmt(*handler, interpreter)
+                })
+            }
+            (result, _) => result,
+        };
+        if let Some(finally) = finally {
+            // An error, `break`, `continue` or `return` in `finally` replaces
+            // whatever the rest of the statement did.
+            match ast.accept_stmt(finally, self)? {
+                Flow::Normal => {}
+                flow => return Ok(flow),
+            }
+        }
+        result
+    }
+
+    fn visit_match_stmt(
+        &mut self,
+        ast: &Ast,
+        _id: StmtId,
+        keyword: &Token,
+        subject: ExprId,
+        arms: &[MatchArm],
+    ) -> Result<Flow> {
+        self.run_match(ast, keyword, subject, arms, |interpreter, body| {
+            ast.accept_stmt(*body, interpreter)
+        })
+    }
+
+    fn visit_import_stmt(
+        &mut self,
+        _ast: &Ast,
+        _id: StmtId,
+        path: &Token,
+        imports: &Imports,
+    ) -> Result<Flow> {
+        let module = self.import(path)?;
+        match imports {
+            Imports::Module(name) => self.define(&name.lexeme, Value::Module(module)),
+            Imports::Names(names) => {
+                for name in names {
+                    let value = module
+                        .get(&name.lexeme)
+                        .map_err(|message| RuntimeError::new(name, name.span, &message))?;
+                    self.define(&name.lexeme, value);
+                }
+            }
+        }
+        Ok(Flow::Normal)
+    }
+
+    fn visit_export_stmt(
+        &mut self,
+        ast: &Ast,
+        _id: StmtId,
+        _keyword: &Token,
+        declaration: StmtId,
+    ) -> Result<Flow> {
+        ast.accept_stmt(declaration, self)
+    }
+
+    fn visit_break_stmt(&mut self, _ast: &Ast, _id: StmtId, _keyword: &Token) -> Result<Flow> {
+        Ok(Flow::Break)
+    }
+
+    fn visit_continue_stmt(&mut self, _ast: &Ast, _id: StmtId, _keyword: &Token) -> Result<Flow> {
+        Ok(Flow::Continue)
+    }
+}
+
+/// Checks the number of arguments to a call against what the callee
+/// accepts.
+fn check_arity(
+    arity: &RangeInclusive<usize>,
+    count: usize,
+    paren: &Token,
+    span: Span,
+) -> Result<()> {
+    if arity.contains(&count) {
+        return Ok(());
+    }
+    let expected = if arity.start() == arity.end() {
+        arity.start().to_string()
+    } else {
+        format!("{} to {}", arity.start(), arity.end())
+    };
+    Err(RuntimeError::new(
+        paren,
+        span,
+        &format!("Expected {} arguments but got {}.", expected, count),
+    ))
+}
+
+/// `+ - * / %`, also in their compound assignment forms.
+fn arithmetic(
+    operator: &TokenType,
+    left: Value,
+    right: Value,
+) -> std::result::Result<Value, &'static str> {
+    use crate::tokenizer::TokenType::*;
+    let result = match operator {
+        Plus | PlusEqual => match (left, right) {
+            (Value::Number(a), Value::Number(b)) => Some(Value::Number(a + b)),
+            (Value::String(a), Value::String(b)) => {
+                Some(Value::String(format!("{}{}", a, b).into()))
+            }
+            _ => return Err("Operands must be two numbers or two strings."),
+        },
+        Minus | MinusEqual => bin_op_numeric(left, right, |a, b| a - b),
+        Star | StarEqual => bin_op_numeric(left, right, |a, b| a * b),
+        Slash | SlashEqual => bin_op_numeric(left, right, |a, b| a / b),
+        Percent | PercentEqual => bin_op_numeric(left, right, |a, b| a % b),
+        _ => unreachable!("only arithmetic operators reach here"),
+    };
+    result.ok_or("Operands must be numbers.")
+}
+
+/// `object[index]` for a list or map. Indexing a list with a range slices.
+fn element(object: &Value, index: &Value) -> std::result::Result<Value, String> {
+    match object {
+        Value::List(list) => {
+            let elements = list.borrow();
+            if let Value::Range(range) = index {
+                let selected = range.positions(elements.len());
+                let selected = selected.map(|i| elements[i].clone()).collect();
+                return Ok(Value::List(Rc::new(RefCell::new(selected))));
+            }
+            let position = list::index(index, elements.len())?;
+            Ok(elements[position].clone())
+        }
+        Value::Map(map) => map::get(&map.borrow(), index),
+        _ => unreachable!("indexable only returns lists and maps"),
+    }
+}
+
+/// `object[index] = value` for a list or map.
+fn set_element(object: &Value, index: &Value, value: Value) -> std::result::Result<(), String> {
+    match object {
+        Value::List(list) => {
+            let mut elements = list.borrow_mut();
+            let position = list::index(index, elements.len())?;
+            elements[position] = value;
+        }
+        Value::Map(map) => {
+            let key = Key::new(index)?;
+            map.borrow_mut().insert(key, value);
+        }
+        _ => unreachable!("indexable only returns lists and maps"),
+    }
+    Ok(())
+}
+
+/// Applies `op` when both operands are numbers, or returns `None`.
+fn bin_op_numeric<F: Fn(f64, f64) -> f64>(a: Value, b: Value, op: F) -> Option<Value> {
+    if let (Value::Number(a), Value::Number(b)) = (a, b) {
+        Some(Value::Number(op(a, b)))
     } else {
-        panic!("Operands must be numbers")
+        None
     }
 }
 
-fn bin_op_numeric_bool<F: Fn(f64, f64) -> bool>(
-    a: LiteralValue,
-    b: LiteralValue,
-    op: F,
-) -> LiteralValue {
-    if let (LiteralValue::Number(a), LiteralValue::Number(b)) = (a, b) {
-        LiteralValue::Bool(op(a, b))
+fn bin_op_numeric_bool<F: Fn(f64, f64) -> bool>(a: Value, b: Value, op: F) -> Option<Value> {
+    if let (Value::Number(a), Value::Number(b)) = (a, b) {
+        Some(Value::Bool(op(a, b)))
     } else {
-        panic!("Operands must be numbers")
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parser::Parser;
+    use crate::testing::{self, Rng};
+
+    /// An independent recursive-descent evaluator for arithmetic, written
+    /// from the grammar rather than the parser's operator table:
+    ///
+    /// ```text
+    /// term   -> factor ( ( "+" | "-" ) factor )*
+    /// factor -> unary ( ( "*" | "/" | "%" ) unary )*
+    /// unary  -> "-" unary | power
+    /// power  -> atom ( "**" unary )?
+    /// atom   -> NUMBER | "(" term ")"
+    /// ```
+    struct Reference<'a> {
+        tokens: Vec<&'a str>,
+        current: usize,
+    }
+
+    impl<'a> Reference<'a> {
+        fn evaluate(source: &'a str) -> f64 {
+            let mut tokens = Vec::new();
+            let mut rest = source.trim_start();
+            while !rest.is_empty() {
+                let len = if rest.starts_with("**") {
+                    2
+                } else if rest.starts_with(|c: char| c.is_ascii_digit()) {
+                    rest.find(|c: char| !c.is_ascii_digit() && c != '.')
+                        .unwrap_or(rest.len())
+                } else {
+                    1
+                };
+                tokens.push(&rest[..len]);
+                rest = rest[len..].trim_start();
+            }
+            let mut reference = Reference { tokens, current: 0 };
+            let value = reference.term();
+            assert_eq!(reference.current, reference.tokens.len(), "{}", source);
+            value
+        }
+
+        fn peek(&self) -> Option<&'a str> {
+            self.tokens.get(self.current).copied()
+        }
+
+        fn next(&mut self) -> &'a str {
+            self.current += 1;
+            self.tokens[self.current - 1]
+        }
+
+        fn term(&mut self) -> f64 {
+            let mut value = self.factor();
+            while let Some(operator @ ("+" | "-")) = self.peek() {
+                self.next();
+                let right = self.factor();
+                value = if operator == "+" {
+                    value + right
+                } else {
+                    value - right
+                };
+            }
+            value
+        }
+
+        fn factor(&mut self) -> f64 {
+            let mut value = self.unary();
+            while let Some(operator @ ("*" | "/" | "%")) = self.peek() {
+                self.next();
+                let right = self.unary();
+                value = match operator {
+                    "*" => value * right,
+                    "/" => value / right,
+                    _ => value % right,
+                };
+            }
+            value
+        }
+
+        fn unary(&mut self) -> f64 {
+            if self.peek() == Some("-") {
+                self.next();
+                return -self.unary();
+            }
+            self.power()
+        }
+
+        fn power(&mut self) -> f64 {
+            let base = self.atom();
+            if self.peek() == Some("**") {
+                self.next();
+                return base.powf(self.unary());
+            }
+            base
+        }
+
+        fn atom(&mut self) -> f64 {
+            let token = self.next();
+            if token == "(" {
+                let value = self.term();
+                assert_eq!(self.next(), ")");
+                value
+            } else {
+                token.parse().unwrap()
+            }
+        }
+    }
+
+    #[test]
+    fn evaluate_agrees_with_reference() {
+        let mut rng = Rng::new(0xbf58_476d_1ce4_e5b9);
+        for _ in 0..3000 {
+            let depth = 1 + rng.below(5);
+            let source = testing::arithmetic(&mut rng, depth);
+            let expected = Reference::evaluate(&source);
+
+            let mut parser = Parser::new(&source);
+            let expr = parser
+                .parse()
+                .unwrap_or_else(|e| panic!("{}\nwhile parsing: {}", e, source));
+            let ast = parser.into_ast();
+            let actual = match Interpreter::new().evaluate(&ast, expr) {
+                Ok(Value::Number(n)) => n,
+                other => panic!("{} evaluated to {:?}", source, other),
+            };
+            assert!(
+                actual == expected || (actual.is_nan() && expected.is_nan()),
+                "{} = {}, expected {}",
+                source,
+                actual,
+                expected
+            );
+        }
     }
 }
@@ -0,0 +1,452 @@
+// json.rs
+use std::fmt::{self, Write};
+
+/// A minimal JSON document model, enough for exporting ASTs.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Json {
+    Null,
+    Bool(bool),
+    Number(f64),
+    String(String),
+    Array(Vec<Json>),
+    /// Keys keep their insertion order.
+    Object(Vec<(String, Json)>),
+}
+
+impl Json {
+    fn is_scalar(&self) -> bool {
+        !matches!(self, Json::Array(_) | Json::Object(_))
+    }
+
+    /// Renders the value with two-space indentation.
+    pub fn to_pretty(&self) -> String {
+        let mut out = String::new();
+        self.write(&mut out, Some("  "), 0);
+        out
+    }
+
+    fn write(&self, out: &mut String, indent: Option<&str>, depth: usize) {
+        let newline = |out: &mut String, depth: usize| {
+            if let Some(indent) = indent {
+                out.push('\n');
+                out.push_str(&indent.repeat(depth));
+            }
+        };
+        match self {
+            Json::Null => out.push_str("null"),
+            Json::Bool(b) => {
+                let _ = write!(out, "{}", b);
+            }
+            Json::Number(n) if n.is_finite() => {
+                let _ = write!(out, "{}", n);
+            }
+            // JSON has no NaN or infinities.
+            Json::Number(_) => out.push_str("null"),
+            Json::String(s) => write_string(out, s),
+            Json::Array(items) if items.is_empty() => out.push_str("[]"),
+            // Keep short lists of scalars such as spans on one line.
+            Json::Array(items) if indent.is_some() && items.iter().all(Json::is_scalar) => {
+                self.write(out, None, depth)
+            }
+            Json::Array(items) => {
+                out.push('[');
+                for (i, item) in items.iter().enumerate() {
+                    if i > 0 {
+                        out.push(',');
+                        if indent.is_none() {
+                            out.push(' ');
+                        }
+                    }
+                    newline(out, depth + 1);
+                    item.write(out, indent, depth + 1);
+                }
+                newline(out, depth);
+                out.push(']');
+            }
+            Json::Object(entries) if entries.is_empty() => out.push_str("{}"),
+            Json::Object(entries) => {
+                out.push('{');
+                for (i, (key, value)) in entries.iter().enumerate() {
+                    if i > 0 {
+                        out.push(',');
+                        if indent.is_none() {
+                            out.push(' ');
+                        }
+                    }
+                    newline(out, depth + 1);
+                    write_string(out, key);
+                    out.push_str(": ");
+                    value.write(out, indent, depth + 1);
+                }
+                newline(out, depth);
+                out.push('}');
+            }
+        }
+    }
+}
+
+/// Renders the value on one line.
+impl fmt::Display for Json {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let mut out = String::new();
+        self.write(&mut out, None, 0);
+        f.write_str(&out)
+    }
+}
+
+fn write_string(out: &mut String, s: &str) {
+    out.push('"');
+    for c in s.chars() {
+        match c {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            '\n' => out.push_str("\\n"),
+            '\r' => out.push_str("\\r"),
+            '\t' => out.push_str("\\t"),
+            c if (c as u32) < 0x20 => {
+                let _ = write!(out, "\\u{:04x}", c as u32);
+            }
+            c => out.push(c),
+        }
+    }
+    out.push('"');
+}
+
+impl Json {
+    /// Parses a complete JSON document.
+    pub fn parse(text: &str) -> Result<Json, String> {
+        let mut parser = JsonParser {
+            text,
+            pos: 0,
+            depth: 0,
+        };
+        parser.skip_whitespace();
+        let value = parser.value()?;
+        parser.skip_whitespace();
+        if parser.pos < text.len() {
+            return Err(parser.error("Unexpected trailing characters"));
+        }
+        Ok(value)
+    }
+
+    /// Looks up `key` in an object. Returns `None` for other values.
+    pub fn get(&self, key: &str) -> Option<&Json> {
+        match self {
+            Json::Object(entries) => entries
+                .iter()
+                .find(|(entry, _)| entry == key)
+                .map(|(_, value)| value),
+            _ => None,
+        }
+    }
+
+    /// The JSON type name, for error messages.
+    pub fn type_name(&self) -> &'static str {
+        match self {
+            Json::Null => "null",
+            Json::Bool(_) => "boolean",
+            Json::Number(_) => "number",
+            Json::String(_) => "string",
+            Json::Array(_) => "array",
+            Json::Object(_) => "object",
+        }
+    }
+}
+
+/// How deeply arrays and objects may nest. The parser recurses once per
+/// level, so without a limit a long run of `[` overflows the stack.
+const MAX_DEPTH: usize = 512;
+
+struct JsonParser<'a> {
+    text: &'a str,
+    pos: usize,
+    /// Arrays and objects open at `pos`.
+    depth: usize,
+}
+
+impl JsonParser<'_> {
+    fn value(&mut self) -> Result<Json, String> {
+        match self.peek() {
+            Some('{' | '[') => {
+                if self.depth == MAX_DEPTH {
+                    let message = format!("Nesting is deeper than {} levels", MAX_DEPTH);
+                    return Err(self.error(&message));
+                }
+                self.depth += 1;
+                let value = if self.peek() == Some('{') {
+                    self.object()
+                } else {
+                    self.array()
+                };
+                self.depth -= 1;
+                value
+            }
+            Some('"') => Ok(Json::String(self.string()?)),
+            Some('t') => self.keyword("true", Json::Bool(true)),
+            Some('f') => self.keyword("false", Json::Bool(false)),
+            Some('n') => self.keyword("null", Json::Null),
+            Some(c) if c == '-' || c.is_ascii_digit() => self.number(),
+            Some(_) => Err(self.error("Expected a JSON value")),
+            None => Err(self.error("Unexpected end of input")),
+        }
+    }
+
+    fn object(&mut self) -> Result<Json, String> {
+        self.expect('{')?;
+        let mut entries = Vec::new();
+        self.skip_whitespace();
+        if self.eat('}') {
+            return Ok(Json::Object(entries));
+        }
+        loop {
+            self.skip_whitespace();
+            if self.peek() != Some('"') {
+                return Err(self.error("Expected a string key"));
+            }
+            let key = self.string()?;
+            self.skip_whitespace();
+            self.expect(':')?;
+            self.skip_whitespace();
+            entries.push((key, self.value()?));
+            self.skip_whitespace();
+            if self.eat('}') {
+                return Ok(Json::Object(entries));
+            }
+            self.expect(',')?;
+        }
+    }
+
+    fn array(&mut self) -> Result<Json, String> {
+        self.expect('[')?;
+        let mut items = Vec::new();
+        self.skip_whitespace();
+        if self.eat(']') {
+            return Ok(Json::Array(items));
+        }
+        loop {
+            self.skip_whitespace();
+            items.push(self.value()?);
+            self.skip_whitespace();
+            if self.eat(']') {
+                return Ok(Json::Array(items));
+            }
+            self.expect(',')?;
+        }
+    }
+
+    fn string(&mut self) -> Result<String, String> {
+        self.expect('"')?;
+        let mut out = String::new();
+        loop {
+            match self.next() {
+                None => return Err(self.error("Unterminated string")),
+                Some('"') => return Ok(out),
+                Some('\\') => match self.next() {
+                    Some('"') => out.push('"'),
+                    Some('\\') => out.push('\\'),
+                    Some('/') => out.push('/'),
+                    Some('b') => out.push('\u{8}'),
+                    Some('f') => out.push('\u{c}'),
+                    Some('n') => out.push('\n'),
+                    Some('r') => out.push('\r'),
+                    Some('t') => out.push('\t'),
+                    Some('u') => out.push(self.unicode_escape()?),
+                    _ => return Err(self.error("Invalid escape sequence")),
+                },
+                Some(c) if (c as u32) < 0x20 => {
+                    return Err(self.error("Control character in string"))
+                }
+                Some(c) => out.push(c),
+            }
+        }
+    }
+
+    /// Decodes the digits after `\u`, joining surrogate pairs.
+    fn unicode_escape(&mut self) -> Result<char, String> {
+        let high = self.hex4()?;
+        let code = if (0xD800..0xDC00).contains(&high) {
+            if !(self.eat('\\') && self.eat('u')) {
+                return Err(self.error("Unpaired surrogate in string"));
+            }
+            let low = self.hex4()?;
+            if !(0xDC00..0xE000).contains(&low) {
+                return Err(self.error("Unpaired surrogate in string"));
+            }
+            0x10000 + ((high - 0xD800) << 10) + (low - 0xDC00)
+        } else {
+            high
+        };
+        char::from_u32(code).ok_or_else(|| self.error("Invalid unicode escape"))
+    }
+
+    fn hex4(&mut self) -> Result<u32, String> {
+        let digits = self.text.get(self.pos..self.pos + 4).unwrap_or("");
+        let value = u32::from_str_radix(digits, 16)
+            .ok()
+            .filter(|_| digits.chars().all(|c| c.is_ascii_hexdigit()))
+            .ok_or_else(|| self.error("Invalid unicode escape"))?;
+        self.pos += 4;
+        Ok(value)
+    }
+
+    fn number(&mut self) -> Result<Json, String> {
+        let start = self.pos;
+        self.eat('-');
+        if !self.eat('0') && !self.digits() {
+            return Err(self.error("Invalid number"));
+        }
+        if self.eat('.') && !self.digits() {
+            return Err(self.error("Invalid number"));
+        }
+        if self.eat('e') || self.eat('E') {
+            if !self.eat('+') {
+                self.eat('-');
+            }
+            if !self.digits() {
+                return Err(self.error("Invalid number"));
+            }
+        }
+        match self.text[start..self.pos].parse::<f64>() {
+            Ok(n) if n.is_finite() => Ok(Json::Number(n)),
+            Ok(_) => Err(self.error_at(start, "Number out of range")),
+            Err(_) => Err(self.error("Invalid number")),
+        }
+    }
+
+    fn digits(&mut self) -> bool {
+        let start = self.pos;
+        while self.peek().is_some_and(|c| c.is_ascii_digit()) {
+            self.pos += 1;
+        }
+        self.pos > start
+    }
+
+    fn keyword(&mut self, word: &str, value: Json) -> Result<Json, String> {
+        if self.text[self.pos..].starts_with(word) {
+            self.pos += word.len();
+            Ok(value)
+        } else {
+            Err(self.error("Expected a JSON value"))
+        }
+    }
+
+    fn skip_whitespace(&mut self) {
+        while let Some(' ' | '\t' | '\n' | '\r') = self.peek() {
+            self.pos += 1;
+        }
+    }
+
+    fn expect(&mut self, c: char) -> Result<(), String> {
+        if self.eat(c) {
+            Ok(())
+        } else {
+            Err(self.error(&format!("Expected '{}'", c)))
+        }
+    }
+
+    fn eat(&mut self, c: char) -> bool {
+        if self.peek() == Some(c) {
+            self.pos += c.len_utf8();
+            true
+        } else {
+            false
+        }
+    }
+
+    fn peek(&self) -> Option<char> {
+        self.text[self.pos..].chars().next()
+    }
+
+    fn next(&mut self) -> Option<char> {
+        let c = self.peek()?;
+        self.pos += c.len_utf8();
+        Some(c)
+    }
+
+    fn error(&self, message: &str) -> String {
+        self.error_at(self.pos, message)
+    }
+
+    fn error_at(&self, pos: usize, message: &str) -> String {
+        let before = &self.text[..pos];
+        let line = before.matches('\n').count() + 1;
+        let column = before.rsplit('\n').next().unwrap_or("").chars().count() + 1;
+        format!(
+            "Invalid JSON at line {}, column {}: {}.",
+            line, column, message
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_every_kind_of_value() {
+        let json = Json::parse(r#" {"a": [1, -2.5e1, true, false, null], "b": "xé\n"} "#);
+        assert_eq!(
+            json,
+            Ok(Json::Object(vec![
+                (
+                    "a".to_string(),
+                    Json::Array(vec![
+                        Json::Number(1.0),
+                        Json::Number(-25.0),
+                        Json::Bool(true),
+                        Json::Bool(false),
+                        Json::Null,
+                    ])
+                ),
+                ("b".to_string(), Json::String("xé\n".to_string())),
+            ]))
+        );
+    }
+
+    #[test]
+    fn reports_where_malformed_input_goes_wrong() {
+        let cases = [
+            (r#"{"a" 1}"#, "line 1, column 6: Expected ':'"),
+            ("[1,]", "line 1, column 4: Expected a JSON value"),
+            (
+                "[1]\n  x",
+                "line 2, column 3: Unexpected trailing characters",
+            ),
+            ("\n\"abc", "line 2, column 5: Unterminated string"),
+            ("01", "line 1, column 2: Unexpected trailing characters"),
+            ("", "line 1, column 1: Unexpected end of input"),
+        ];
+        for (text, expected) in cases {
+            assert_eq!(
+                Json::parse(text),
+                Err(format!("Invalid JSON at {}.", expected)),
+                "{:?}",
+                text
+            );
+        }
+    }
+
+    #[test]
+    fn limits_nesting_depth() {
+        let deepest = format!("{}{}", "[".repeat(MAX_DEPTH), "]".repeat(MAX_DEPTH));
+        assert!(Json::parse(&deepest).is_ok());
+
+        let too_deep = "[".repeat(200_000);
+        assert_eq!(
+            Json::parse(&too_deep),
+            Err(format!(
+                "Invalid JSON at line 1, column {}: Nesting is deeper than {} levels.",
+                MAX_DEPTH + 1,
+                MAX_DEPTH
+            ))
+        );
+    }
+
+    #[test]
+    fn rejects_numbers_out_of_range() {
+        assert_eq!(
+            Json::parse("[1, -1e400]"),
+            Err("Invalid JSON at line 1, column 5: Number out of range.".to_string())
+        );
+    }
+}
@@ -0,0 +1,113 @@
+// list.rs
+use crate::value::{self, NativeFn, Value};
+use std::cell::RefCell;
+
+/// The methods lists have, by name, with their arity.
+const METHODS: &[(&str, usize, NativeFn)] = &[
+    ("push", 1, push),
+    ("pop", 0, pop),
+    ("insert", 2, insert),
+    ("remove", 1, remove),
+    ("len", 0, len),
+    ("contains", 1, contains),
+    ("reverse", 0, reverse),
+];
+
+/// Looks up `name` on a list and binds it to the list.
+pub fn method(list: &Value, name: &str) -> Option<Value> {
+    value::bind(METHODS, list, name)
+}
+
+/// Resolves `index` to a position in a list of `len` elements. Indexes
+/// must be integers; negative ones count from the end.
+pub fn index(index: &Value, len: usize) -> Result<usize, String> {
+    let position = from_end(integer(index)?, len);
+    if position < 0.0 || position >= len as f64 {
+        return Err("List index out of range.".to_string());
+    }
+    Ok(position as usize)
+}
+
+/// Resolves the bounds of `list[start:end]`. Missing bounds mean the ends of
+/// the list, negative ones count from the end, and out-of-range ones are
+/// clamped, so slicing never fails on range.
+pub fn slice_bounds(
+    start: Option<&Value>,
+    end: Option<&Value>,
+    len: usize,
+) -> Result<(usize, usize), String> {
+    let bound = |bound: Option<&Value>, default: usize| -> Result<usize, String> {
+        let Some(bound) = bound else {
+            return Ok(default);
+        };
+        let position = from_end(integer(bound)?, len);
+        Ok(position.clamp(0.0, len as f64) as usize)
+    };
+    let start = bound(start, 0)?;
+    let end = bound(end, len)?;
+    Ok((start, end.max(start)))
+}
+
+fn integer(value: &Value) -> Result<f64, String> {
+    match value {
+        Value::Number(n) if n.fract() == 0.0 => Ok(*n),
+        _ => Err("List index must be an integer.".to_string()),
+    }
+}
+
+/// Maps a negative position to one counted from the end.
+fn from_end(position: f64, len: usize) -> f64 {
+    if position < 0.0 {
+        position + len as f64
+    } else {
+        position
+    }
+}
+
+fn elements(list: &Value) -> &RefCell<Vec<Value>> {
+    match list {
+        Value::List(elements) => elements,
+        _ => unreachable!("list methods are only bound to lists"),
+    }
+}
+
+fn push(list: &Value, mut arguments: Vec<Value>) -> Result<Value, String> {
+    elements(list).borrow_mut().push(arguments.remove(0));
+    Ok(Value::Nil)
+}
+
+fn pop(list: &Value, _arguments: Vec<Value>) -> Result<Value, String> {
+    elements(list)
+        .borrow_mut()
+        .pop()
+        .ok_or_else(|| "Can't pop from an empty list.".to_string())
+}
+
+fn insert(list: &Value, mut arguments: Vec<Value>) -> Result<Value, String> {
+    let value = arguments.pop().unwrap();
+    let mut elements = elements(list).borrow_mut();
+    // Inserting at the length appends.
+    let position = index(&arguments[0], elements.len() + 1)?;
+    elements.insert(position, value);
+    Ok(Value::Nil)
+}
+
+fn remove(list: &Value, arguments: Vec<Value>) -> Result<Value, String> {
+    let mut elements = elements(list).borrow_mut();
+    let position = index(&arguments[0], elements.len())?;
+    Ok(elements.remove(position))
+}
+
+fn len(list: &Value, _arguments: Vec<Value>) -> Result<Value, String> {
+    Ok(Value::Number(elements(list).borrow().len() as f64))
+}
+
+fn contains(list: &Value, arguments: Vec<Value>) -> Result<Value, String> {
+    let found = elements(list).borrow().contains(&arguments[0]);
+    Ok(Value::Bool(found))
+}
+
+fn reverse(list: &Value, _arguments: Vec<Value>) -> Result<Value, String> {
+    elements(list).borrow_mut().reverse();
+    Ok(Value::Nil)
+}
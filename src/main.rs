@@ -1,29 +1,78 @@
 mod ast;
+mod ast_export;
+mod ast_import;
 mod ast_printer;
+mod cst;
+mod formatter;
 mod interpreter;
+mod json;
+mod list;
+mod map;
+mod module;
 mod parser;
+mod range;
+mod test_runner;
+#[cfg(test)]
+mod testing;
 mod tokenizer;
+mod unparser;
+mod value;
 
-use crate::ast_printer::AstPrinter;
+use crate::ast::Root;
+use crate::ast_export::Format;
 use crate::interpreter::Interpreter;
+use crate::interpreter::RuntimeError;
 use crate::parser::Parser;
-use crate::tokenizer::{Scanner, TokenType};
+use crate::tokenizer::{Scanner, Span, TokenType};
 use std::env;
 use std::fs;
-use std::io::{self, Write};
+use std::path::Path;
+use std::rc::Rc;
+use std::thread;
+
+/// The native stack for the thread that runs the command: room for
+/// `MAX_CALL_DEPTH` nested calls, far more than the main thread has.
+const STACK_SIZE: usize = 512 * 1024 * 1024;
 
 fn main() {
+    let command = thread::Builder::new()
+        .stack_size(STACK_SIZE)
+        .spawn(run_command)
+        .expect("failed to start the interpreter thread");
+    if command.join().is_err() {
+        // The panic message has already been printed.
+        std::process::exit(101);
+    }
+}
+
+fn run_command() {
     let args: Vec<String> = env::args().collect();
     if args.len() < 3 {
-        writeln!(io::stderr(), "Usage: {} <command> <filename>", args[0]).unwrap();
+        eprintln!("Usage: {} <command> [options] <filename>", args[0]);
         return;
     }
 
     let command = &args[1];
-    let filename = &args[2];
+    let (options, operands): (Vec<&String>, Vec<&String>) =
+        args[2..].iter().partition(|arg| arg.starts_with("--"));
+    let Some(filename) = operands.first() else {
+        eprintln!("Usage: {} <command> [options] <filename>", args[0]);
+        return;
+    };
+    let has_option = |name: &str| options.iter().any(|option| *option == name);
+    let option_value = |name: &str| {
+        options
+            .iter()
+            .find_map(|option| option.strip_prefix(name)?.strip_prefix('='))
+    };
+
+    if command == "test" {
+        let passed = test_runner::run(Path::new(filename.as_str()));
+        std::process::exit(if passed { 0 } else { 1 });
+    }
 
     let file_contents = fs::read_to_string(filename).unwrap_or_else(|_| {
-        writeln!(io::stderr(), "Failed to read file {}", filename).unwrap();
+        eprintln!("Failed to read file {}", filename);
         String::new()
     });
 
@@ -32,13 +81,13 @@ fn main() {
             let mut scanner = Scanner::new(&file_contents);
             let tokens = scanner.scan_tokens();
 
-            for (token, lexeme, literal) in tokens {
-                match token {
-                    TokenType::String => println!("STRING {} {}", lexeme, literal),
-                    TokenType::Number => println!("NUMBER {} {}", lexeme, literal),
-                    TokenType::Identifier => println!("IDENTIFIER {} null", lexeme),
+            for token in tokens {
+                match token.token_type {
+                    TokenType::String => println!("STRING {} {}", token.lexeme, token.literal),
+                    TokenType::Number => println!("NUMBER {} {}", token.lexeme, token.literal),
+                    TokenType::Identifier => println!("IDENTIFIER {} null", token.lexeme),
                     TokenType::Eof => println!("EOF  null"),
-                    _ => println!("{}", token),
+                    _ => println!("{}", token.token_type),
                 }
             }
 
@@ -47,13 +96,26 @@ fn main() {
             }
         }
         "parse" => {
-            let mut parser = Parser::new(&file_contents);
-            match parser.parse() {
-                Ok(expr) => {
-                    println!("{}", AstPrinter::print(&expr));
-                }
+            let format = match option_value("--format") {
+                None => Format::Sexpr,
+                Some(name) => Format::from_name(name).unwrap_or_else(|| {
+                    eprintln!(
+                        "Unknown format: {} (expected sexpr, json, dot, tree or lox)",
+                        name
+                    );
+                    std::process::exit(64);
+                }),
+            };
+            let parsed = if has_option("--from-json") {
+                ast_import::import(&file_contents)
+            } else {
+                let mut parser = Parser::new(&file_contents);
+                parser.parse_root().map(|root| (parser.into_ast(), root))
+            };
+            match parsed.and_then(|(ast, root)| ast_export::export(&ast, &root, format)) {
+                Ok(output) => print!("{}", output),
                 Err(e) => {
-                    writeln!(io::stderr(), "Error: {}", e).unwrap();
+                    eprintln!("{}", e);
                     std::process::exit(65);
                 }
             }
@@ -62,18 +124,121 @@ fn main() {
             let mut parser = Parser::new(&file_contents);
             match parser.parse() {
                 Ok(expr) => {
-                    let interpreter = Interpreter;
-                    let result = interpreter.evaluate(expr);
-                    println!("{}", result);
+                    let ast = Rc::new(parser.into_ast());
+                    let mut interpreter = Interpreter::new();
+                    match interpreter.evaluate_expression(ast, expr) {
+                        Ok(result) => println!("{}", result),
+                        Err(e) => report_runtime_error(Some(&file_contents), &e),
+                    }
+                }
+                Err(e) => {
+                    eprintln!("{}", e);
+                    std::process::exit(65);
+                }
+            }
+        }
+        "run" => {
+            let from_json = has_option("--ast");
+            let parsed = if from_json {
+                ast_import::import(&file_contents).and_then(|(ast, root)| match root {
+                    Root::Program(statements) => Ok((ast, statements)),
+                    Root::Expression(_) => {
+                        Err("Invalid AST at $: expected a \"Program\" node.".to_string())
+                    }
+                })
+            } else {
+                let mut parser = Parser::new(&file_contents);
+                let parsed = parser.parse_program();
+                if parser.has_scan_errors {
+                    if let Err(e) = parsed {
+                        eprintln!("{}", e);
+                    }
+                    std::process::exit(65);
+                }
+                parsed.map(|statements| (parser.into_ast(), statements))
+            };
+            let path = Path::new(filename.as_str());
+            let parsed = parsed.and_then(|(ast, statements)| {
+                module::check_imports(&ast, &statements, Some(path)).map(|()| (ast, statements))
+            });
+            match parsed {
+                Ok((ast, statements)) => {
+                    let mut interpreter = Interpreter::for_file(path);
+                    if let Err(e) = interpreter.interpret(Rc::new(ast), &statements) {
+                        // Spans in an imported AST don't point into this file.
+                        let source = (!from_json).then_some(file_contents.as_str());
+                        report_runtime_error(source, &e);
+                    }
                 }
                 Err(e) => {
-                    writeln!(io::stderr(), "Error: {}", e).unwrap();
+                    eprintln!("{}", e);
                     std::process::exit(65);
                 }
             }
         }
+        "cst" => match cst::parse_lossless(&file_contents) {
+            Ok(tree) => {
+                if has_option("--text") {
+                    print!("{}", tree);
+                } else {
+                    print!("{}", tree.dump());
+                }
+            }
+            Err(e) => {
+                eprintln!("{}", e);
+                std::process::exit(65);
+            }
+        },
+        "fmt" => match formatter::format_source(&file_contents) {
+            Ok(formatted) => {
+                if has_option("--check") {
+                    if formatted != file_contents {
+                        eprintln!("Would reformat {}", filename);
+                        std::process::exit(1);
+                    }
+                } else if has_option("--write") {
+                    if formatted != file_contents {
+                        fs::write(filename, formatted).unwrap_or_else(|e| {
+                            eprintln!("Failed to write file {}: {}", filename, e);
+                            std::process::exit(74);
+                        });
+                    }
+                } else {
+                    print!("{}", formatted);
+                }
+            }
+            Err(e) => {
+                eprintln!("{}", e);
+                std::process::exit(65);
+            }
+        },
         _ => {
-            writeln!(io::stderr(), "Unknown command: {}", command).unwrap();
+            eprintln!("Unknown command: {}", command);
         }
     }
 }
+
+/// Prints a runtime error followed by the offending source line with the
+/// failing sub-expression underlined, then exits.
+fn report_runtime_error(source: Option<&str>, error: &RuntimeError) -> ! {
+    eprintln!("{}", error);
+    if let Some((text, indent, width)) = source.and_then(|source| excerpt(source, error.span)) {
+        eprintln!("    {}", text);
+        eprintln!("    {}{}", " ".repeat(indent), "^".repeat(width));
+    }
+    std::process::exit(70);
+}
+
+/// The line of `source` that `span` starts on, with the column and width to
+/// underline, or `None` if the span doesn't fit in `source`.
+fn excerpt(source: &str, span: Span) -> Option<(&str, usize, usize)> {
+    let line_start = source.get(..span.start)?.rfind('\n').map_or(0, |i| i + 1);
+    let line_end = source[line_start..]
+        .find('\n')
+        .map_or(source.len(), |i| line_start + i);
+    let end = span.end.min(line_end).max(span.start);
+    let text = &source[line_start..line_end];
+    let indent = source[line_start..span.start].chars().count();
+    let width = source.get(span.start..end)?.chars().count().max(1);
+    Some((text, indent, width))
+}
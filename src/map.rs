@@ -0,0 +1,157 @@
+// map.rs
+use crate::value::{self, NativeFn, Value};
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::rc::Rc;
+
+/// A map key. Only values with a stable notion of equality can be keys:
+/// strings, numbers, booleans and nil. `-0` is the same key as `0`, since
+/// the two are equal; `NaN` is equal to nothing, so it can't be a key.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum Key {
+    Nil,
+    Bool(bool),
+    /// The bits of the number, with `-0` stored as `0`.
+    Number(u64),
+    String(Rc<str>),
+}
+
+impl Key {
+    pub fn new(value: &Value) -> Result<Key, String> {
+        match value {
+            Value::Nil => Ok(Key::Nil),
+            Value::Bool(b) => Ok(Key::Bool(*b)),
+            Value::Number(n) if n.is_nan() => Err("NaN can't be used as a map key.".to_string()),
+            // `0.0 == -0.0`, so this also normalizes `-0`.
+            Value::Number(n) if *n == 0.0 => Ok(Key::Number(0f64.to_bits())),
+            Value::Number(n) => Ok(Key::Number(n.to_bits())),
+            Value::String(s) => Ok(Key::String(s.clone())),
+            _ => Err("Map keys must be strings, numbers, booleans or nil.".to_string()),
+        }
+    }
+
+    pub fn to_value(&self) -> Value {
+        match self {
+            Key::Nil => Value::Nil,
+            Key::Bool(b) => Value::Bool(*b),
+            Key::Number(bits) => Value::Number(f64::from_bits(*bits)),
+            Key::String(s) => Value::String(s.clone()),
+        }
+    }
+}
+
+/// A hash map that remembers insertion order. Updating an existing key
+/// keeps its position; removing a key and adding it again moves it to the
+/// end.
+#[derive(Debug, Default)]
+pub struct Map {
+    entries: Vec<(Key, Value)>,
+    /// Where each key is in `entries`.
+    positions: HashMap<Key, usize>,
+}
+
+impl Map {
+    pub fn get(&self, key: &Key) -> Option<&Value> {
+        self.positions.get(key).map(|&i| &self.entries[i].1)
+    }
+
+    pub fn insert(&mut self, key: Key, value: Value) {
+        match self.positions.get(&key) {
+            Some(&i) => self.entries[i].1 = value,
+            None => {
+                self.positions.insert(key.clone(), self.entries.len());
+                self.entries.push((key, value));
+            }
+        }
+    }
+
+    pub fn remove(&mut self, key: &Key) -> Option<Value> {
+        let i = self.positions.remove(key)?;
+        let (_, value) = self.entries.remove(i);
+        for (key, _) in &self.entries[i..] {
+            *self.positions.get_mut(key).unwrap() -= 1;
+        }
+        Some(value)
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    /// Entries in insertion order.
+    pub fn iter(&self) -> impl Iterator<Item = (&Key, &Value)> {
+        self.entries.iter().map(|(key, value)| (key, value))
+    }
+}
+
+/// The methods maps have, by name, with their arity.
+const METHODS: &[(&str, usize, NativeFn)] = &[
+    ("keys", 0, keys),
+    ("values", 0, values),
+    ("has", 1, has),
+    ("remove", 1, remove),
+    ("len", 0, len),
+];
+
+/// Looks up `name` on a map and binds it to the map.
+pub fn method(map: &Value, name: &str) -> Option<Value> {
+    value::bind(METHODS, map, name)
+}
+
+/// The value stored under `key`, or an error naming the missing key.
+pub fn get(map: &Map, key: &Value) -> Result<Value, String> {
+    map.get(&Key::new(key)?)
+        .cloned()
+        .ok_or_else(|| undefined_key(key))
+}
+
+fn undefined_key(key: &Value) -> String {
+    match key {
+        Value::String(s) => format!("Undefined key \"{}\".", s),
+        _ => format!("Undefined key {}.", key),
+    }
+}
+
+fn entries(map: &Value) -> &RefCell<Map> {
+    match map {
+        Value::Map(entries) => entries,
+        _ => unreachable!("map methods are only bound to maps"),
+    }
+}
+
+fn list(values: Vec<Value>) -> Value {
+    Value::List(Rc::new(RefCell::new(values)))
+}
+
+fn keys(map: &Value, _arguments: Vec<Value>) -> Result<Value, String> {
+    let keys = entries(map)
+        .borrow()
+        .iter()
+        .map(|(key, _)| key.to_value())
+        .collect();
+    Ok(list(keys))
+}
+
+fn values(map: &Value, _arguments: Vec<Value>) -> Result<Value, String> {
+    let values = entries(map)
+        .borrow()
+        .iter()
+        .map(|(_, value)| value.clone())
+        .collect();
+    Ok(list(values))
+}
+
+fn has(map: &Value, arguments: Vec<Value>) -> Result<Value, String> {
+    let key = Key::new(&arguments[0])?;
+    Ok(Value::Bool(entries(map).borrow().get(&key).is_some()))
+}
+
+fn remove(map: &Value, arguments: Vec<Value>) -> Result<Value, String> {
+    let key = Key::new(&arguments[0])?;
+    let removed = entries(map).borrow_mut().remove(&key);
+    removed.ok_or_else(|| undefined_key(&arguments[0]))
+}
+
+fn len(map: &Value, _arguments: Vec<Value>) -> Result<Value, String> {
+    Ok(Value::Number(entries(map).borrow().len() as f64))
+}
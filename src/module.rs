@@ -0,0 +1,174 @@
+// module.rs
+use crate::ast::{Ast, ExprKind, FunctionBody, Imports, StmtId, StmtKind};
+use crate::parser::Parser;
+use crate::tokenizer::Token;
+use crate::value::Value;
+use std::collections::{HashMap, HashSet};
+use std::env;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// The exported variables of an imported file. A module runs once, and
+/// every file that imports it shares the result.
+#[derive(Debug)]
+pub struct Module {
+    /// The file name without its extension, e.g. `util` for `lib/util.lox`.
+    pub name: String,
+    pub variables: HashMap<String, Value>,
+}
+
+impl Module {
+    pub fn new(path: &Path, variables: HashMap<String, Value>) -> Self {
+        Module {
+            name: module_name(path),
+            variables,
+        }
+    }
+
+    /// The variable called `name`, or an error naming the module.
+    pub fn get(&self, name: &str) -> Result<Value, String> {
+        self.variables
+            .get(name)
+            .cloned()
+            .ok_or_else(|| not_exported(&self.name, name))
+    }
+}
+
+fn module_name(path: &Path) -> String {
+    path.file_stem()
+        .map_or_else(String::new, |stem| stem.to_string_lossy().into_owned())
+}
+
+fn not_exported(module: &str, name: &str) -> String {
+    format!("Module \"{}\" does not export '{}'.", module, name)
+}
+
+/// The names that a file's top-level `export` declarations define.
+pub fn exports(ast: &Ast, statements: &[StmtId]) -> HashSet<String> {
+    statements
+        .iter()
+        .filter_map(|&stmt| match &ast.stmt(stmt).kind {
+            StmtKind::Export(_, declaration) => match &ast.stmt(*declaration).kind {
+                StmtKind::Var(name, _) | StmtKind::Function(name, _) => Some(name.lexeme.clone()),
+                _ => None,
+            },
+            _ => None,
+        })
+        .collect()
+}
+
+/// Checks, before `file` runs, that every module it imports parses and that
+/// `from ... import` only names exported variables. Errors read like parse
+/// errors in `file`. Modules that can't be found or read are left for the
+/// import to report when it runs.
+pub fn check_imports(ast: &Ast, statements: &[StmtId], file: Option<&Path>) -> Result<(), String> {
+    let mut imports = Vec::new();
+    collect_imports(ast, statements, &mut imports);
+    for (path, names) in imports {
+        let Some(source) = resolve(&path.literal, file).and_then(|f| fs::read_to_string(f).ok())
+        else {
+            continue;
+        };
+        let mut parser = Parser::new(&source);
+        let statements = parser.parse_program();
+        if parser.has_scan_errors {
+            // The scanner has already reported them.
+            let message = format!("Module \"{}\" has syntax errors.", path.literal);
+            return Err(error(path, &message));
+        }
+        let statements = statements
+            .map_err(|e| error(path, &format!("In module \"{}\": {}", path.literal, e)))?;
+        let Imports::Names(names) = names else {
+            continue;
+        };
+        let ast = parser.into_ast();
+        let exports = exports(&ast, &statements);
+        let module = module_name(Path::new(&path.literal));
+        if let Some(name) = names.iter().find(|name| !exports.contains(&name.lexeme)) {
+            return Err(error(name, &not_exported(&module, &name.lexeme)));
+        }
+    }
+    Ok(())
+}
+
+/// Every import statement in `statements`, including those in nested
+/// statements and function declarations. Imports in function expressions
+/// are left for the import to check when it runs.
+fn collect_imports<'a>(
+    ast: &'a Ast,
+    statements: &[StmtId],
+    out: &mut Vec<(&'a Token, &'a Imports)>,
+) {
+    for &stmt in statements {
+        match &ast.stmt(stmt).kind {
+            StmtKind::Import(path, imports) => out.push((path, imports)),
+            StmtKind::Block(statements) => collect_imports(ast, statements, out),
+            StmtKind::Function(_, function) => {
+                if let ExprKind::Function(_, FunctionBody::Block(body)) = &ast.expr(*function).kind
+                {
+                    collect_imports(ast, body, out);
+                }
+            }
+            StmtKind::ForIn(_, _, _, body) => collect_imports(ast, &[*body], out),
+            StmtKind::Try(body, catch, finally) => {
+                collect_imports(ast, &[*body], out);
+                if let Some((_, handler)) = catch {
+                    collect_imports(ast, &[*handler], out);
+                }
+                if let Some(finally) = finally {
+                    collect_imports(ast, &[*finally], out);
+                }
+            }
+            StmtKind::Match(_, _, arms) => {
+                for arm in arms {
+                    collect_imports(ast, &[arm.body], out);
+                }
+            }
+            StmtKind::Expression(_)
+            | StmtKind::Print(_)
+            | StmtKind::Var(..)
+            | StmtKind::Return(..)
+            | StmtKind::Break(_)
+            | StmtKind::Continue(_)
+            | StmtKind::Throw(..)
+            | StmtKind::Export(..) => {}
+        }
+    }
+}
+
+/// Formats an error at `token` the way the parser does.
+fn error(token: &Token, message: &str) -> String {
+    format!(
+        "[line {}] Error at '{}': {}",
+        token.line, token.lexeme, message
+    )
+}
+
+/// Finds the file that `import "path"` in `importer` refers to. Relative
+/// paths are tried against the importing file's directory first, then
+/// against each directory on `LOX_PATH`.
+pub fn resolve(path: &str, importer: Option<&Path>) -> Option<PathBuf> {
+    let path = Path::new(path);
+    if path.is_absolute() {
+        return path.is_file().then(|| path.to_path_buf());
+    }
+    let base = importer
+        .and_then(Path::parent)
+        .map_or_else(PathBuf::new, Path::to_path_buf);
+    let search_path: Vec<PathBuf> = env::var_os("LOX_PATH")
+        .map(|dirs| env::split_paths(&dirs).collect())
+        .unwrap_or_default();
+    std::iter::once(base)
+        .chain(search_path)
+        .map(|dir| dir.join(path))
+        .find(|candidate| candidate.is_file())
+}
+
+/// How a module appears in an import cycle: just its file name, since the
+/// directories are usually the same.
+pub fn display_name(path: &Path) -> String {
+    path.file_name().map_or_else(
+        || path.display().to_string(),
+        |name| name.to_string_lossy().into_owned(),
+    )
+}
@@ -1,109 +1,859 @@
 // parser.rs
-use crate::ast::{Expr, LiteralValue};
-use crate::tokenizer::{Scanner, TokenType};
+use crate::ast::{
+    Ast, ExprId, ExprKind, FunctionBody, Imports, LiteralValue, MatchArm, Pattern, PatternKind,
+    Root, StmtId, StmtKind,
+};
+use crate::tokenizer::{Scanner, Span, Token, TokenType};
 
-pub struct Parser<'a> {
-    scanner: Scanner<'a>,
+pub struct Parser {
     current: usize,
-    tokens: Vec<(TokenType, String, String)>,
+    tokens: Vec<Token>,
+    ast: Ast,
+    /// How many loops enclose the statement being parsed, for rejecting
+    /// `break` and `continue` outside of one.
+    loop_depth: usize,
+    /// How many function bodies enclose the statement being parsed, for
+    /// rejecting `return` outside of one.
+    function_depth: usize,
+    /// The scanner reported errors; it has already printed them.
+    pub has_scan_errors: bool,
 }
 
-impl<'a> Parser<'a> {
-    pub fn new(source: &'a str) -> Self {
+impl Parser {
+    pub fn new(source: &str) -> Self {
         let mut scanner = Scanner::new(source);
-        let tokens = scanner.scan_tokens().to_vec();
+        scanner.scan_tokens();
+        let has_scan_errors = scanner.has_errors;
+        Parser {
+            has_scan_errors,
+            ..Parser::from_tokens(scanner.into_tokens())
+        }
+    }
+
+    /// A parser over tokens that have already been scanned without errors.
+    pub fn from_tokens(tokens: Vec<Token>) -> Self {
         Parser {
-            scanner,
             current: 0,
             tokens,
+            ast: Ast::default(),
+            loop_depth: 0,
+            function_depth: 0,
+            has_scan_errors: false,
         }
     }
 
-    pub fn parse(&mut self) -> Result<Expr, String> {
+    /// Hands over the tree that `parse` / `parse_program` built.
+    pub fn into_ast(self) -> Ast {
+        self.ast
+    }
+
+    pub fn parse(&mut self) -> Result<ExprId, String> {
         self.expression()
     }
 
-    fn expression(&mut self) -> Result<Expr, String> {
-        self.term()
+    /// Parses the whole input as a single expression if it is one, and as a
+    /// program otherwise.
+    pub fn parse_root(&mut self) -> Result<Root, String> {
+        if let Ok(expr) = self.expression() {
+            if self.is_at_end() {
+                return Ok(Root::Expression(expr));
+            }
+        }
+        self.current = 0;
+        self.ast = Ast::default();
+        Ok(Root::Program(self.parse_program()?))
     }
 
-    // term -> factor ( ( "+" | "-" ) factor )*
-    fn term(&mut self) -> Result<Expr, String> {
-        let mut expr = self.factor()?;
+    // program -> ( "export"? declaration )* EOF
+    pub fn parse_program(&mut self) -> Result<Vec<StmtId>, String> {
+        let mut statements = Vec::new();
+        while !self.is_at_end() {
+            if self.match_token(&[TokenType::Export]) {
+                statements.push(self.export()?);
+            } else {
+                statements.push(self.declaration()?);
+            }
+        }
+        Ok(statements)
+    }
 
-        while self.match_token(&[
-            TokenType::Minus,
-            TokenType::Plus,
-            TokenType::LessEqual,
-            TokenType::Less,
-            TokenType::Greater,
-            TokenType::GreaterEqual,
-            TokenType::EqualEqual,
-            TokenType::BangEqual,
-        ]) {
-            let operator = self.previous().0.clone();
-            let right = self.factor()?;
-            expr = Expr::Binary(Box::new(expr), operator, Box::new(right));
+    /// The declaration after `export`.
+    fn export(&mut self) -> Result<StmtId, String> {
+        let keyword = self.previous().clone();
+        if !self.check(&TokenType::Var) && !self.is_function_declaration() {
+            return Err(self.error(
+                self.peek(),
+                "Expect variable or function declaration after 'export'.",
+            ));
         }
+        let declaration = self.declaration()?;
+        let span = keyword.span.to(self.ast.stmt(declaration).span);
+        Ok(self
+            .ast
+            .add_stmt(span, StmtKind::Export(keyword, declaration)))
+    }
 
-        Ok(expr)
+    // declaration -> "var" IDENTIFIER ( "=" expression )? ";"
+    //              | "fun" IDENTIFIER function | statement
+    fn declaration(&mut self) -> Result<StmtId, String> {
+        if self.is_function_declaration() {
+            return self.function_declaration();
+        }
+        if !self.check(&TokenType::Var) {
+            return self.statement();
+        }
+        let start = self.advance().span;
+        let name = self
+            .consume(TokenType::Identifier, "Expect variable name.")?
+            .clone();
+        let initializer = if self.match_token(&[TokenType::Equal]) {
+            Some(self.expression()?)
+        } else {
+            None
+        };
+        let end = self
+            .consume(
+                TokenType::Semicolon,
+                "Expect ';' after variable declaration.",
+            )?
+            .span;
+        Ok(self
+            .ast
+            .add_stmt(start.to(end), StmtKind::Var(name, initializer)))
     }
 
-    fn factor(&mut self) -> Result<Expr, String> {
-        let mut expr = self.unary()?;
+    /// A `fun` followed by a name declares a function; one followed by `(`
+    /// starts a function expression.
+    fn is_function_declaration(&self) -> bool {
+        self.check(&TokenType::Fun)
+            && self
+                .tokens
+                .get(self.current + 1)
+                .is_some_and(|next| next.token_type == TokenType::Identifier)
+    }
 
-        while self.match_token(&[TokenType::Slash, TokenType::Star]) {
-            let operator = self.previous().0.clone();
-            let right = self.unary()?;
-            expr = Expr::Binary(Box::new(expr), operator, Box::new(right));
+    // fun_declaration -> "fun" IDENTIFIER function
+    fn function_declaration(&mut self) -> Result<StmtId, String> {
+        let start = self.advance().span;
+        let name = self.advance().clone();
+        let open = self
+            .consume(TokenType::LeftParen, "Expect '(' after function name.")?
+            .span;
+        let function = self.function(open)?;
+        let span = start.to(self.span(function));
+        Ok(self.ast.add_stmt(span, StmtKind::Function(name, function)))
+    }
+
+    // statement -> "print" expression ";" | block | for_in | "break" ";"
+    //            | "continue" ";" | "return" expression? ";"
+    //            | "throw" expression ";" | try | match | import
+    //            | expression ";"
+    fn statement(&mut self) -> Result<StmtId, String> {
+        let start = self.peek().span;
+        if self.check(&TokenType::Export) {
+            return Err(self.error(self.peek(), "Can only export top-level declarations."));
+        }
+        if self.match_token(&[TokenType::Return]) {
+            return self.return_statement(start);
+        }
+        if self.match_token(&[TokenType::Throw]) {
+            let keyword = self.previous().clone();
+            let value = self.expression()?;
+            let end = self
+                .consume(TokenType::Semicolon, "Expect ';' after thrown value.")?
+                .span;
+            return Ok(self
+                .ast
+                .add_stmt(start.to(end), StmtKind::Throw(keyword, value)));
+        }
+        if self.match_token(&[TokenType::Try]) {
+            return self.try_statement(start);
+        }
+        if self.match_token(&[TokenType::Match]) {
+            return self.match_statement(start);
+        }
+        if self.match_token(&[TokenType::Import, TokenType::From]) {
+            return self.import(start);
+        }
+        if self.match_token(&[TokenType::For]) {
+            return self.for_in(start);
+        }
+        if self.match_token(&[TokenType::Break, TokenType::Continue]) {
+            return self.loop_jump(start);
+        }
+        // A `{` starting a statement is always a block; map literals can
+        // only appear where an expression is expected.
+        if self.match_token(&[TokenType::LeftBrace]) {
+            return self.block(start);
+        }
+        if self.match_token(&[TokenType::Print]) {
+            let value = self.expression()?;
+            let end = self
+                .consume(TokenType::Semicolon, "Expect ';' after value.")?
+                .span;
+            return Ok(self.ast.add_stmt(start.to(end), StmtKind::Print(value)));
         }
 
-        Ok(expr)
+        let expr = self.expression()?;
+        let end = self
+            .consume(TokenType::Semicolon, "Expect ';' after expression.")?
+            .span;
+        Ok(self.ast.add_stmt(start.to(end), StmtKind::Expression(expr)))
     }
 
-    fn unary(&mut self) -> Result<Expr, String> {
-        if self.match_token(&[TokenType::Bang, TokenType::Minus]) {
-            let operator = self.previous().0.clone();
-            let right = self.unary()?;
-            return Ok(Expr::Unary(operator, Box::new(right)));
+    // block -> "{" declaration* "}"
+    fn block(&mut self, start: Span) -> Result<StmtId, String> {
+        let (statements, end) = self.block_contents()?;
+        Ok(self
+            .ast
+            .add_stmt(start.to(end), StmtKind::Block(statements)))
+    }
+
+    /// The declarations after a `{`, and the span of the closing `}`.
+    fn block_contents(&mut self) -> Result<(Vec<StmtId>, Span), String> {
+        let mut statements = Vec::new();
+        while !self.check(&TokenType::RightBrace) && !self.is_at_end() {
+            statements.push(self.declaration()?);
         }
+        let end = self
+            .consume(TokenType::RightBrace, "Expect '}' after block.")?
+            .span;
+        Ok((statements, end))
+    }
 
-        self.primary()
+    /// The rest of a `return` statement.
+    fn return_statement(&mut self, start: Span) -> Result<StmtId, String> {
+        let keyword = self.previous().clone();
+        if self.function_depth == 0 {
+            return Err(self.error(&keyword, "Can't return from top-level code."));
+        }
+        let value = if self.check(&TokenType::Semicolon) {
+            None
+        } else {
+            Some(self.expression()?)
+        };
+        let end = self
+            .consume(TokenType::Semicolon, "Expect ';' after return value.")?
+            .span;
+        Ok(self
+            .ast
+            .add_stmt(start.to(end), StmtKind::Return(keyword, value)))
     }
 
-    fn primary(&mut self) -> Result<Expr, String> {
-        if self.match_token(&[TokenType::LeftParen]) {
-            let expr = self.expression()?;
-            self.consume(TokenType::RightParen, "Expect ')' after expression.")?;
-            return Ok(Expr::Grouping(Box::new(expr)));
+    // for_in -> "for" "(" IDENTIFIER "in" expression ")" statement
+    fn for_in(&mut self, start: Span) -> Result<StmtId, String> {
+        self.consume(TokenType::LeftParen, "Expect '(' after 'for'.")?;
+        let name = self
+            .consume(TokenType::Identifier, "Expect loop variable name.")?
+            .clone();
+        let keyword = self
+            .consume(TokenType::In, "Expect 'in' after loop variable.")?
+            .clone();
+        let iterable = self.expression()?;
+        self.consume(TokenType::RightParen, "Expect ')' after for clauses.")?;
+        self.loop_depth += 1;
+        let body = self.statement();
+        self.loop_depth -= 1;
+        let body = body?;
+        let span = start.to(self.ast.stmt(body).span);
+        Ok(self
+            .ast
+            .add_stmt(span, StmtKind::ForIn(name, keyword, iterable, body)))
+    }
+
+    // try -> "try" block ( "catch" "(" IDENTIFIER ")" block )?
+    //        ( "finally" block )?
+    fn try_statement(&mut self, start: Span) -> Result<StmtId, String> {
+        let body = self.clause_block("Expect '{' after 'try'.")?;
+        let catch = if self.match_token(&[TokenType::Catch]) {
+            self.consume(TokenType::LeftParen, "Expect '(' after 'catch'.")?;
+            let name = self
+                .consume(TokenType::Identifier, "Expect error variable name.")?
+                .clone();
+            self.consume(TokenType::RightParen, "Expect ')' after error variable.")?;
+            Some((name, self.clause_block("Expect '{' after catch clause.")?))
+        } else {
+            None
+        };
+        let finally = if self.match_token(&[TokenType::Finally]) {
+            Some(self.clause_block("Expect '{' after 'finally'.")?)
+        } else {
+            None
+        };
+        let last = match (&catch, finally) {
+            (_, Some(finally)) => finally,
+            (Some((_, handler)), None) => *handler,
+            (None, None) => {
+                return Err(self.error(self.peek(), "Expect 'catch' or 'finally' after try block."))
+            }
+        };
+        let span = start.to(self.ast.stmt(last).span);
+        Ok(self.ast.add_stmt(span, StmtKind::Try(body, catch, finally)))
+    }
+
+    /// A block that a `try` statement requires.
+    fn clause_block(&mut self, message: &str) -> Result<StmtId, String> {
+        let start = self.consume(TokenType::LeftBrace, message)?.span;
+        self.block(start)
+    }
+
+    // match -> "match" "(" expression ")" "{" arm* "}"
+    // arm -> pattern ( "if" expression )? "=>" statement
+    fn match_statement(&mut self, start: Span) -> Result<StmtId, String> {
+        let keyword = self.previous().clone();
+        let subject = self.match_subject()?;
+        let mut arms = Vec::new();
+        while !self.check(&TokenType::RightBrace) && !self.is_at_end() {
+            let (pattern, guard) = self.arm_head()?;
+            let body = self.statement()?;
+            arms.push(MatchArm {
+                pattern,
+                guard,
+                body,
+            });
         }
+        let end = self
+            .consume(TokenType::RightBrace, "Expect '}' after match arms.")?
+            .span;
+        Ok(self
+            .ast
+            .add_stmt(start.to(end), StmtKind::Match(keyword, subject, arms)))
+    }
 
-        if self.is_at_end() {
-            return Err("Unexpected end of input".to_string());
+    // match_expr -> "match" "(" expression ")" "{" ( expr_arm ( "," expr_arm )* ","? )? "}"
+    // expr_arm -> pattern ( "if" expression )? "=>" assignment
+    fn match_expression(&mut self, keyword: Token) -> Result<ExprId, String> {
+        let subject = self.match_subject()?;
+        let mut arms = Vec::new();
+        while !self.check(&TokenType::RightBrace) && !self.is_at_end() {
+            let (pattern, guard) = self.arm_head()?;
+            let body = self.parse_precedence(Precedence::Assignment)?;
+            arms.push(MatchArm {
+                pattern,
+                guard,
+                body,
+            });
+            if !self.match_token(&[TokenType::Comma]) {
+                break;
+            }
         }
+        let end = self
+            .consume(TokenType::RightBrace, "Expect '}' after match arms.")?
+            .span;
+        Ok(self.ast.add_expr(
+            keyword.span.to(end),
+            ExprKind::Match(keyword, subject, arms),
+        ))
+    }
+
+    /// `(subject) {`, after the `match`.
+    fn match_subject(&mut self) -> Result<ExprId, String> {
+        self.consume(TokenType::LeftParen, "Expect '(' after 'match'.")?;
+        let subject = self.expression()?;
+        self.consume(TokenType::RightParen, "Expect ')' after match value.")?;
+        self.consume(TokenType::LeftBrace, "Expect '{' before match arms.")?;
+        Ok(subject)
+    }
+
+    /// An arm's pattern and guard, up to and including its `=>`.
+    fn arm_head(&mut self) -> Result<(Pattern, Option<ExprId>), String> {
+        let pattern = self.pattern()?;
+        self.distinct_bindings(&pattern)?;
+        let guard = if self.match_token(&[TokenType::If]) {
+            Some(self.expression()?)
+        } else {
+            None
+        };
+        self.consume(TokenType::EqualGreater, "Expect '=>' after pattern.")?;
+        Ok((pattern, guard))
+    }
 
-        match &self.peek().0 {
-            TokenType::True => {
+    /// Rejects a pattern that binds the same name twice, which would leave
+    /// it unclear which value the name gets.
+    fn distinct_bindings(&self, pattern: &Pattern) -> Result<(), String> {
+        let mut names = Vec::new();
+        pattern.bindings(&mut names);
+        for (i, name) in names.iter().enumerate() {
+            if names[..i].iter().any(|bound| bound.lexeme == name.lexeme) {
+                return Err(self.error(name, "Already a binding with this name in this pattern."));
+            }
+        }
+        Ok(())
+    }
+
+    // pattern -> literal | "-" NUMBER | IDENTIFIER | list_pattern
+    fn pattern(&mut self) -> Result<Pattern, String> {
+        let token = self.peek().clone();
+        let kind = match token.token_type {
+            TokenType::Identifier => {
+                self.advance();
+                PatternKind::Binding(token.clone())
+            }
+            TokenType::Number
+            | TokenType::String
+            | TokenType::True
+            | TokenType::False
+            | TokenType::Nil => {
                 self.advance();
-                Ok(Expr::Literal(LiteralValue::Bool(true)))
+                PatternKind::Literal(self.literal(token.clone())?)
             }
-            TokenType::False => {
+            TokenType::Minus => {
                 self.advance();
-                Ok(Expr::Literal(LiteralValue::Bool(false)))
+                let number = self
+                    .consume(TokenType::Number, "Expect number after '-' in pattern.")?
+                    .clone();
+                let number = self.literal(number)?;
+                let span = token.span.to(self.ast.expr(number).span);
+                PatternKind::Literal(
+                    self.ast
+                        .add_expr(span, ExprKind::Unary(token.clone(), number)),
+                )
             }
-            TokenType::Nil => {
+            TokenType::LeftBracket => {
                 self.advance();
-                Ok(Expr::Literal(LiteralValue::Nil))
+                return self.list_pattern(token);
             }
+            _ => return Err(self.error(&token, "Expect pattern.")),
+        };
+        let span = token.span.to(self.previous().span);
+        Ok(Pattern { span, kind })
+    }
+
+    // list_pattern -> "[" ( pattern ( "," pattern )* ","? )? ( ".." IDENTIFIER )? "]"
+    fn list_pattern(&mut self, open: Token) -> Result<Pattern, String> {
+        let mut elements = Vec::new();
+        let mut rest = None;
+        while !self.check(&TokenType::RightBracket) {
+            if self.match_token(&[TokenType::DotDot]) {
+                let name = self.consume(TokenType::Identifier, "Expect name after '..'.")?;
+                rest = Some(name.clone());
+                break;
+            }
+            elements.push(self.pattern()?);
+            if !self.match_token(&[TokenType::Comma]) {
+                break;
+            }
+        }
+        let message = match rest {
+            Some(_) => "Expect ']' after rest pattern.",
+            None => "Expect ']' after list pattern.",
+        };
+        let close = self.consume(TokenType::RightBracket, message)?.span;
+        Ok(Pattern {
+            span: open.span.to(close),
+            kind: PatternKind::List(elements, rest),
+        })
+    }
+
+    // import -> "import" STRING "as" IDENTIFIER ";"
+    //         | "from" STRING "import" IDENTIFIER ( "," IDENTIFIER )* ";"
+    fn import(&mut self, start: Span) -> Result<StmtId, String> {
+        let keyword = self.previous().token_type.clone();
+        let path = self
+            .consume(TokenType::String, "Expect module path.")?
+            .clone();
+        let imports = if keyword == TokenType::Import {
+            self.consume(TokenType::As, "Expect 'as' after module path.")?;
+            let name = self.consume(TokenType::Identifier, "Expect module name after 'as'.")?;
+            Imports::Module(name.clone())
+        } else {
+            self.consume(TokenType::Import, "Expect 'import' after module path.")?;
+            let mut names = Vec::new();
+            loop {
+                let name = self.consume(TokenType::Identifier, "Expect name to import.")?;
+                names.push(name.clone());
+                if !self.match_token(&[TokenType::Comma]) {
+                    break;
+                }
+            }
+            Imports::Names(names)
+        };
+        let end = self
+            .consume(TokenType::Semicolon, "Expect ';' after import.")?
+            .span;
+        Ok(self
+            .ast
+            .add_stmt(start.to(end), StmtKind::Import(path, imports)))
+    }
+
+    /// The rest of a `break` or `continue` statement.
+    fn loop_jump(&mut self, start: Span) -> Result<StmtId, String> {
+        let keyword = self.previous().clone();
+        if self.loop_depth == 0 {
+            let message = format!("Can't use '{}' outside of a loop.", keyword.lexeme);
+            return Err(self.error(&keyword, &message));
+        }
+        let message = format!("Expect ';' after '{}'.", keyword.lexeme);
+        let end = self.consume(TokenType::Semicolon, &message)?.span;
+        let kind = match keyword.token_type {
+            TokenType::Break => StmtKind::Break(keyword),
+            _ => StmtKind::Continue(keyword),
+        };
+        Ok(self.ast.add_stmt(start.to(end), kind))
+    }
+
+    fn expression(&mut self) -> Result<ExprId, String> {
+        self.parse_precedence(Precedence::Comma)
+    }
+
+    /// Parses an expression whose operators all bind at least as tightly as
+    /// `precedence`, driven entirely by the `rule` table.
+    fn parse_precedence(&mut self, precedence: Precedence) -> Result<ExprId, String> {
+        if self.is_at_end() {
+            return Err(self.error(self.peek(), "Expect expression."));
+        }
+        let prefix = match rule(&self.peek().token_type).prefix {
+            Some(prefix) => prefix,
+            None => return Err(self.error(self.peek(), "Expect expression.")),
+        };
+        let token = self.advance().clone();
+        let mut expr = prefix(self, token)?;
+
+        loop {
+            let next = rule(&self.peek().token_type);
+            let infix = match next.infix {
+                Some(infix) if next.precedence >= precedence => infix,
+                _ => break,
+            };
+            let token = self.advance().clone();
+            expr = infix(self, expr, token)?;
+        }
+
+        Ok(expr)
+    }
+
+    fn literal(&mut self, token: Token) -> Result<ExprId, String> {
+        let value = match token.token_type {
+            TokenType::True => LiteralValue::Bool(true),
+            TokenType::False => LiteralValue::Bool(false),
+            TokenType::Nil => LiteralValue::Nil,
             TokenType::Number => {
-                let value = self.advance().2.parse::<f64>().map_err(|e| e.to_string())?;
-                Ok(Expr::Literal(LiteralValue::Number(value)))
+                LiteralValue::Number(token.literal.parse::<f64>().map_err(|e| e.to_string())?)
+            }
+            TokenType::String => LiteralValue::String(token.literal),
+            _ => unreachable!("only literal tokens have the literal rule"),
+        };
+        Ok(self.ast.add_expr(token.span, ExprKind::Literal(value)))
+    }
+
+    fn grouping(&mut self, open: Token) -> Result<ExprId, String> {
+        if self.is_arrow() {
+            return self.arrow(open);
+        }
+        let expr = self.expression()?;
+        let close = self
+            .consume(TokenType::RightParen, "Expect ')' after expression.")?
+            .span;
+        Ok(self
+            .ast
+            .add_expr(open.span.to(close), ExprKind::Grouping(expr)))
+    }
+
+    /// Whether the tokens after a `(` are the parameters of an arrow
+    /// function: names separated by commas, then `)` and `=>`.
+    fn is_arrow(&self) -> bool {
+        let token_type = |i: usize| self.tokens.get(i).map(|token| &token.token_type);
+        let mut i = self.current;
+        if token_type(i) == Some(&TokenType::Identifier) {
+            i += 1;
+            while token_type(i) == Some(&TokenType::Comma)
+                && token_type(i + 1) == Some(&TokenType::Identifier)
+            {
+                i += 2;
+            }
+        }
+        token_type(i) == Some(&TokenType::RightParen)
+            && token_type(i + 1) == Some(&TokenType::EqualGreater)
+    }
+
+    // arrow -> "(" parameters ")" "=>" assignment
+    fn arrow(&mut self, open: Token) -> Result<ExprId, String> {
+        let params = self.parameters()?;
+        self.consume(TokenType::EqualGreater, "Expect '=>' after parameters.")?;
+        if self.check(&TokenType::LeftBrace) {
+            return Err(self.error(
+                self.peek(),
+                "Expect expression after '=>', not a block; parenthesize a map literal.",
+            ));
+        }
+        let body = self.parse_precedence(Precedence::Assignment)?;
+        let span = open.span.to(self.span(body));
+        Ok(self.ast.add_expr(
+            span,
+            ExprKind::Function(params, FunctionBody::Expression(body)),
+        ))
+    }
+
+    // lambda -> "fun" function
+    fn lambda(&mut self, keyword: Token) -> Result<ExprId, String> {
+        self.consume(TokenType::LeftParen, "Expect '(' after 'fun'.")?;
+        self.function(keyword.span)
+    }
+
+    /// The parameters and block body of a function, after its `(`. The
+    /// node's span starts at `start`, which is the `fun` of a function
+    /// expression.
+    fn function(&mut self, start: Span) -> Result<ExprId, String> {
+        let params = self.parameters()?;
+        self.consume(TokenType::LeftBrace, "Expect '{' before function body.")?;
+        // Loops outside the function don't enclose its body.
+        let loop_depth = std::mem::replace(&mut self.loop_depth, 0);
+        self.function_depth += 1;
+        let body = self.block_contents();
+        self.function_depth -= 1;
+        self.loop_depth = loop_depth;
+        let (statements, end) = body?;
+        Ok(self.ast.add_expr(
+            start.to(end),
+            ExprKind::Function(params, FunctionBody::Block(statements)),
+        ))
+    }
+
+    // parameters -> ( IDENTIFIER ( "," IDENTIFIER )* )? ")"
+    fn parameters(&mut self) -> Result<Vec<Token>, String> {
+        let mut params: Vec<Token> = Vec::new();
+        if !self.check(&TokenType::RightParen) {
+            loop {
+                if params.len() >= 255 {
+                    return Err(self.error(self.peek(), "Can't have more than 255 parameters."));
+                }
+                let name = self
+                    .consume(TokenType::Identifier, "Expect parameter name.")?
+                    .clone();
+                if params.iter().any(|param| param.lexeme == name.lexeme) {
+                    return Err(self.error(&name, "Already a parameter with this name."));
+                }
+                params.push(name);
+                if !self.match_token(&[TokenType::Comma]) {
+                    break;
+                }
+            }
+        }
+        self.consume(TokenType::RightParen, "Expect ')' after parameters.")?;
+        Ok(params)
+    }
+
+    fn variable(&mut self, name: Token) -> Result<ExprId, String> {
+        Ok(self.ast.add_expr(name.span, ExprKind::Variable(name)))
+    }
+
+    // list -> "[" ( assignment ( "," assignment )* ","? )? "]"
+    fn list(&mut self, open: Token) -> Result<ExprId, String> {
+        let mut elements = Vec::new();
+        while !self.check(&TokenType::RightBracket) {
+            elements.push(self.parse_precedence(Precedence::Assignment)?);
+            if !self.match_token(&[TokenType::Comma]) {
+                break;
+            }
+        }
+        let close = self
+            .consume(TokenType::RightBracket, "Expect ']' after list elements.")?
+            .span;
+        Ok(self
+            .ast
+            .add_expr(open.span.to(close), ExprKind::List(elements)))
+    }
+
+    // map -> "{" ( entry ( "," entry )* ","? )? "}"
+    // entry -> assignment ":" assignment
+    fn map(&mut self, open: Token) -> Result<ExprId, String> {
+        let mut entries = Vec::new();
+        while !self.check(&TokenType::RightBrace) {
+            let key = self.parse_precedence(Precedence::Assignment)?;
+            let colon = self
+                .consume(TokenType::Colon, "Expect ':' after map key.")?
+                .clone();
+            let value = self.parse_precedence(Precedence::Assignment)?;
+            entries.push((key, colon, value));
+            if !self.match_token(&[TokenType::Comma]) {
+                break;
+            }
+        }
+        let close = self
+            .consume(TokenType::RightBrace, "Expect '}' after map entries.")?
+            .span;
+        Ok(self
+            .ast
+            .add_expr(open.span.to(close), ExprKind::Map(entries)))
+    }
+
+    fn unary(&mut self, operator: Token) -> Result<ExprId, String> {
+        let right = self.parse_precedence(Precedence::Unary)?;
+        let span = operator.span.to(self.span(right));
+        Ok(self.ast.add_expr(span, ExprKind::Unary(operator, right)))
+    }
+
+    fn binary(&mut self, left: ExprId, operator: Token) -> Result<ExprId, String> {
+        let rule = rule(&operator.token_type);
+        let right = match rule.associativity {
+            Associativity::Left => self.parse_precedence(rule.precedence.next())?,
+            Associativity::Right => self.parse_precedence(rule.precedence)?,
+        };
+        let span = self.span(left).to(self.span(right));
+        Ok(self
+            .ast
+            .add_expr(span, ExprKind::Binary(left, operator, right)))
+    }
+
+    fn logical(&mut self, left: ExprId, operator: Token) -> Result<ExprId, String> {
+        let precedence = rule(&operator.token_type).precedence;
+        let right = self.parse_precedence(precedence.next())?;
+        let span = self.span(left).to(self.span(right));
+        Ok(self
+            .ast
+            .add_expr(span, ExprKind::Logical(left, operator, right)))
+    }
+
+    // conditional -> logic_or ( "?" expression ":" conditional )?
+    fn conditional(&mut self, condition: ExprId, _question: Token) -> Result<ExprId, String> {
+        let then_branch = self.expression()?;
+        self.consume(
+            TokenType::Colon,
+            "Expect ':' after then branch of conditional expression.",
+        )?;
+        // Right-associative: `a ? b : c ? d : e` nests in the else branch.
+        let else_branch = self.parse_precedence(Precedence::Conditional)?;
+        let span = self.span(condition).to(self.span(else_branch));
+        Ok(self.ast.add_expr(
+            span,
+            ExprKind::Conditional(condition, then_branch, else_branch),
+        ))
+    }
+
+    // assignment -> ( IDENTIFIER | call "[" expression "]" ) "=" assignment
+    fn assign(&mut self, target: ExprId, equals: Token) -> Result<ExprId, String> {
+        let value = self.parse_precedence(Precedence::Assignment)?;
+        let span = self.span(target).to(self.span(value));
+        let kind = match &self.ast.expr(target).kind {
+            ExprKind::Variable(name) => ExprKind::Assign(name.clone(), value),
+            ExprKind::Index(object, bracket, index) => {
+                ExprKind::IndexSet(*object, bracket.clone(), *index, value)
             }
-            TokenType::String => {
-                let value = self.advance().2.clone();
-                Ok(Expr::Literal(LiteralValue::String(value)))
+            _ => return Err(self.error(&equals, "Invalid assignment target.")),
+        };
+        Ok(self.ast.add_expr(span, kind))
+    }
+
+    // compound -> ( IDENTIFIER | call "[" expression "]" ) ( "+=" | "-=" | ... ) assignment
+    fn compound_assign(&mut self, target: ExprId, operator: Token) -> Result<ExprId, String> {
+        self.check_target(target, &operator)?;
+        let value = self.parse_precedence(Precedence::Assignment)?;
+        let span = self.span(target).to(self.span(value));
+        Ok(self
+            .ast
+            .add_expr(span, ExprKind::CompoundAssign(target, operator, value)))
+    }
+
+    // prefix_update -> ( "++" | "--" ) unary
+    fn prefix_update(&mut self, operator: Token) -> Result<ExprId, String> {
+        let target = self.parse_precedence(Precedence::Unary)?;
+        self.check_target(target, &operator)?;
+        let span = operator.span.to(self.span(target));
+        Ok(self
+            .ast
+            .add_expr(span, ExprKind::Update(target, operator, true)))
+    }
+
+    // postfix_update -> call ( "++" | "--" )
+    fn postfix_update(&mut self, target: ExprId, operator: Token) -> Result<ExprId, String> {
+        self.check_target(target, &operator)?;
+        let span = self.span(target).to(operator.span);
+        Ok(self
+            .ast
+            .add_expr(span, ExprKind::Update(target, operator, false)))
+    }
+
+    /// Compound assignment and `++`/`--` read and write the same places
+    /// that `=` can.
+    fn check_target(&self, target: ExprId, operator: &Token) -> Result<(), String> {
+        match self.ast.expr(target).kind {
+            ExprKind::Variable(_) | ExprKind::Index(..) => Ok(()),
+            _ => Err(self.error(operator, "Invalid assignment target.")),
+        }
+    }
+
+    // call -> primary "(" ( assignment ( "," assignment )* )? ")"
+    fn call(&mut self, callee: ExprId, _open: Token) -> Result<ExprId, String> {
+        let mut arguments = Vec::new();
+        if !self.check(&TokenType::RightParen) {
+            loop {
+                if arguments.len() >= 255 {
+                    return Err(self.error(self.peek(), "Can't have more than 255 arguments."));
+                }
+                arguments.push(self.parse_precedence(Precedence::Assignment)?);
+                if !self.match_token(&[TokenType::Comma]) {
+                    break;
+                }
             }
-            _ => Err(format!("Unexpected token: {:?}", self.peek())),
+        }
+        let paren = self
+            .consume(TokenType::RightParen, "Expect ')' after arguments.")?
+            .clone();
+        let span = self.span(callee).to(paren.span);
+        Ok(self
+            .ast
+            .add_expr(span, ExprKind::Call(callee, paren, arguments)))
+    }
+
+    fn get(&mut self, object: ExprId, _dot: Token) -> Result<ExprId, String> {
+        let name = self
+            .consume(TokenType::Identifier, "Expect property name after '.'.")?
+            .clone();
+        let span = self.span(object).to(name.span);
+        Ok(self.ast.add_expr(span, ExprKind::Get(object, name)))
+    }
+
+    // index -> call "[" ( assignment | assignment? ":" assignment? ) "]"
+    fn index(&mut self, object: ExprId, bracket: Token) -> Result<ExprId, String> {
+        if self.match_token(&[TokenType::Colon]) {
+            return self.slice(object, bracket, None);
+        }
+        let index = self.parse_precedence(Precedence::Assignment)?;
+        if self.match_token(&[TokenType::Colon]) {
+            return self.slice(object, bracket, Some(index));
+        }
+        let close = self
+            .consume(TokenType::RightBracket, "Expect ']' after index.")?
+            .span;
+        let span = self.span(object).to(close);
+        Ok(self
+            .ast
+            .add_expr(span, ExprKind::Index(object, bracket, index)))
+    }
+
+    /// The rest of `object[start:end]`, after the `:`.
+    fn slice(
+        &mut self,
+        object: ExprId,
+        bracket: Token,
+        start: Option<ExprId>,
+    ) -> Result<ExprId, String> {
+        let end = if self.check(&TokenType::RightBracket) {
+            None
+        } else {
+            Some(self.parse_precedence(Precedence::Assignment)?)
+        };
+        let close = self
+            .consume(TokenType::RightBracket, "Expect ']' after slice.")?
+            .span;
+        let span = self.span(object).to(close);
+        Ok(self
+            .ast
+            .add_expr(span, ExprKind::Slice(object, bracket, start, end)))
+    }
+
+    fn span(&self, expr: ExprId) -> Span {
+        self.ast.expr(expr).span
+    }
+
+    fn error(&self, token: &Token, message: &str) -> String {
+        if token.token_type == TokenType::Eof {
+            format!("[line {}] Error at end: {}", token.line, message)
+        } else {
+            format!(
+                "[line {}] Error at '{}': {}",
+                token.line, token.lexeme, message
+            )
         }
     }
 
@@ -117,15 +867,11 @@ impl<'a> Parser<'a> {
         false
     }
 
-    fn consume(
-        &mut self,
-        t: TokenType,
-        message: &str,
-    ) -> Result<&(TokenType, String, String), String> {
+    fn consume(&mut self, t: TokenType, message: &str) -> Result<&Token, String> {
         if self.check(&t) {
             Ok(self.advance())
         } else {
-            Err(message.to_string())
+            Err(self.error(self.peek(), message))
         }
     }
 
@@ -133,11 +879,11 @@ impl<'a> Parser<'a> {
         if self.is_at_end() {
             false
         } else {
-            &self.peek().0 == t
+            &self.peek().token_type == t
         }
     }
 
-    fn advance(&mut self) -> &(TokenType, String, String) {
+    fn advance(&mut self) -> &Token {
         if !self.is_at_end() {
             self.current += 1;
         }
@@ -145,14 +891,222 @@ impl<'a> Parser<'a> {
     }
 
     fn is_at_end(&self) -> bool {
-        self.peek().0 == TokenType::Eof
+        self.peek().token_type == TokenType::Eof
     }
 
-    fn peek(&self) -> &(TokenType, String, String) {
+    fn peek(&self) -> &Token {
         &self.tokens[self.current]
     }
 
-    fn previous(&self) -> &(TokenType, String, String) {
+    fn previous(&self) -> &Token {
         &self.tokens[self.current - 1]
     }
 }
+
+/// Binding power of infix operators, weakest first.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Precedence {
+    None,
+    Comma,       // ,
+    Assignment,  // =
+    Conditional, // ?:
+    Or,          // or
+    And,         // and
+    Equality,    // == !=
+    Comparison,  // < > <= >=
+    Range,       // .. ..=
+    Term,        // + -
+    Factor,      // * / %
+    Unary,       // ! -
+    Exponent,    // **
+    Call,        // () [] .
+    Primary,     // literals, variables, lists, maps, groupings, functions, match
+}
+
+impl Precedence {
+    /// The next tighter level, used for the right operand of a
+    /// left-associative operator.
+    fn next(self) -> Precedence {
+        match self {
+            Precedence::None => Precedence::Comma,
+            Precedence::Comma => Precedence::Assignment,
+            Precedence::Assignment => Precedence::Conditional,
+            Precedence::Conditional => Precedence::Or,
+            Precedence::Or => Precedence::And,
+            Precedence::And => Precedence::Equality,
+            Precedence::Equality => Precedence::Comparison,
+            Precedence::Comparison => Precedence::Range,
+            Precedence::Range => Precedence::Term,
+            Precedence::Term => Precedence::Factor,
+            Precedence::Factor => Precedence::Unary,
+            Precedence::Unary => Precedence::Exponent,
+            Precedence::Exponent => Precedence::Call,
+            Precedence::Call | Precedence::Primary => Precedence::Primary,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Associativity {
+    Left,
+    Right,
+}
+
+type PrefixFn = fn(&mut Parser, Token) -> Result<ExprId, String>;
+type InfixFn = fn(&mut Parser, ExprId, Token) -> Result<ExprId, String>;
+
+/// How a token parses at the start of an expression (`prefix`) and after a
+/// complete left operand (`infix`). Postfix operators are infix rules that
+/// don't parse a right operand.
+struct ParseRule {
+    prefix: Option<PrefixFn>,
+    infix: Option<InfixFn>,
+    precedence: Precedence,
+    associativity: Associativity,
+}
+
+/// Precedence and associativity of an infix operator, as the parser sees it.
+pub fn infix_precedence(token_type: &TokenType) -> (Precedence, Associativity) {
+    let rule = rule(token_type);
+    (rule.precedence, rule.associativity)
+}
+
+/// The operator table. Adding an operator means adding an entry here.
+fn rule(token_type: &TokenType) -> ParseRule {
+    use Associativity::*;
+    use TokenType::*;
+
+    let (prefix, infix, precedence, associativity): (
+        Option<PrefixFn>,
+        Option<InfixFn>,
+        Precedence,
+        Associativity,
+    ) = match token_type {
+        LeftParen => (
+            Some(Parser::grouping),
+            Some(Parser::call),
+            Precedence::Call,
+            Left,
+        ),
+        LeftBracket => (
+            Some(Parser::list),
+            Some(Parser::index),
+            Precedence::Call,
+            Left,
+        ),
+        LeftBrace => (Some(Parser::map), None, Precedence::None, Left),
+        Dot => (None, Some(Parser::get), Precedence::Call, Left),
+        Comma => (None, Some(Parser::binary), Precedence::Comma, Left),
+        Equal => (None, Some(Parser::assign), Precedence::Assignment, Right),
+        PlusEqual | MinusEqual | StarEqual | SlashEqual | PercentEqual => (
+            None,
+            Some(Parser::compound_assign),
+            Precedence::Assignment,
+            Right,
+        ),
+        PlusPlus | MinusMinus => (
+            Some(Parser::prefix_update),
+            Some(Parser::postfix_update),
+            Precedence::Call,
+            Left,
+        ),
+        Question => (
+            None,
+            Some(Parser::conditional),
+            Precedence::Conditional,
+            Right,
+        ),
+        Or => (None, Some(Parser::logical), Precedence::Or, Left),
+        And => (None, Some(Parser::logical), Precedence::And, Left),
+        Minus => (
+            Some(Parser::unary),
+            Some(Parser::binary),
+            Precedence::Term,
+            Left,
+        ),
+        DotDot | DotDotEqual => (None, Some(Parser::binary), Precedence::Range, Left),
+        Plus => (None, Some(Parser::binary), Precedence::Term, Left),
+        Slash | Star | Percent => (None, Some(Parser::binary), Precedence::Factor, Left),
+        StarStar => (None, Some(Parser::binary), Precedence::Exponent, Right),
+        Bang => (Some(Parser::unary), None, Precedence::None, Left),
+        BangEqual | EqualEqual => (None, Some(Parser::binary), Precedence::Equality, Left),
+        Greater | GreaterEqual | Less | LessEqual => {
+            (None, Some(Parser::binary), Precedence::Comparison, Left)
+        }
+        Number | String | True | False | Nil => {
+            (Some(Parser::literal), None, Precedence::None, Left)
+        }
+        Identifier => (Some(Parser::variable), None, Precedence::None, Left),
+        Fun => (Some(Parser::lambda), None, Precedence::None, Left),
+        Match => (Some(Parser::match_expression), None, Precedence::None, Left),
+        _ => (None, None, Precedence::None, Left),
+    };
+
+    ParseRule {
+        prefix,
+        infix,
+        precedence,
+        associativity,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ast_printer::AstPrinter;
+    use crate::testing::{self, Rng};
+    use crate::unparser::Unparser;
+
+    fn parse(source: &str) -> (Ast, Vec<StmtId>) {
+        let mut parser = Parser::new(source);
+        let statements = parser
+            .parse_program()
+            .unwrap_or_else(|e| panic!("{}\nwhile parsing: {}", e, source));
+        (parser.into_ast(), statements)
+    }
+
+    fn shape(ast: &Ast, statements: &[StmtId]) -> Vec<String> {
+        statements
+            .iter()
+            .map(|&stmt| AstPrinter::print_stmt(ast, stmt))
+            .collect()
+    }
+
+    fn unparse(ast: &Ast, statements: &[StmtId]) -> String {
+        statements
+            .iter()
+            .map(|&stmt| format!("{}\n", Unparser::stmt(ast, stmt).unwrap()))
+            .collect()
+    }
+
+    #[test]
+    fn print_and_reparse_is_stable() {
+        let mut rng = Rng::new(0xd1b5_4a32_d192_ed03);
+        for _ in 0..1000 {
+            let count = 1 + rng.below(4);
+            let source = testing::program(&mut rng, count);
+            let (ast, statements) = parse(&source);
+
+            let printed = unparse(&ast, &statements);
+            let (reparsed, again) = parse(&printed);
+            assert_eq!(
+                shape(&ast, &statements),
+                shape(&reparsed, &again),
+                "{}\nprinted as:\n{}",
+                source,
+                printed
+            );
+            assert_eq!(printed, unparse(&reparsed, &again));
+        }
+    }
+
+    #[test]
+    fn parser_never_panics() {
+        let mut rng = Rng::new(0xa076_1d64_78bd_642f);
+        for _ in 0..3000 {
+            let len = rng.below(48);
+            let source = testing::noise(&mut rng, len);
+            let _ = Parser::new(&source).parse_root();
+        }
+    }
+}
@@ -0,0 +1,179 @@
+// range.rs
+use crate::value::{self, NativeFn, NativeMethod, Value};
+use std::fmt;
+use std::rc::Rc;
+
+/// A lazy sequence of integers `start, start + step, ...` that stops before
+/// `end`, or at it for `start..=end`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Range {
+    start: i64,
+    end: i64,
+    step: i64,
+    inclusive: bool,
+}
+
+impl Range {
+    /// `start..end` or `start..=end`.
+    pub fn new(start: &Value, end: &Value, inclusive: bool) -> Result<Range, String> {
+        Ok(Range {
+            start: bound(start)?,
+            end: bound(end)?,
+            step: 1,
+            inclusive,
+        })
+    }
+
+    fn with_step(start: &Value, end: &Value, step: &Value) -> Result<Range, String> {
+        let step = match step {
+            Value::Number(n) if n.fract() == 0.0 && *n != 0.0 && n.abs() <= MAX_EXACT => *n as i64,
+            _ => return Err("Range step must be a non-zero integer.".to_string()),
+        };
+        Ok(Range {
+            start: bound(start)?,
+            end: bound(end)?,
+            step,
+            inclusive: false,
+        })
+    }
+
+    /// The exclusive end. Inclusive ranges always step by 1.
+    fn stop(&self) -> i64 {
+        if self.inclusive {
+            self.end + 1
+        } else {
+            self.end
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        let (distance, step) = if self.step > 0 {
+            (self.stop() - self.start, self.step)
+        } else {
+            (self.start - self.stop(), -self.step)
+        };
+        if distance <= 0 {
+            0
+        } else {
+            ((distance + step - 1) / step) as usize
+        }
+    }
+
+    pub fn get(&self, i: usize) -> Option<i64> {
+        (i < self.len()).then(|| self.start + i as i64 * self.step)
+    }
+
+    pub fn contains(&self, n: f64) -> bool {
+        if n.fract() != 0.0 || n.abs() > MAX_EXACT {
+            return false;
+        }
+        let n = n as i64;
+        let in_bounds = if self.step > 0 {
+            self.start <= n && n < self.stop()
+        } else {
+            self.stop() < n && n <= self.start
+        };
+        in_bounds && (n - self.start) % self.step == 0
+    }
+
+    /// The positions this range selects from a list of `len` elements.
+    /// Negative bounds count from the end and positions outside the list
+    /// are skipped, as with `xs[start:end]`.
+    pub fn positions(&self, len: usize) -> impl Iterator<Item = usize> {
+        let len = len as i64;
+        let from_end = |n: i64| if n < 0 { n + len } else { n };
+        let resolved = Range {
+            start: from_end(self.start),
+            end: from_end(self.end),
+            ..*self
+        };
+        // Work out which steps land inside the list rather than walking up
+        // to them, since a bound can be up to 2^53 positions away.
+        let Range { start, step, .. } = resolved;
+        let (first, last) = if step > 0 {
+            (div_ceil(-start, step), div_ceil(len - start, step))
+        } else {
+            (div_ceil(start - len + 1, -step), div_ceil(start + 1, -step))
+        };
+        let first = first.max(0);
+        let last = last.min(resolved.len() as i64).max(first);
+        (first..last).map(move |i| (start + i * step) as usize)
+    }
+}
+
+/// `a / b` rounded up, for a positive `b`.
+fn div_ceil(a: i64, b: i64) -> i64 {
+    a.div_euclid(b) + i64::from(a.rem_euclid(b) != 0)
+}
+
+/// Integers beyond this can't all be represented as numbers.
+const MAX_EXACT: f64 = 9_007_199_254_740_992.0;
+
+fn bound(value: &Value) -> Result<i64, String> {
+    match value {
+        Value::Number(n) if n.fract() == 0.0 && n.abs() <= MAX_EXACT => Ok(*n as i64),
+        Value::Number(n) if n.fract() == 0.0 => Err(format!(
+            "Range bound {} is outside the supported range -2^53 to 2^53.",
+            value
+        )),
+        _ => Err("Range bounds must be integers.".to_string()),
+    }
+}
+
+impl fmt::Display for Range {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if self.step != 1 {
+            write!(f, "range({}, {}, {})", self.start, self.end, self.step)
+        } else if self.inclusive {
+            write!(f, "{}..={}", self.start, self.end)
+        } else {
+            write!(f, "{}..{}", self.start, self.end)
+        }
+    }
+}
+
+/// The global `range(start, end)` / `range(start, end, step)` function.
+pub fn function() -> Value {
+    Value::Native(Rc::new(NativeMethod {
+        name: "range",
+        arity: 2..=3,
+        receiver: Value::Nil,
+        function: range,
+    }))
+}
+
+fn range(_receiver: &Value, arguments: Vec<Value>) -> Result<Value, String> {
+    let range = match arguments.as_slice() {
+        [start, end] => Range::new(start, end, false)?,
+        [start, end, step] => Range::with_step(start, end, step)?,
+        _ => unreachable!("arity is checked before the call"),
+    };
+    Ok(Value::Range(range))
+}
+
+/// The methods ranges have, by name, with their arity.
+const METHODS: &[(&str, usize, NativeFn)] = &[("contains", 1, contains), ("len", 0, len)];
+
+/// Looks up `name` on a range and binds it to the range.
+pub fn method(range: &Value, name: &str) -> Option<Value> {
+    value::bind(METHODS, range, name)
+}
+
+fn this(range: &Value) -> &Range {
+    match range {
+        Value::Range(range) => range,
+        _ => unreachable!("range methods are only bound to ranges"),
+    }
+}
+
+fn contains(range: &Value, arguments: Vec<Value>) -> Result<Value, String> {
+    let found = match arguments[0] {
+        Value::Number(n) => this(range).contains(n),
+        _ => false,
+    };
+    Ok(Value::Bool(found))
+}
+
+fn len(range: &Value, _arguments: Vec<Value>) -> Result<Value, String> {
+    Ok(Value::Number(this(range).len() as f64))
+}
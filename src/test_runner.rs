@@ -0,0 +1,257 @@
+// test_runner.rs
+//! Runs `.lox` files annotated the way the Crafting Interpreters test suite
+//! does it, and checks the interpreter's stdout, stderr and exit code:
+//!
+//! - `// expect: text` expects `text` as the next line of output.
+//! - `// expect runtime error: msg` expects `msg` on stderr, followed by
+//!   `[line N]` for the line of the comment, and exit code 70.
+//! - `// Error ...` expects `[line N] Error ...` for the line of the comment,
+//!   `// [line N] Error ...` the same for line `N`; either means exit code 65.
+//!   `[java line N]` errors apply to this interpreter, `[c line N]` ones
+//!   don't.
+//! - `// nontest` marks a file that is not a test.
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+/// What a test file expects from running it.
+#[derive(Debug, Default)]
+struct Expectations {
+    /// Expected stdout, with the source line each comes from.
+    output: Vec<(usize, String)>,
+    compile_errors: Vec<String>,
+    /// The message and the line it should be reported on.
+    runtime_error: Option<(String, usize)>,
+}
+
+impl Expectations {
+    fn exit_code(&self) -> i32 {
+        if !self.compile_errors.is_empty() {
+            65
+        } else if self.runtime_error.is_some() {
+            70
+        } else {
+            0
+        }
+    }
+
+    fn count(&self) -> usize {
+        self.output.len() + self.compile_errors.len() + usize::from(self.runtime_error.is_some())
+    }
+}
+
+/// Reads the annotations in `source`, or `None` for a `// nontest` file.
+fn parse_expectations(source: &str) -> Option<Expectations> {
+    let mut expectations = Expectations::default();
+    for (i, line) in source.lines().enumerate() {
+        let line_number = i + 1;
+        if line.contains("// nontest") {
+            return None;
+        }
+        if let Some(text) = comment_after(line, "// expect:") {
+            let text = text.strip_prefix(' ').unwrap_or(text);
+            expectations.output.push((line_number, text.to_string()));
+        } else if let Some(message) = comment_after(line, "// expect runtime error: ") {
+            expectations.runtime_error = Some((message.to_string(), line_number));
+        } else if let Some(error) = comment_after(line, "// Error") {
+            expectations
+                .compile_errors
+                .push(format!("[line {}] Error{}", line_number, error));
+        } else if let Some(rest) = comment_after(line, "// [") {
+            if let Some(error) = error_line(rest) {
+                expectations.compile_errors.push(error);
+            }
+        }
+    }
+    Some(expectations)
+}
+
+fn comment_after<'a>(line: &'a str, marker: &str) -> Option<&'a str> {
+    line.find(marker).map(|i| &line[i + marker.len()..])
+}
+
+/// Parses the rest of `// [line N] Error ...`, keeping `[java line N]` and
+/// dropping `[c line N]`.
+fn error_line(rest: &str) -> Option<String> {
+    let rest = match rest.strip_prefix("java ") {
+        Some(rest) => rest,
+        None if rest.starts_with("c ") => return None,
+        None => rest,
+    };
+    let (number, error) = rest.strip_prefix("line ")?.split_once("] ")?;
+    let number: usize = number.parse().ok()?;
+    error
+        .starts_with("Error")
+        .then(|| format!("[line {}] {}", number, error))
+}
+
+/// Checks one run against the expectations, returning what went wrong.
+fn validate(
+    expectations: &Expectations,
+    stdout: &str,
+    stderr: &str,
+    exit_code: Option<i32>,
+) -> Vec<String> {
+    let mut failures = Vec::new();
+    let errors: Vec<&str> = stderr.lines().collect();
+
+    if let Some((message, line)) = &expectations.runtime_error {
+        match errors.first() {
+            None => failures.push(format!(
+                "Expected runtime error '{}' and got none.",
+                message
+            )),
+            Some(first) if first != message => failures.push(format!(
+                "Expected runtime error '{}' and got: {}",
+                message, first
+            )),
+            Some(_) => {
+                let trace = format!("[line {}]", line);
+                if !errors[1..].iter().any(|error| error.starts_with(&trace)) {
+                    failures.push(format!(
+                        "Expected runtime error on line {} but got: {}",
+                        line,
+                        errors[1..].join(" / ")
+                    ));
+                }
+            }
+        }
+    } else {
+        let mut found = Vec::new();
+        for error in &errors {
+            if expectations.compile_errors.iter().any(|e| e == error) {
+                found.push(*error);
+            } else if error.starts_with("[line ") && error.contains("] Error") {
+                failures.push(format!("Unexpected error: {}", error));
+            } else {
+                failures.push(format!("Unexpected output on stderr: {}", error));
+            }
+        }
+        for expected in &expectations.compile_errors {
+            if !found.contains(&expected.as_str()) {
+                failures.push(format!("Missing expected error: {}", expected));
+            }
+        }
+    }
+
+    let expected_code = expectations.exit_code();
+    if exit_code != Some(expected_code) {
+        failures.push(match exit_code {
+            Some(code) => format!("Expected exit code {} and got {}.", expected_code, code),
+            None => format!("Expected exit code {} and got a signal.", expected_code),
+        });
+    }
+
+    let lines: Vec<&str> = stdout.lines().collect();
+    for (i, actual) in lines.iter().enumerate() {
+        match expectations.output.get(i) {
+            None => failures.push(format!("Got output '{}' when none was expected.", actual)),
+            Some((line, expected)) if expected != actual => failures.push(format!(
+                "Expected output '{}' on line {} and got '{}'.",
+                expected, line, actual
+            )),
+            Some(_) => {}
+        }
+    }
+    for (line, expected) in expectations.output.iter().skip(lines.len()) {
+        failures.push(format!(
+            "Missing expected output '{}' on line {}.",
+            expected, line
+        ));
+    }
+    failures
+}
+
+/// All `.lox` files under `dir`, sorted so runs are reproducible.
+fn find_tests(dir: &Path, tests: &mut Vec<PathBuf>) -> std::io::Result<()> {
+    let mut entries = fs::read_dir(dir)?
+        .map(|entry| entry.map(|entry| entry.path()))
+        .collect::<std::io::Result<Vec<_>>>()?;
+    entries.sort();
+    for path in entries {
+        if path.is_dir() {
+            find_tests(&path, tests)?;
+        } else if path.extension().is_some_and(|ext| ext == "lox") {
+            tests.push(path);
+        }
+    }
+    Ok(())
+}
+
+/// Runs every test under `dir` with this executable's `run` command and
+/// prints failures and a per-directory summary. Returns whether all passed.
+pub fn run(dir: &Path) -> bool {
+    let mut tests = Vec::new();
+    if let Err(e) = find_tests(dir, &mut tests) {
+        eprintln!("Failed to read directory {}: {}", dir.display(), e);
+        std::process::exit(74);
+    }
+    let interpreter = std::env::current_exe().unwrap_or_else(|e| {
+        eprintln!("Cannot find the interpreter executable: {}", e);
+        std::process::exit(74);
+    });
+
+    // Pass and total counts per top-level subdirectory, e.g. per chapter.
+    let mut chapters: BTreeMap<String, (usize, usize)> = BTreeMap::new();
+    let (mut passed, mut failed, mut expectations_checked) = (0, 0, 0);
+    for path in &tests {
+        let relative = path.strip_prefix(dir).unwrap_or(path);
+        let source = match fs::read_to_string(path) {
+            Ok(source) => source,
+            Err(e) => {
+                println!(
+                    "FAIL {}\n     Failed to read file: {}",
+                    relative.display(),
+                    e
+                );
+                failed += 1;
+                continue;
+            }
+        };
+        let Some(expectations) = parse_expectations(&source) else {
+            continue;
+        };
+
+        let failures = match Command::new(&interpreter).arg("run").arg(path).output() {
+            Ok(output) => validate(
+                &expectations,
+                &String::from_utf8_lossy(&output.stdout),
+                &String::from_utf8_lossy(&output.stderr),
+                output.status.code(),
+            ),
+            Err(e) => vec![format!("Failed to run the interpreter: {}", e)],
+        };
+
+        let chapter = match relative.parent().and_then(|p| p.components().next()) {
+            Some(component) => component.as_os_str().to_string_lossy().into_owned(),
+            None => ".".to_string(),
+        };
+        let counts = chapters.entry(chapter).or_default();
+        counts.1 += 1;
+        if failures.is_empty() {
+            counts.0 += 1;
+            passed += 1;
+            expectations_checked += expectations.count();
+        } else {
+            failed += 1;
+            println!("FAIL {}", relative.display());
+            for failure in failures {
+                println!("     {}", failure);
+            }
+        }
+    }
+
+    for (chapter, (chapter_passed, total)) in &chapters {
+        println!("{:<24} {:>4} / {:<4}", chapter, chapter_passed, total);
+    }
+    if failed == 0 {
+        println!(
+            "All {} tests passed ({} expectations).",
+            passed, expectations_checked
+        );
+    } else {
+        println!("{} tests passed. {} tests failed.", passed, failed);
+    }
+    failed == 0
+}
@@ -0,0 +1,212 @@
+// testing.rs
+//! Random input generators shared by the property tests. Everything is
+//! seeded, so a failing case reproduces on every run.
+
+/// A xorshift generator, so the tests need no random-number crate.
+pub struct Rng(u64);
+
+impl Rng {
+    pub fn new(seed: u64) -> Self {
+        // Xorshift gets stuck at zero.
+        Rng(seed.max(1))
+    }
+
+    pub fn next(&mut self) -> u64 {
+        self.0 ^= self.0 << 13;
+        self.0 ^= self.0 >> 7;
+        self.0 ^= self.0 << 17;
+        self.0
+    }
+
+    pub fn below(&mut self, n: usize) -> usize {
+        (self.next() % n as u64) as usize
+    }
+
+    /// True one time in `n`.
+    pub fn one_in(&mut self, n: usize) -> bool {
+        self.below(n) == 0
+    }
+
+    pub fn pick<'a, T>(&mut self, items: &'a [T]) -> &'a T {
+        &items[self.below(items.len())]
+    }
+}
+
+const INFIX: &[&str] = &[
+    ",", "or", "and", "==", "!=", "<", "<=", ">", ">=", "..", "..=", "+", "-", "*", "/", "%", "**",
+];
+
+/// A well-formed program: print and expression statements over every
+/// expression form, including functions and `match`, with arbitrary
+/// spacing, comments and redundant parentheses.
+pub fn program(rng: &mut Rng, statements: usize) -> String {
+    let mut out = String::new();
+    for _ in 0..statements {
+        out.push_str(&space(rng));
+        if rng.one_in(2) {
+            out.push_str("print ");
+        }
+        let depth = rng.below(5);
+        let expression = expression(rng, depth);
+        if expression.starts_with("match") {
+            // It would parse as a `match` statement.
+            out.push_str(&format!("({})", expression));
+        } else {
+            out.push_str(&expression);
+        }
+        out.push_str(&space(rng));
+        out.push(';');
+        out.push_str(&space(rng));
+    }
+    out
+}
+
+/// A well-formed expression. Operands are only parenthesized at random, so
+/// the text exercises the parser's precedence rules rather than spelling
+/// out the tree.
+pub fn expression(rng: &mut Rng, depth: usize) -> String {
+    let text = if depth == 0 {
+        literal(rng)
+    } else {
+        match rng.below(8) {
+            0 => literal(rng),
+            1 => {
+                let operator = *rng.pick(&["-", "!"]);
+                let operand = expression(rng, depth - 1);
+                // `- -1` must not run together.
+                let gap = if operand.starts_with('-') || rng.one_in(4) {
+                    " "
+                } else {
+                    ""
+                };
+                format!("{}{}{}", operator, gap, operand)
+            }
+            2 => format!(
+                "{}{}?{}{}{}:{}{}",
+                expression(rng, depth - 1),
+                space(rng),
+                space(rng),
+                expression(rng, depth - 1),
+                space(rng),
+                space(rng),
+                expression(rng, depth - 1)
+            ),
+            3 => {
+                let params = *rng.pick(&["", "a", "a, b"]);
+                let body = expression(rng, depth - 1);
+                if rng.one_in(2) {
+                    format!(
+                        "fun ({}) {{{}return {};{}}}",
+                        params,
+                        space(rng),
+                        body,
+                        space(rng)
+                    )
+                } else {
+                    // The body of an arrow takes in any operators after it,
+                    // so it stays parenthesized, as the unparser prints it.
+                    format!("(({}){}=>{}{})", params, space(rng), space(rng), body)
+                }
+            }
+            4 => {
+                let subject = expression(rng, depth - 1);
+                let arms: Vec<String> = (0..1 + rng.below(3))
+                    .map(|_| {
+                        let pattern = *rng.pick(&["_", "1", "\"s\"", "-2", "[a, ..b]", "[[_], c]"]);
+                        let mut value = expression(rng, depth - 1);
+                        if value.contains(',') {
+                            // A comma would end the arm.
+                            value = format!("({})", value);
+                        }
+                        format!("{}{}=> {}", space(rng), pattern, value)
+                    })
+                    .collect();
+                let comma = if rng.one_in(2) { "," } else { "" };
+                format!(
+                    "match ({}) {{{}{}{}}}",
+                    subject,
+                    arms.join(","),
+                    comma,
+                    space(rng)
+                )
+            }
+            _ => {
+                let operator = *rng.pick(INFIX);
+                format!(
+                    "{} {} {}",
+                    expression(rng, depth - 1),
+                    operator,
+                    expression(rng, depth - 1)
+                )
+            }
+        }
+    };
+    if depth > 0 && rng.one_in(3) {
+        format!("({})", text)
+    } else {
+        text
+    }
+}
+
+fn literal(rng: &mut Rng) -> String {
+    match rng.below(8) {
+        0 => "nil".to_string(),
+        1 => "true".to_string(),
+        2 => "false".to_string(),
+        3 => format!("\"s{}\"", rng.below(100)),
+        4 => format!("{}.{}", rng.below(100), rng.below(100)),
+        _ => rng.below(1000).to_string(),
+    }
+}
+
+/// Insignificant text between tokens: nothing, spaces, newlines or comments.
+fn space(rng: &mut Rng) -> String {
+    match rng.below(8) {
+        0 => "\n".to_string(),
+        1 => " // note\n".to_string(),
+        2 => " /* note */ ".to_string(),
+        3 | 4 => " ".to_string(),
+        _ => String::new(),
+    }
+}
+
+/// An arithmetic expression over small numbers with `+ - * / % **`, unary
+/// minus and random parentheses.
+pub fn arithmetic(rng: &mut Rng, depth: usize) -> String {
+    if depth == 0 || rng.one_in(4) {
+        return match rng.below(3) {
+            0 => format!("{}.{}", rng.below(10), rng.below(100)),
+            _ => rng.below(20).to_string(),
+        };
+    }
+    let text = match rng.below(5) {
+        0 => format!("- {}", arithmetic(rng, depth - 1)),
+        _ => format!(
+            "{} {} {}",
+            arithmetic(rng, depth - 1),
+            rng.pick(&["+", "-", "*", "/", "%", "**"]),
+            arithmetic(rng, depth - 1)
+        ),
+    };
+    if rng.one_in(3) {
+        format!("({})", text)
+    } else {
+        text
+    }
+}
+
+/// Arbitrary text that leans towards the characters the scanner treats
+/// specially, including unterminated strings and comments.
+pub fn noise(rng: &mut Rng, len: usize) -> String {
+    const PIECES: &[&str] = &[
+        "\"", "/", "*", "/*", "*/", "//", "\n", "\r", "\t", " ", ".", "1", "0.", "12.5", "a", "_x",
+        "and", "print", "!", "=", "<", ">", "(", ")", "{", "}", ";", "?", ":", "@", "#", "\0", "é",
+        "λ", "🦀", "\u{feff}",
+    ];
+    if rng.one_in(3) {
+        // Raw bytes, as a file read lossily would give them.
+        let bytes: Vec<u8> = (0..len).map(|_| rng.next() as u8).collect();
+        return String::from_utf8_lossy(&bytes).into_owned();
+    }
+    (0..len).map(|_| *rng.pick(PIECES)).collect()
+}
@@ -1,22 +1,36 @@
-use std::io::{self, Write};
-
 #[derive(Debug, PartialEq, Clone)]
 pub enum TokenType {
     LeftParen,
     RightParen,
     LeftBrace,
     RightBrace,
+    LeftBracket,
+    RightBracket,
     Comma,
     Dot,
+    DotDot,
+    DotDotEqual,
     Minus,
+    MinusEqual,
+    MinusMinus,
     Plus,
+    PlusEqual,
+    PlusPlus,
     Semicolon,
+    Question,
+    Colon,
     Star,
+    StarEqual,
+    StarStar,
     Slash,
+    SlashEqual,
+    Percent,
+    PercentEqual,
     Bang,
     BangEqual,
     Equal,
     EqualEqual,
+    EqualGreater,
     Less,
     LessEqual,
     Greater,
@@ -28,19 +42,31 @@ pub enum TokenType {
 
     // Reserved words
     And,
+    As,
+    Break,
+    Catch,
     Class,
+    Continue,
     Else,
+    Export,
     False,
+    Finally,
     For,
+    From,
     Fun,
     If,
+    Import,
+    In,
+    Match,
     Nil,
     Or,
     Print,
     Return,
     Super,
     This,
+    Throw,
     True,
+    Try,
     Var,
     While,
 }
@@ -52,17 +78,33 @@ impl std::fmt::Display for TokenType {
             TokenType::RightParen => write!(f, "RIGHT_PAREN ) null"),
             TokenType::LeftBrace => write!(f, "LEFT_BRACE {{ null"),
             TokenType::RightBrace => write!(f, "RIGHT_BRACE }} null"),
+            TokenType::LeftBracket => write!(f, "LEFT_BRACKET [ null"),
+            TokenType::RightBracket => write!(f, "RIGHT_BRACKET ] null"),
             TokenType::Comma => write!(f, "COMMA , null"),
             TokenType::Dot => write!(f, "DOT . null"),
+            TokenType::DotDot => write!(f, "DOT_DOT .. null"),
+            TokenType::DotDotEqual => write!(f, "DOT_DOT_EQUAL ..= null"),
             TokenType::Minus => write!(f, "MINUS - null"),
+            TokenType::MinusEqual => write!(f, "MINUS_EQUAL -= null"),
+            TokenType::MinusMinus => write!(f, "MINUS_MINUS -- null"),
             TokenType::Plus => write!(f, "PLUS + null"),
+            TokenType::PlusEqual => write!(f, "PLUS_EQUAL += null"),
+            TokenType::PlusPlus => write!(f, "PLUS_PLUS ++ null"),
             TokenType::Semicolon => write!(f, "SEMICOLON ; null"),
+            TokenType::Question => write!(f, "QUESTION ? null"),
+            TokenType::Colon => write!(f, "COLON : null"),
             TokenType::Star => write!(f, "STAR * null"),
+            TokenType::StarEqual => write!(f, "STAR_EQUAL *= null"),
+            TokenType::StarStar => write!(f, "STAR_STAR ** null"),
             TokenType::Slash => write!(f, "SLASH / null"),
+            TokenType::SlashEqual => write!(f, "SLASH_EQUAL /= null"),
+            TokenType::Percent => write!(f, "PERCENT % null"),
+            TokenType::PercentEqual => write!(f, "PERCENT_EQUAL %= null"),
             TokenType::Bang => write!(f, "BANG ! null"),
             TokenType::BangEqual => write!(f, "BANG_EQUAL != null"),
             TokenType::Equal => write!(f, "EQUAL = null"),
             TokenType::EqualEqual => write!(f, "EQUAL_EQUAL == null"),
+            TokenType::EqualGreater => write!(f, "EQUAL_GREATER => null"),
             TokenType::Less => write!(f, "LESS < null"),
             TokenType::LessEqual => write!(f, "LESS_EQUAL <= null"),
             TokenType::Greater => write!(f, "GREATER > null"),
@@ -72,31 +114,97 @@ impl std::fmt::Display for TokenType {
             TokenType::Identifier => write!(f, "IDENTIFIER"),
             TokenType::Eof => write!(f, "EOF  null"),
             TokenType::And => write!(f, "AND and null"),
+            TokenType::As => write!(f, "AS as null"),
+            TokenType::Break => write!(f, "BREAK break null"),
+            TokenType::Catch => write!(f, "CATCH catch null"),
             TokenType::Class => write!(f, "CLASS class null"),
+            TokenType::Continue => write!(f, "CONTINUE continue null"),
             TokenType::Else => write!(f, "ELSE else null"),
+            TokenType::Export => write!(f, "EXPORT export null"),
             TokenType::False => write!(f, "FALSE false null"),
+            TokenType::Finally => write!(f, "FINALLY finally null"),
             TokenType::For => write!(f, "FOR for null"),
+            TokenType::From => write!(f, "FROM from null"),
             TokenType::Fun => write!(f, "FUN fun null"),
             TokenType::If => write!(f, "IF if null"),
+            TokenType::Import => write!(f, "IMPORT import null"),
+            TokenType::In => write!(f, "IN in null"),
+            TokenType::Match => write!(f, "MATCH match null"),
             TokenType::Nil => write!(f, "NIL nil null"),
             TokenType::Or => write!(f, "OR or null"),
             TokenType::Print => write!(f, "PRINT print null"),
             TokenType::Return => write!(f, "RETURN return null"),
             TokenType::Super => write!(f, "SUPER super null"),
             TokenType::This => write!(f, "THIS this null"),
+            TokenType::Throw => write!(f, "THROW throw null"),
             TokenType::True => write!(f, "TRUE true null"),
+            TokenType::Try => write!(f, "TRY try null"),
             TokenType::Var => write!(f, "VAR var null"),
             TokenType::While => write!(f, "WHILE while null"),
         }
     }
 }
 
+/// A half-open byte range `start..end` into the source text.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct Span {
+    pub start: usize,
+    pub end: usize,
+}
+
+impl Span {
+    pub fn new(start: usize, end: usize) -> Self {
+        Span { start, end }
+    }
+
+    /// The smallest span covering both `self` and `other`.
+    pub fn to(self, other: Span) -> Span {
+        Span::new(self.start.min(other.start), self.end.max(other.end))
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Token {
+    pub token_type: TokenType,
+    pub lexeme: String,
+    pub literal: String,
+    pub line: usize,
+    pub span: Span,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TriviaKind {
+    LineComment,
+    BlockComment,
+    /// Spaces, tabs and newlines. Only recorded by a lossless scanner.
+    Whitespace,
+    /// Characters that could not be scanned, such as an unterminated string.
+    /// Only recorded by a lossless scanner.
+    Skipped,
+}
+
+/// Source text that carries no meaning for the parser but that tools such as
+/// the formatter need to keep.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Trivia {
+    pub kind: TriviaKind,
+    pub text: String,
+    /// Line the trivia starts on.
+    pub line: usize,
+    pub span: Span,
+}
+
 pub struct Scanner<'a> {
     source: &'a str,
-    tokens: Vec<(TokenType, String, String)>,
+    tokens: Vec<Token>,
+    trivia: Vec<Trivia>,
     start: usize,
     current: usize,
     line: usize,
+    start_line: usize,
+    /// Record whitespace and skipped characters as trivia too, so that every
+    /// byte of the source belongs to a token or a trivia.
+    lossless: bool,
     pub has_errors: bool,
 }
 
@@ -105,33 +213,51 @@ impl<'a> Scanner<'a> {
         Scanner {
             source,
             tokens: Vec::new(),
+            trivia: Vec::new(),
             start: 0,
             current: 0,
             line: 1,
+            start_line: 1,
+            lossless: false,
             has_errors: false,
         }
     }
 
-    pub fn scan_tokens(&mut self) -> &Vec<(TokenType, String, String)> {
+    pub fn lossless(source: &'a str) -> Self {
+        Scanner {
+            lossless: true,
+            ..Scanner::new(source)
+        }
+    }
+
+    pub fn scan_tokens(&mut self) -> &Vec<Token> {
         while !self.is_at_end() {
             self.start = self.current;
+            self.start_line = self.line;
             self.scan_token();
         }
 
         // Only add EOF token if all tokens have been processed
-        if let Some(&(ref last_type, _, _)) = self.tokens.last() {
-            if *last_type != TokenType::Eof {
-                self.tokens
-                    .push((TokenType::Eof, String::new(), String::new()));
-            }
-        } else {
-            self.tokens
-                .push((TokenType::Eof, String::new(), String::new()));
+        if self.tokens.last().map(|t| &t.token_type) != Some(&TokenType::Eof) {
+            self.start = self.current;
+            self.add_token_with_literal(TokenType::Eof, String::new(), String::new());
         }
 
         &self.tokens
     }
 
+    /// Scans the whole source and takes ownership of the tokens.
+    pub fn into_tokens(mut self) -> Vec<Token> {
+        self.scan_tokens();
+        self.tokens
+    }
+
+    /// Like `into_tokens`, but also returns the comments in source order.
+    pub fn into_tokens_with_trivia(mut self) -> (Vec<Token>, Vec<Trivia>) {
+        self.scan_tokens();
+        (self.tokens, self.trivia)
+    }
+
     fn scan_token(&mut self) {
         let c = self.advance().unwrap_or('\0');
         match c {
@@ -139,18 +265,71 @@ impl<'a> Scanner<'a> {
             ')' => self.add_token(TokenType::RightParen),
             '{' => self.add_token(TokenType::LeftBrace),
             '}' => self.add_token(TokenType::RightBrace),
+            '[' => self.add_token(TokenType::LeftBracket),
+            ']' => self.add_token(TokenType::RightBracket),
             ',' => self.add_token(TokenType::Comma),
-            '.' => self.add_token(TokenType::Dot),
-            '-' => self.add_token(TokenType::Minus),
-            '+' => self.add_token(TokenType::Plus),
+            '.' => {
+                let token_type = if !self.match_char('.') {
+                    TokenType::Dot
+                } else if self.match_char('=') {
+                    TokenType::DotDotEqual
+                } else {
+                    TokenType::DotDot
+                };
+                self.add_token(token_type);
+            }
+            '-' => {
+                let token_type = if self.match_char('-') {
+                    TokenType::MinusMinus
+                } else if self.match_char('=') {
+                    TokenType::MinusEqual
+                } else {
+                    TokenType::Minus
+                };
+                self.add_token(token_type);
+            }
+            '+' => {
+                let token_type = if self.match_char('+') {
+                    TokenType::PlusPlus
+                } else if self.match_char('=') {
+                    TokenType::PlusEqual
+                } else {
+                    TokenType::Plus
+                };
+                self.add_token(token_type);
+            }
             ';' => self.add_token(TokenType::Semicolon),
-            '*' => self.add_token(TokenType::Star),
+            '?' => self.add_token(TokenType::Question),
+            ':' => self.add_token(TokenType::Colon),
+            '*' => {
+                let token_type = if self.match_char('*') {
+                    TokenType::StarStar
+                } else if self.match_char('=') {
+                    TokenType::StarEqual
+                } else {
+                    TokenType::Star
+                };
+                self.add_token(token_type);
+            }
+            '%' => {
+                let token_type = if self.match_char('=') {
+                    TokenType::PercentEqual
+                } else {
+                    TokenType::Percent
+                };
+                self.add_token(token_type);
+            }
             '/' => {
                 if self.match_char('/') {
                     // A comment goes until the end of the line.
                     while self.peek() != Some('\n') && !self.is_at_end() {
                         self.advance();
                     }
+                    self.add_trivia(TriviaKind::LineComment);
+                } else if self.match_char('*') {
+                    self.block_comment();
+                } else if self.match_char('=') {
+                    self.add_token(TokenType::SlashEqual);
                 } else {
                     self.add_token(TokenType::Slash);
                 }
@@ -166,6 +345,8 @@ impl<'a> Scanner<'a> {
             '=' => {
                 let token_type = if self.match_char('=') {
                     TokenType::EqualEqual
+                } else if self.match_char('>') {
+                    TokenType::EqualGreater
                 } else {
                     TokenType::Equal
                 };
@@ -188,58 +369,53 @@ impl<'a> Scanner<'a> {
                 self.add_token(token_type);
             }
             '"' => self.string(),
-            ' ' | '\r' | '\t' => {} // Ignore whitespace
-            '\n' => self.line += 1,
+            ' ' | '\r' | '\t' | '\n' => self.whitespace(c),
             _ if self.is_digit(c) => self.number(),
             _ if self.is_alpha(c) => self.identifier(),
             _ => {
                 self.report_error(c);
                 self.has_errors = true;
+                self.add_lossless_trivia(TriviaKind::Skipped);
             }
         }
     }
 
+    // `current` is a byte offset, so step over whole UTF-8 characters.
     fn advance(&mut self) -> Option<char> {
-        if self.is_at_end() {
-            None
-        } else {
-            let c = self.source.chars().nth(self.current);
-            self.current += 1;
-            c
-        }
+        let c = self.peek()?;
+        self.current += c.len_utf8();
+        Some(c)
     }
 
     fn match_char(&mut self, expected: char) -> bool {
         if self.peek() == Some(expected) {
-            self.current += 1;
+            self.current += expected.len_utf8();
             return true;
         }
         false
     }
 
     fn peek(&self) -> Option<char> {
-        if self.is_at_end() {
-            None
-        } else {
-            self.source.chars().nth(self.current)
-        }
+        self.source[self.current..].chars().next()
     }
 
     fn peek_next(&self) -> Option<char> {
-        if self.current + 1 >= self.source.len() {
-            None
-        } else {
-            self.source.chars().nth(self.current + 1)
-        }
+        self.source[self.current..].chars().nth(1)
     }
 
     fn add_token(&mut self, token_type: TokenType) {
         let text = self.source[self.start..self.current].to_string();
-        self.tokens.push((token_type, text.clone(), text));
+        self.add_token_with_literal(token_type, text.clone(), text);
     }
 
     fn add_token_with_literal(&mut self, token_type: TokenType, lexeme: String, literal: String) {
-        self.tokens.push((token_type, lexeme, literal));
+        self.tokens.push(Token {
+            token_type,
+            lexeme,
+            literal,
+            line: self.line,
+            span: Span::new(self.start, self.current),
+        });
     }
 
     fn is_at_end(&self) -> bool {
@@ -247,13 +423,7 @@ impl<'a> Scanner<'a> {
     }
 
     fn report_error(&self, c: char) {
-        writeln!(
-            io::stderr(),
-            "[line {}] Error: Unexpected character: {}",
-            self.line,
-            c
-        )
-        .unwrap();
+        eprintln!("[line {}] Error: Unexpected character: {}", self.line, c);
     }
 
     fn string(&mut self) {
@@ -265,13 +435,9 @@ impl<'a> Scanner<'a> {
         }
 
         if self.is_at_end() {
-            writeln!(
-                io::stderr(),
-                "[line {}] Error: Unterminated string.",
-                self.line
-            )
-            .unwrap();
+            eprintln!("[line {}] Error: Unterminated string.", self.line);
             self.has_errors = true;
+            self.add_lossless_trivia(TriviaKind::Skipped);
             return;
         }
 
@@ -283,16 +449,74 @@ impl<'a> Scanner<'a> {
         self.add_token_with_literal(TokenType::String, format!("\"{}\"", value), value);
     }
 
+    fn block_comment(&mut self) {
+        // Block comments nest, so track how many are still open.
+        let mut depth = 1;
+
+        while depth > 0 {
+            match self.advance() {
+                None => {
+                    eprintln!(
+                        "[line {}] Error: Unterminated block comment.",
+                        self.start_line
+                    );
+                    self.has_errors = true;
+                    break;
+                }
+                Some('\n') => self.line += 1,
+                Some('/') if self.peek() == Some('*') => {
+                    self.advance();
+                    depth += 1;
+                }
+                Some('*') if self.peek() == Some('/') => {
+                    self.advance();
+                    depth -= 1;
+                }
+                Some(_) => {}
+            }
+        }
+
+        self.add_trivia(TriviaKind::BlockComment);
+    }
+
+    fn whitespace(&mut self, first: char) {
+        if first == '\n' {
+            self.line += 1;
+        }
+        while let Some(c @ (' ' | '\r' | '\t' | '\n')) = self.peek() {
+            if c == '\n' {
+                self.line += 1;
+            }
+            self.advance();
+        }
+        self.add_lossless_trivia(TriviaKind::Whitespace);
+    }
+
+    fn add_trivia(&mut self, kind: TriviaKind) {
+        self.trivia.push(Trivia {
+            kind,
+            text: self.source[self.start..self.current].to_string(),
+            line: self.start_line,
+            span: Span::new(self.start, self.current),
+        });
+    }
+
+    fn add_lossless_trivia(&mut self, kind: TriviaKind) {
+        if self.lossless {
+            self.add_trivia(kind);
+        }
+    }
+
     fn number(&mut self) {
-        while self.peek().map_or(false, |c| self.is_digit(c)) {
+        while self.peek().is_some_and(|c| self.is_digit(c)) {
             self.advance();
         }
 
         let mut has_decimal = false;
-        if self.peek() == Some('.') && self.peek_next().map_or(false, |c| self.is_digit(c)) {
+        if self.peek() == Some('.') && self.peek_next().is_some_and(|c| self.is_digit(c)) {
             has_decimal = true;
             self.advance(); // consume the "."
-            while self.peek().map_or(false, |c| self.is_digit(c)) {
+            while self.peek().is_some_and(|c| self.is_digit(c)) {
                 self.advance();
             }
         }
@@ -315,7 +539,7 @@ impl<'a> Scanner<'a> {
     }
 
     fn is_digit(&self, c: char) -> bool {
-        c >= '0' && c <= '9'
+        c.is_ascii_digit()
     }
 
     fn is_alpha(&self, c: char) -> bool {
@@ -329,19 +553,31 @@ impl<'a> Scanner<'a> {
     fn identifier_type(&self, identifier: &str) -> TokenType {
         match identifier {
             "and" => TokenType::And,
+            "as" => TokenType::As,
+            "break" => TokenType::Break,
+            "catch" => TokenType::Catch,
             "class" => TokenType::Class,
+            "continue" => TokenType::Continue,
             "else" => TokenType::Else,
+            "export" => TokenType::Export,
             "false" => TokenType::False,
+            "finally" => TokenType::Finally,
             "for" => TokenType::For,
+            "from" => TokenType::From,
             "fun" => TokenType::Fun,
             "if" => TokenType::If,
+            "import" => TokenType::Import,
+            "in" => TokenType::In,
+            "match" => TokenType::Match,
             "nil" => TokenType::Nil,
             "or" => TokenType::Or,
             "print" => TokenType::Print,
             "return" => TokenType::Return,
             "super" => TokenType::Super,
             "this" => TokenType::This,
+            "throw" => TokenType::Throw,
             "true" => TokenType::True,
+            "try" => TokenType::Try,
             "var" => TokenType::Var,
             "while" => TokenType::While,
             _ => TokenType::Identifier,
@@ -349,7 +585,7 @@ impl<'a> Scanner<'a> {
     }
 
     fn identifier(&mut self) {
-        while self.peek().map_or(false, |c| self.is_alphanumeric(c)) {
+        while self.peek().is_some_and(|c| self.is_alphanumeric(c)) {
             self.advance();
         }
 
@@ -366,8 +602,9 @@ impl TokenType {
             TokenType::Minus => "-".to_string(),
             TokenType::Plus => "+".to_string(),
             TokenType::Star => "*".to_string(),
+            TokenType::StarStar => "**".to_string(),
             TokenType::Slash => "/".to_string(),
-            TokenType::Bang => "!".to_string(),
+            TokenType::Percent => "%".to_string(),
             TokenType::Equal => "=".to_string(),
             TokenType::Less => "<".to_string(),
             TokenType::Greater => ">".to_string(),
@@ -376,8 +613,59 @@ impl TokenType {
             TokenType::GreaterEqual => ">=".to_string(),
             TokenType::EqualEqual => "==".to_string(),
             TokenType::BangEqual => "!=".to_string(),
+            TokenType::Comma => ",".to_string(),
+            TokenType::DotDot => "..".to_string(),
+            TokenType::DotDotEqual => "..=".to_string(),
+            TokenType::And => "and".to_string(),
+            TokenType::Or => "or".to_string(),
             // ... (other matches)
             _ => format!("{:?}", self),
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::{self, Rng};
+
+    #[test]
+    fn scanner_never_panics() {
+        let mut rng = Rng::new(0x9e37_79b9_7f4a_7c15);
+        for _ in 0..3000 {
+            let len = rng.below(64);
+            let source = testing::noise(&mut rng, len);
+            let tokens = Scanner::new(&source).into_tokens();
+            assert_eq!(
+                tokens.last().map(|token| &token.token_type),
+                Some(&TokenType::Eof),
+                "{:?}",
+                source
+            );
+        }
+    }
+
+    #[test]
+    fn lossless_scan_covers_every_byte() {
+        let mut rng = Rng::new(0x5851_f42d_4c95_7f2d);
+        for _ in 0..3000 {
+            let len = rng.below(64);
+            let source = testing::noise(&mut rng, len);
+            let (tokens, trivia) = Scanner::lossless(&source).into_tokens_with_trivia();
+            let mut spans: Vec<Span> = tokens
+                .iter()
+                .filter(|token| token.token_type != TokenType::Eof)
+                .map(|token| token.span)
+                .chain(trivia.iter().map(|trivia| trivia.span))
+                .collect();
+            spans.sort_by_key(|span| span.start);
+
+            let mut offset = 0;
+            for span in spans {
+                assert_eq!(span.start, offset, "gap or overlap in {:?}", source);
+                offset = span.end;
+            }
+            assert_eq!(offset, source.len(), "{:?}", source);
+        }
+    }
+}
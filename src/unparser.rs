@@ -0,0 +1,1128 @@
+// unparser.rs
+use crate::ast::{
+    Ast, ExprId, ExprKind, ExprVisitor, FunctionBody, Imports, LiteralValue, MatchArm, Pattern,
+    PatternKind, StmtId, StmtVisitor,
+};
+use crate::parser::{infix_precedence, Associativity, Precedence};
+use crate::tokenizer::{Token, TokenType};
+
+/// Prints the AST back as Lox source, adding parentheses only where
+/// precedence or associativity requires them. Explicit `Grouping` nodes are
+/// kept, so for a parsed tree `parse(unparse(ast))` yields the same tree.
+///
+/// Numbers print in their shortest round-trip form. Lox strings have no
+/// escapes, so a tree with a string containing `"` is an error.
+#[derive(Default)]
+pub struct Unparser {
+    /// The first string that can't be written as a Lox literal.
+    unprintable: Option<String>,
+}
+
+impl Unparser {
+    pub fn expr(ast: &Ast, expr: ExprId) -> Result<String, String> {
+        let mut unparser = Unparser::default();
+        let text = ast.accept_expr(expr, &mut unparser);
+        unparser.finish(text)
+    }
+
+    pub fn stmt(ast: &Ast, stmt: StmtId) -> Result<String, String> {
+        let mut unparser = Unparser::default();
+        let text = ast.accept_stmt(stmt, &mut unparser);
+        unparser.finish(text)
+    }
+
+    fn finish(self, text: String) -> Result<String, String> {
+        match self.unprintable {
+            None => Ok(text),
+            Some(s) => Err(format!(
+                "Can't print the string {:?} as Lox: strings can't contain '\"'.",
+                s
+            )),
+        }
+    }
+
+    fn string(&mut self, s: &str) -> String {
+        if s.contains('"') && self.unprintable.is_none() {
+            self.unprintable = Some(s.to_string());
+        }
+        format!("\"{}\"", s)
+    }
+
+    fn operand(&mut self, ast: &Ast, expr: ExprId, parenthesize: bool) -> String {
+        let text = ast.accept_expr(expr, self);
+        if parenthesize {
+            format!("({})", text)
+        } else {
+            text
+        }
+    }
+
+    /// `match (subject) { arms }`, with the arms printed already.
+    fn match_text(&mut self, ast: &Ast, subject: ExprId, arms: &[String], sep: &str) -> String {
+        let subject = ast.accept_expr(subject, self);
+        if arms.is_empty() {
+            format!("match ({}) {{}}", subject)
+        } else {
+            format!("match ({}) {{ {} }}", subject, arms.join(sep))
+        }
+    }
+
+    /// An arm's pattern and guard, up to its `=>`.
+    fn arm_head<Body>(&mut self, ast: &Ast, arm: &MatchArm<Body>) -> String {
+        let mut out = self.pattern(ast, &arm.pattern);
+        if let Some(guard) = arm.guard {
+            out.push_str(&format!(" if {}", ast.accept_expr(guard, self)));
+        }
+        out.push_str(" =>");
+        out
+    }
+
+    fn pattern(&mut self, ast: &Ast, pattern: &Pattern) -> String {
+        match &pattern.kind {
+            PatternKind::Literal(expr) => ast.accept_expr(*expr, self),
+            PatternKind::Binding(name) => name.lexeme.clone(),
+            PatternKind::List(elements, rest) => {
+                let mut parts: Vec<String> = elements
+                    .iter()
+                    .map(|element| self.pattern(ast, element))
+                    .collect();
+                if let Some(rest) = rest {
+                    parts.push(format!("..{}", rest.lexeme));
+                }
+                format!("[{}]", parts.join(", "))
+            }
+        }
+    }
+
+    /// Prints an operand of a postfix operator such as a call or index.
+    fn postfix_operand(&mut self, ast: &Ast, expr: ExprId) -> String {
+        let parens = operand_precedence(ast, expr) < Precedence::Call;
+        self.operand(ast, expr, parens)
+    }
+
+    /// Prints a list element, argument or index, which can't be a comma
+    /// expression without parentheses.
+    fn element(&mut self, ast: &Ast, expr: ExprId) -> String {
+        let parens = operand_precedence(ast, expr) < Precedence::Assignment;
+        self.operand(ast, expr, parens)
+    }
+
+    fn elements(&mut self, ast: &Ast, exprs: &[ExprId]) -> String {
+        exprs
+            .iter()
+            .map(|&expr| self.element(ast, expr))
+            .collect::<Vec<_>>()
+            .join(", ")
+    }
+
+    /// `(params) { statements }` after `fun` or a function's name, or
+    /// `(params) => body` for an arrow function.
+    fn function(&mut self, ast: &Ast, params: &[Token], body: &FunctionBody) -> String {
+        let params: Vec<&str> = params.iter().map(|param| param.lexeme.as_str()).collect();
+        match body {
+            FunctionBody::Block(statements) => {
+                format!("({}) {}", params.join(", "), self.block(ast, statements))
+            }
+            FunctionBody::Expression(expr) => {
+                let body = self.element(ast, *expr);
+                // A body starting with `{` would parse as a block.
+                if body.starts_with('{') {
+                    format!("({}) => ({})", params.join(", "), body)
+                } else {
+                    format!("({}) => {}", params.join(", "), body)
+                }
+            }
+        }
+    }
+
+    fn block(&mut self, ast: &Ast, statements: &[StmtId]) -> String {
+        if statements.is_empty() {
+            return "{}".to_string();
+        }
+        let statements: Vec<String> = statements
+            .iter()
+            .map(|&stmt| ast.accept_stmt(stmt, self))
+            .collect();
+        format!("{{ {} }}", statements.join(" "))
+    }
+
+    fn infix(&mut self, ast: &Ast, left: ExprId, operator: &Token, right: ExprId) -> String {
+        let (precedence, associativity) = infix_precedence(&operator.token_type);
+        let left_precedence = operand_precedence(ast, left);
+        let right_precedence = operand_precedence(ast, right);
+        // The operand on the side the operator does not associate towards
+        // must bind strictly tighter.
+        let (left_parens, right_parens) = match associativity {
+            Associativity::Left => (left_precedence < precedence, right_precedence <= precedence),
+            Associativity::Right => (left_precedence <= precedence, right_precedence < precedence),
+        };
+        // `-b` in `a ** -b` would swallow a following `** c`.
+        let left_parens =
+            left_parens || (precedence >= Precedence::Unary && ends_with_prefix(ast, left));
+        // A prefix operator parses at any level on the right.
+        let right_parens = right_parens && !starts_with_prefix(ast, right);
+
+        let left = self.operand(ast, left, left_parens);
+        let right = self.operand(ast, right, right_parens);
+        if operator.token_type == TokenType::Comma {
+            format!("{}, {}", left, right)
+        } else {
+            format!("{} {} {}", left, operator.lexeme, right)
+        }
+    }
+}
+
+/// How tightly a node binds, for deciding whether it needs parentheses.
+fn operand_precedence(ast: &Ast, expr: ExprId) -> Precedence {
+    match &ast.expr(expr).kind {
+        _ if starts_with_prefix(ast, expr) => Precedence::Unary,
+        ExprKind::Literal(_) | ExprKind::Grouping(_) => Precedence::Primary,
+        ExprKind::Unary(..) => unreachable!("unary nodes start with a prefix"),
+        ExprKind::Binary(_, operator, _) | ExprKind::Logical(_, operator, _) => {
+            infix_precedence(&operator.token_type).0
+        }
+        ExprKind::Conditional(..) => Precedence::Conditional,
+        ExprKind::Variable(_) | ExprKind::List(_) | ExprKind::Map(..) => Precedence::Primary,
+        ExprKind::Index(..) | ExprKind::Slice(..) | ExprKind::Get(..) | ExprKind::Call(..) => {
+            Precedence::Call
+        }
+        ExprKind::Update(..) => Precedence::Call,
+        ExprKind::Assign(..) | ExprKind::IndexSet(..) | ExprKind::CompoundAssign(..) => {
+            Precedence::Assignment
+        }
+        ExprKind::Function(_, FunctionBody::Block(_)) => Precedence::Primary,
+        // The body takes everything after `=>` that binds tighter than a
+        // comma.
+        ExprKind::Function(_, FunctionBody::Expression(_)) => Precedence::Assignment,
+        ExprKind::Match(..) => Precedence::Primary,
+    }
+}
+
+/// Whether the node prints as a prefix operator applied to an operand. A
+/// negative number literal prints as `-n`, so it counts.
+fn starts_with_prefix(ast: &Ast, expr: ExprId) -> bool {
+    match &ast.expr(expr).kind {
+        ExprKind::Unary(..) | ExprKind::Update(_, _, true) => true,
+        ExprKind::Literal(LiteralValue::Number(n)) => n.is_sign_negative(),
+        _ => false,
+    }
+}
+
+/// Whether the node's printed text ends in an unparenthesized prefix
+/// operator, which would take any tighter-binding operator after it as part
+/// of its operand.
+fn ends_with_prefix(ast: &Ast, expr: ExprId) -> bool {
+    if starts_with_prefix(ast, expr) {
+        return true;
+    }
+    match &ast.expr(expr).kind {
+        ExprKind::Binary(_, operator, right) if operator.token_type == TokenType::StarStar => {
+            ends_with_prefix(ast, *right)
+        }
+        _ => false,
+    }
+}
+
+impl ExprVisitor<String> for Unparser {
+    fn visit_literal(&mut self, _ast: &Ast, _id: ExprId, value: &LiteralValue) -> String {
+        match value {
+            LiteralValue::Number(n) => format!("{}", n),
+            LiteralValue::String(s) => self.string(s),
+            LiteralValue::Bool(b) => format!("{}", b),
+            LiteralValue::Nil => "nil".to_string(),
+        }
+    }
+
+    fn visit_grouping(&mut self, ast: &Ast, _id: ExprId, inner: ExprId) -> String {
+        format!("({})", ast.accept_expr(inner, self))
+    }
+
+    fn visit_unary(&mut self, ast: &Ast, _id: ExprId, operator: &Token, right: ExprId) -> String {
+        let parens = operand_precedence(ast, right) < Precedence::Unary;
+        let operand = self.operand(ast, right, parens);
+        // Keep `- -x` from running together into a different token.
+        if operand.starts_with(&operator.lexeme) {
+            format!("{} {}", operator.lexeme, operand)
+        } else {
+            format!("{}{}", operator.lexeme, operand)
+        }
+    }
+
+    fn visit_binary(
+        &mut self,
+        ast: &Ast,
+        _id: ExprId,
+        left: ExprId,
+        operator: &Token,
+        right: ExprId,
+    ) -> String {
+        self.infix(ast, left, operator, right)
+    }
+
+    fn visit_logical(
+        &mut self,
+        ast: &Ast,
+        _id: ExprId,
+        left: ExprId,
+        operator: &Token,
+        right: ExprId,
+    ) -> String {
+        self.infix(ast, left, operator, right)
+    }
+
+    fn visit_conditional(
+        &mut self,
+        ast: &Ast,
+        _id: ExprId,
+        condition: ExprId,
+        then_branch: ExprId,
+        else_branch: ExprId,
+    ) -> String {
+        // `?:` is right-associative, so a conditional in the condition needs
+        // parentheses while one in the else branch does not. The then branch
+        // is a full expression.
+        let condition_parens = operand_precedence(ast, condition) <= Precedence::Conditional;
+        let else_parens = operand_precedence(ast, else_branch) < Precedence::Conditional;
+        let condition = self.operand(ast, condition, condition_parens);
+        let then_branch = self.operand(ast, then_branch, false);
+        let else_branch = self.operand(ast, else_branch, else_parens);
+        format!("{} ? {} : {}", condition, then_branch, else_branch)
+    }
+
+    fn visit_variable(&mut self, _ast: &Ast, _id: ExprId, name: &Token) -> String {
+        name.lexeme.clone()
+    }
+
+    fn visit_assign(&mut self, ast: &Ast, _id: ExprId, name: &Token, value: ExprId) -> String {
+        format!("{} = {}", name.lexeme, self.element(ast, value))
+    }
+
+    fn visit_list(&mut self, ast: &Ast, _id: ExprId, elements: &[ExprId]) -> String {
+        format!("[{}]", self.elements(ast, elements))
+    }
+
+    fn visit_map(&mut self, ast: &Ast, _id: ExprId, entries: &[(ExprId, Token, ExprId)]) -> String {
+        let entries: Vec<String> = entries
+            .iter()
+            .map(|&(key, _, value)| {
+                let key = self.element(ast, key);
+                format!("{}: {}", key, self.element(ast, value))
+            })
+            .collect();
+        format!("{{{}}}", entries.join(", "))
+    }
+
+    fn visit_index(
+        &mut self,
+        ast: &Ast,
+        _id: ExprId,
+        object: ExprId,
+        _bracket: &Token,
+        index: ExprId,
+    ) -> String {
+        let object = self.postfix_operand(ast, object);
+        format!("{}[{}]", object, self.element(ast, index))
+    }
+
+    fn visit_slice(
+        &mut self,
+        ast: &Ast,
+        _id: ExprId,
+        object: ExprId,
+        _bracket: &Token,
+        start: Option<ExprId>,
+        end: Option<ExprId>,
+    ) -> String {
+        let object = self.postfix_operand(ast, object);
+        let start = start.map_or(String::new(), |start| self.element(ast, start));
+        let end = end.map_or(String::new(), |end| self.element(ast, end));
+        format!("{}[{}:{}]", object, start, end)
+    }
+
+    fn visit_index_set(
+        &mut self,
+        ast: &Ast,
+        _id: ExprId,
+        object: ExprId,
+        _bracket: &Token,
+        index: ExprId,
+        value: ExprId,
+    ) -> String {
+        let object = self.postfix_operand(ast, object);
+        let index = self.element(ast, index);
+        format!("{}[{}] = {}", object, index, self.element(ast, value))
+    }
+
+    fn visit_compound_assign(
+        &mut self,
+        ast: &Ast,
+        _id: ExprId,
+        target: ExprId,
+        operator: &Token,
+        value: ExprId,
+    ) -> String {
+        let target = ast.accept_expr(target, self);
+        format!(
+            "{} {} {}",
+            target,
+            operator.lexeme,
+            self.element(ast, value)
+        )
+    }
+
+    fn visit_update(
+        &mut self,
+        ast: &Ast,
+        _id: ExprId,
+        target: ExprId,
+        operator: &Token,
+        prefix: bool,
+    ) -> String {
+        let target = ast.accept_expr(target, self);
+        if prefix {
+            format!("{}{}", operator.lexeme, target)
+        } else {
+            format!("{}{}", target, operator.lexeme)
+        }
+    }
+
+    fn visit_get(&mut self, ast: &Ast, _id: ExprId, object: ExprId, name: &Token) -> String {
+        format!("{}.{}", self.postfix_operand(ast, object), name.lexeme)
+    }
+
+    fn visit_call(
+        &mut self,
+        ast: &Ast,
+        _id: ExprId,
+        callee: ExprId,
+        _paren: &Token,
+        arguments: &[ExprId],
+    ) -> String {
+        let callee = self.postfix_operand(ast, callee);
+        format!("{}({})", callee, self.elements(ast, arguments))
+    }
+
+    fn visit_function(
+        &mut self,
+        ast: &Ast,
+        _id: ExprId,
+        params: &[Token],
+        body: &FunctionBody,
+    ) -> String {
+        let function = self.function(ast, params, body);
+        match body {
+            FunctionBody::Block(_) => format!("fun {}", function),
+            FunctionBody::Expression(_) => function,
+        }
+    }
+
+    fn visit_match(
+        &mut self,
+        ast: &Ast,
+        _id: ExprId,
+        _keyword: &Token,
+        subject: ExprId,
+        arms: &[MatchArm<ExprId>],
+    ) -> String {
+        let arms: Vec<String> = arms
+            .iter()
+            .map(|arm| {
+                let head = self.arm_head(ast, arm);
+                format!("{} {}", head, self.element(ast, arm.body))
+            })
+            .collect();
+        self.match_text(ast, subject, &arms, ", ")
+    }
+}
+
+impl StmtVisitor<String> for Unparser {
+    fn visit_expression_stmt(&mut self, ast: &Ast, _id: StmtId, expr: ExprId) -> String {
+        let expr = ast.accept_expr(expr, self);
+        // A statement starting with `{` would parse as a block, and one
+        // starting with `match` as a `match` statement.
+        if expr.starts_with('{') || expr.starts_with("match (") {
+            format!("({});", expr)
+        } else {
+            format!("{};", expr)
+        }
+    }
+
+    fn visit_print_stmt(&mut self, ast: &Ast, _id: StmtId, expr: ExprId) -> String {
+        format!("print {};", ast.accept_expr(expr, self))
+    }
+
+    fn visit_var_stmt(
+        &mut self,
+        ast: &Ast,
+        _id: StmtId,
+        name: &Token,
+        initializer: Option<ExprId>,
+    ) -> String {
+        match initializer {
+            Some(initializer) => {
+                let initializer = ast.accept_expr(initializer, self);
+                format!("var {} = {};", name.lexeme, initializer)
+            }
+            None => format!("var {};", name.lexeme),
+        }
+    }
+
+    fn visit_function_stmt(
+        &mut self,
+        ast: &Ast,
+        _id: StmtId,
+        name: &Token,
+        function: ExprId,
+    ) -> String {
+        let ExprKind::Function(params, body) = &ast.expr(function).kind else {
+            unreachable!("function declarations hold a Function node");
+        };
+        format!("fun {}{}", name.lexeme, self.function(ast, params, body))
+    }
+
+    fn visit_return_stmt(
+        &mut self,
+        ast: &Ast,
+        _id: StmtId,
+        _keyword: &Token,
+        value: Option<ExprId>,
+    ) -> String {
+        match value {
+            Some(value) => format!("return {};", ast.accept_expr(value, self)),
+            None => "return;".to_string(),
+        }
+    }
+
+    fn visit_for_in_stmt(
+        &mut self,
+        ast: &Ast,
+        _id: StmtId,
+        name: &Token,
+        _keyword: &Token,
+        iterable: ExprId,
+        body: StmtId,
+    ) -> String {
+        let iterable = ast.accept_expr(iterable, self);
+        let body = ast.accept_stmt(body, self);
+        format!("for ({} in {}) {}", name.lexeme, iterable, body)
+    }
+
+    fn visit_break_stmt(&mut self, _ast: &Ast, _id: StmtId, _keyword: &Token) -> String {
+        "break;".to_string()
+    }
+
+    fn visit_continue_stmt(&mut self, _ast: &Ast, _id: StmtId, _keyword: &Token) -> String {
+        "continue;".to_string()
+    }
+
+    fn visit_throw_stmt(
+        &mut self,
+        ast: &Ast,
+        _id: StmtId,
+        _keyword: &Token,
+        value: ExprId,
+    ) -> String {
+        format!("throw {};", ast.accept_expr(value, self))
+    }
+
+    fn visit_try_stmt(
+        &mut self,
+        ast: &Ast,
+        _id: StmtId,
+        body: StmtId,
+        catch: Option<&(Token, StmtId)>,
+        finally: Option<StmtId>,
+    ) -> String {
+        let mut out = format!("try {}", ast.accept_stmt(body, self));
+        if let Some((name, handler)) = catch {
+            let handler = ast.accept_stmt(*handler, self);
+            out.push_str(&format!(" catch ({}) {}", name.lexeme, handler));
+        }
+        if let Some(finally) = finally {
+            out.push_str(&format!(" finally {}", ast.accept_stmt(finally, self)));
+        }
+        out
+    }
+
+    fn visit_match_stmt(
+        &mut self,
+        ast: &Ast,
+        _id: StmtId,
+        _keyword: &Token,
+        subject: ExprId,
+        arms: &[MatchArm],
+    ) -> String {
+        let arms: Vec<String> = arms
+            .iter()
+            .map(|arm| {
+                let head = self.arm_head(ast, arm);
+                format!("{} {}", head, ast.accept_stmt(arm.body, self))
+            })
+            .collect();
+        self.match_text(ast, subject, &arms, " ")
+    }
+
+    fn visit_import_stmt(
+        &mut self,
+        _ast: &Ast,
+        _id: StmtId,
+        path: &Token,
+        imports: &Imports,
+    ) -> String {
+        let path = self.string(&path.literal);
+        match imports {
+            Imports::Module(name) => format!("import {} as {};", path, name.lexeme),
+            Imports::Names(names) => {
+                let names: Vec<&str> = names.iter().map(|name| name.lexeme.as_str()).collect();
+                format!("from {} import {};", path, names.join(", "))
+            }
+        }
+    }
+
+    fn visit_export_stmt(
+        &mut self,
+        ast: &Ast,
+        _id: StmtId,
+        _keyword: &Token,
+        declaration: StmtId,
+    ) -> String {
+        format!("export {}", ast.accept_stmt(declaration, self))
+    }
+
+    fn visit_block_stmt(&mut self, ast: &Ast, _id: StmtId, statements: &[StmtId]) -> String {
+        self.block(ast, statements)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ast::{ExprKind, Root, StmtKind};
+    use crate::ast_export;
+    use crate::json::Json;
+    use crate::parser::Parser;
+    use crate::testing::Rng;
+    use crate::tokenizer::Span;
+
+    const OPERATORS: &[(TokenType, &str)] = &[
+        (TokenType::Comma, ","),
+        (TokenType::Or, "or"),
+        (TokenType::And, "and"),
+        (TokenType::EqualEqual, "=="),
+        (TokenType::BangEqual, "!="),
+        (TokenType::Less, "<"),
+        (TokenType::GreaterEqual, ">="),
+        (TokenType::DotDot, ".."),
+        (TokenType::Plus, "+"),
+        (TokenType::Minus, "-"),
+        (TokenType::Star, "*"),
+        (TokenType::Slash, "/"),
+        (TokenType::Percent, "%"),
+        (TokenType::StarStar, "**"),
+    ];
+
+    const COMPOUND: &[(TokenType, &str)] = &[
+        (TokenType::PlusEqual, "+="),
+        (TokenType::MinusEqual, "-="),
+        (TokenType::StarEqual, "*="),
+        (TokenType::SlashEqual, "/="),
+        (TokenType::PercentEqual, "%="),
+    ];
+
+    const NAMES: &[&str] = &["a", "b", "xs"];
+
+    fn token(token_type: TokenType, lexeme: &str) -> Token {
+        Token {
+            token_type,
+            lexeme: lexeme.to_string(),
+            literal: "null".to_string(),
+            line: 1,
+            span: Span::new(0, 0),
+        }
+    }
+
+    /// Builds trees directly, without going through source, covering every
+    /// node kind the parser produces except groupings.
+    struct Generator<'a> {
+        rng: &'a mut Rng,
+        ast: Ast,
+        /// How many loops enclose the statement being generated, since
+        /// `break` and `continue` only parse inside one.
+        loops: usize,
+        /// Likewise for functions and `return`.
+        functions: usize,
+    }
+
+    impl Generator<'_> {
+        fn program(&mut self, count: usize) -> Vec<StmtId> {
+            (0..count)
+                .map(|_| {
+                    if self.rng.one_in(8) {
+                        let declaration = if self.rng.one_in(2) {
+                            self.var(2)
+                        } else {
+                            self.function_declaration(2)
+                        };
+                        let keyword = token(TokenType::Export, "export");
+                        self.stmt_node(StmtKind::Export(keyword, declaration))
+                    } else {
+                        self.declaration(3)
+                    }
+                })
+                .collect()
+        }
+
+        fn declaration(&mut self, depth: usize) -> StmtId {
+            match self.rng.below(8) {
+                0 | 1 => self.var(depth),
+                2 if depth > 0 => self.function_declaration(depth - 1),
+                _ => self.stmt(depth),
+            }
+        }
+
+        fn var(&mut self, depth: usize) -> StmtId {
+            let name = self.name();
+            let initializer = (!self.rng.one_in(3)).then(|| self.expr(depth));
+            self.stmt_node(StmtKind::Var(name, initializer))
+        }
+
+        fn stmt(&mut self, depth: usize) -> StmtId {
+            let choice = if depth == 0 {
+                self.rng.below(2)
+            } else {
+                self.rng.below(10)
+            };
+            let depth = depth.saturating_sub(1);
+            let expr_depth = self.rng.below(4);
+            let kind = match choice {
+                0 => StmtKind::Print(self.expr(expr_depth)),
+                1 => StmtKind::Expression(self.expr(expr_depth)),
+                2 => {
+                    let count = self.rng.below(3);
+                    StmtKind::Block((0..count).map(|_| self.declaration(depth)).collect())
+                }
+                3 => {
+                    let name = self.name();
+                    let iterable = self.expr(expr_depth);
+                    self.loops += 1;
+                    let body = self.stmt(depth);
+                    self.loops -= 1;
+                    StmtKind::ForIn(name, token(TokenType::In, "in"), iterable, body)
+                }
+                4 if self.loops > 0 && self.rng.one_in(2) => {
+                    StmtKind::Break(token(TokenType::Break, "break"))
+                }
+                4 if self.loops > 0 => StmtKind::Continue(token(TokenType::Continue, "continue")),
+                4 => StmtKind::Throw(token(TokenType::Throw, "throw"), self.expr(expr_depth)),
+                5 => {
+                    let body = self.block(depth);
+                    let (catch, finally) = match self.rng.below(3) {
+                        0 => (true, false),
+                        1 => (false, true),
+                        _ => (true, true),
+                    };
+                    let catch = catch.then(|| (self.name(), self.block(depth)));
+                    let finally = finally.then(|| self.block(depth));
+                    StmtKind::Try(body, catch, finally)
+                }
+                6 => {
+                    let subject = self.expr(expr_depth);
+                    let count = 1 + self.rng.below(3);
+                    let arms = (0..count)
+                        .map(|_| {
+                            let pattern = self.pattern(2, &mut 0);
+                            let guard = self.rng.one_in(3).then(|| self.expr(expr_depth));
+                            let body = self.stmt(depth);
+                            MatchArm {
+                                pattern,
+                                guard,
+                                body,
+                            }
+                        })
+                        .collect();
+                    StmtKind::Match(token(TokenType::Match, "match"), subject, arms)
+                }
+                7 => {
+                    let path = Token {
+                        literal: "lib/m.lox".to_string(),
+                        ..token(TokenType::String, "\"lib/m.lox\"")
+                    };
+                    let imports = if self.rng.one_in(2) {
+                        Imports::Module(self.name())
+                    } else {
+                        let count = 1 + self.rng.below(3);
+                        Imports::Names((0..count).map(|_| self.name()).collect())
+                    };
+                    StmtKind::Import(path, imports)
+                }
+                8 if self.functions > 0 => {
+                    let value = self.rng.one_in(3).then(|| self.expr(expr_depth));
+                    StmtKind::Return(token(TokenType::Return, "return"), value)
+                }
+                _ => StmtKind::Print(self.expr(expr_depth)),
+            };
+            self.stmt_node(kind)
+        }
+
+        fn function_declaration(&mut self, depth: usize) -> StmtId {
+            let name = self.name();
+            let function = self.function(depth, false);
+            self.stmt_node(StmtKind::Function(name, function))
+        }
+
+        /// A function with a block body, or with an expression body when
+        /// `arrow` is set.
+        fn function(&mut self, depth: usize, arrow: bool) -> ExprId {
+            let count = self.rng.below(NAMES.len() + 1);
+            let params = NAMES[..count]
+                .iter()
+                .map(|name| token(TokenType::Identifier, name))
+                .collect();
+            let body = if arrow {
+                FunctionBody::Expression(self.expr(depth))
+            } else {
+                let loops = std::mem::replace(&mut self.loops, 0);
+                self.functions += 1;
+                let count = self.rng.below(3);
+                let statements = (0..count).map(|_| self.declaration(depth)).collect();
+                self.functions -= 1;
+                self.loops = loops;
+                FunctionBody::Block(statements)
+            };
+            self.ast
+                .add_expr(Span::new(0, 0), ExprKind::Function(params, body))
+        }
+
+        fn block(&mut self, depth: usize) -> StmtId {
+            let count = self.rng.below(3);
+            let statements = (0..count).map(|_| self.declaration(depth)).collect();
+            self.stmt_node(StmtKind::Block(statements))
+        }
+
+        /// A pattern whose bindings are all distinct; `bound` counts them.
+        fn pattern(&mut self, depth: usize, bound: &mut usize) -> Pattern {
+            let choice = if depth == 0 {
+                self.rng.below(2)
+            } else {
+                self.rng.below(3)
+            };
+            let mut binding = |rng: &mut Rng| {
+                if rng.one_in(3) {
+                    token(TokenType::Identifier, "_")
+                } else {
+                    *bound += 1;
+                    token(TokenType::Identifier, &format!("p{}", bound))
+                }
+            };
+            let kind = match choice {
+                0 => {
+                    let literal = self.literal();
+                    let value = self
+                        .ast
+                        .add_expr(Span::new(0, 0), ExprKind::Literal(literal));
+                    if self.rng.one_in(4) {
+                        let number = ExprKind::Literal(LiteralValue::Number(2.0));
+                        let number = self.ast.add_expr(Span::new(0, 0), number);
+                        let minus = token(TokenType::Minus, "-");
+                        PatternKind::Literal(
+                            self.ast
+                                .add_expr(Span::new(0, 0), ExprKind::Unary(minus, number)),
+                        )
+                    } else {
+                        PatternKind::Literal(value)
+                    }
+                }
+                1 => PatternKind::Binding(binding(self.rng)),
+                _ => {
+                    let rest = self.rng.one_in(2).then(|| binding(self.rng));
+                    let count = self.rng.below(3);
+                    let elements = (0..count).map(|_| self.pattern(depth - 1, bound)).collect();
+                    PatternKind::List(elements, rest)
+                }
+            };
+            Pattern {
+                span: Span::new(0, 0),
+                kind,
+            }
+        }
+
+        fn expr(&mut self, depth: usize) -> ExprId {
+            let choice = if depth == 0 { 0 } else { self.rng.below(20) };
+            let depth = depth.saturating_sub(1);
+            let kind = match choice {
+                0 => ExprKind::Literal(self.literal()),
+                1 => {
+                    let (token_type, lexeme) = if self.rng.one_in(2) {
+                        (TokenType::Minus, "-")
+                    } else {
+                        (TokenType::Bang, "!")
+                    };
+                    ExprKind::Unary(token(token_type, lexeme), self.expr(depth))
+                }
+                2 => {
+                    let condition = self.expr(depth);
+                    let then_branch = self.expr(depth);
+                    let else_branch = self.expr(depth);
+                    ExprKind::Conditional(condition, then_branch, else_branch)
+                }
+                3 => ExprKind::Variable(self.name()),
+                4 => ExprKind::Assign(self.name(), self.expr(depth)),
+                5 => {
+                    let count = self.rng.below(3);
+                    ExprKind::List((0..count).map(|_| self.expr(depth)).collect())
+                }
+                6 => {
+                    let count = self.rng.below(3);
+                    let entries = (0..count)
+                        .map(|_| {
+                            let key = self.expr(depth);
+                            (key, token(TokenType::Colon, ":"), self.expr(depth))
+                        })
+                        .collect();
+                    ExprKind::Map(entries)
+                }
+                7 => ExprKind::Index(self.expr(depth), bracket(), self.expr(depth)),
+                8 => {
+                    let object = self.expr(depth);
+                    let start = self.rng.one_in(2).then(|| self.expr(depth));
+                    let end = self.rng.one_in(2).then(|| self.expr(depth));
+                    ExprKind::Slice(object, bracket(), start, end)
+                }
+                9 => {
+                    let object = self.expr(depth);
+                    let index = self.expr(depth);
+                    ExprKind::IndexSet(object, bracket(), index, self.expr(depth))
+                }
+                10 => {
+                    let target = self.target(depth);
+                    let (token_type, lexeme) = self.rng.pick(COMPOUND).clone();
+                    let value = self.expr(depth);
+                    ExprKind::CompoundAssign(target, token(token_type, lexeme), value)
+                }
+                11 => {
+                    let target = self.target(depth);
+                    let (token_type, lexeme) = if self.rng.one_in(2) {
+                        (TokenType::PlusPlus, "++")
+                    } else {
+                        (TokenType::MinusMinus, "--")
+                    };
+                    let prefix = self.rng.one_in(2);
+                    ExprKind::Update(target, token(token_type, lexeme), prefix)
+                }
+                12 => ExprKind::Get(self.expr(depth), self.name()),
+                13 => {
+                    let callee = self.expr(depth);
+                    let count = self.rng.below(3);
+                    let arguments = (0..count).map(|_| self.expr(depth)).collect();
+                    ExprKind::Call(callee, token(TokenType::RightParen, ")"), arguments)
+                }
+                14 => {
+                    let arrow = self.rng.one_in(2);
+                    return self.function(depth, arrow);
+                }
+                15 => {
+                    let subject = self.expr(depth);
+                    let count = self.rng.below(3);
+                    let arms = (0..count)
+                        .map(|_| MatchArm {
+                            pattern: self.pattern(2, &mut 0),
+                            guard: self.rng.one_in(3).then(|| self.expr(depth)),
+                            body: self.expr(depth),
+                        })
+                        .collect();
+                    ExprKind::Match(token(TokenType::Match, "match"), subject, arms)
+                }
+                _ => {
+                    let (token_type, lexeme) = self.rng.pick(OPERATORS).clone();
+                    let left = self.expr(depth);
+                    let right = self.expr(depth);
+                    let operator = token(token_type.clone(), lexeme);
+                    if matches!(token_type, TokenType::Or | TokenType::And) {
+                        ExprKind::Logical(left, operator, right)
+                    } else {
+                        ExprKind::Binary(left, operator, right)
+                    }
+                }
+            };
+            self.ast.add_expr(Span::new(0, 0), kind)
+        }
+
+        /// A variable or index, which compound assignment and `++`/`--`
+        /// require.
+        fn target(&mut self, depth: usize) -> ExprId {
+            let kind = if self.rng.one_in(2) {
+                ExprKind::Variable(self.name())
+            } else {
+                ExprKind::Index(self.expr(depth), bracket(), self.expr(depth))
+            };
+            self.ast.add_expr(Span::new(0, 0), kind)
+        }
+
+        fn literal(&mut self) -> LiteralValue {
+            match self.rng.below(5) {
+                0 => LiteralValue::Nil,
+                1 => LiteralValue::Bool(self.rng.one_in(2)),
+                2 => LiteralValue::String(format!("s{}", self.rng.below(10))),
+                _ => LiteralValue::Number(self.rng.below(1000) as f64 / 4.0),
+            }
+        }
+
+        fn name(&mut self) -> Token {
+            let name = *self.rng.pick(NAMES);
+            token(TokenType::Identifier, name)
+        }
+
+        fn stmt_node(&mut self, kind: StmtKind) -> StmtId {
+            self.ast.add_stmt(Span::new(0, 0), kind)
+        }
+    }
+
+    fn bracket() -> Token {
+        token(TokenType::LeftBracket, "[")
+    }
+
+    fn reparse(source: &str) -> (Ast, Vec<StmtId>) {
+        let mut parser = Parser::new(source);
+        let statements = parser
+            .parse_program()
+            .unwrap_or_else(|e| panic!("{}\nwhile parsing: {}", e, source));
+        (parser.into_ast(), statements)
+    }
+
+    fn unparse(ast: &Ast, statements: &[StmtId]) -> String {
+        statements
+            .iter()
+            .map(|&stmt| format!("{}\n", Unparser::stmt(ast, stmt).unwrap()))
+            .collect()
+    }
+
+    fn structure(ast: &Ast, statements: &[StmtId]) -> Json {
+        ast_export::structure(ast, &Root::Program(statements.to_vec()))
+    }
+
+    /// Replaces `Grouping` nodes with what they group: generated trees have
+    /// none, and the parentheses the unparser adds come back as them.
+    fn without_groupings(json: Json) -> Json {
+        match json {
+            Json::Object(entries) => {
+                if entries.contains(&("kind".to_string(), Json::String("Grouping".to_string()))) {
+                    let (_, inner) = entries
+                        .into_iter()
+                        .find(|(key, _)| key == "expression")
+                        .expect("groupings have an expression");
+                    return without_groupings(inner);
+                }
+                Json::Object(
+                    entries
+                        .into_iter()
+                        .map(|(key, value)| (key, without_groupings(value)))
+                        .collect(),
+                )
+            }
+            Json::Array(items) => Json::Array(items.into_iter().map(without_groupings).collect()),
+            scalar => scalar,
+        }
+    }
+
+    #[test]
+    fn generated_programs_round_trip() {
+        let mut rng = Rng::new(0x2545_f491_4f6c_dd1d);
+        for _ in 0..2000 {
+            let count = 1 + rng.below(3);
+            let mut generator = Generator {
+                rng: &mut rng,
+                ast: Ast::default(),
+                loops: 0,
+                functions: 0,
+            };
+            let statements = generator.program(count);
+            let ast = generator.ast;
+            let source = unparse(&ast, &statements);
+
+            let (reparsed, again) = reparse(&source);
+            assert_eq!(
+                without_groupings(structure(&reparsed, &again)),
+                structure(&ast, &statements),
+                "unparsed as: {}",
+                source
+            );
+        }
+    }
+
+    #[test]
+    fn parsed_programs_round_trip_exactly() {
+        let sources = [
+            "print 1 + 2 * 3;",
+            "print (1 + 2) * 3;",
+            "print 1 - (2 - 3);",
+            "print 2 ** 3 ** 2;",
+            "print (2 ** 3) ** 2;",
+            "print 2 ** -1;",
+            "print (2 ** -1) ** 2;",
+            "print -2 ** 2;",
+            "print (-2) ** 2;",
+            "print - -1;",
+            "print !(true and false) or nil;",
+            "print true ? 1 : false ? 2 : 3;",
+            "print (nil ? 1 : 2) ? 3 : 4;",
+            "print 1 ? 2, 3 : 4;",
+            "1, (2, 3);",
+            "print \"hi\" + \"there\";",
+            "print 1.5 % 0.25 == 2;",
+            "print a; print \"a\"; print \"1.0\";",
+            "var xs = [1, [2, 3]]; xs[0] = xs[1][:1];",
+            "({\"a\": 1}[\"a\"] += 2);",
+            "print -xs[0]--; print - --a; print (++a)[0];",
+            "for (x in 0..=3) { if_ = x; break; }",
+            "try { throw nil; } catch (e) { print e.message; } finally {}",
+            "match (xs) { [1, -2, ..rest] if rest => print rest; _ => {} }",
+            "import \"m.lox\" as m; from \"m.lox\" import a, b;",
+            "export var e = a.b(1, 2)(3);",
+            "fun f(a, b) { return a + b; } export fun g() { return; }",
+            "var f = fun (a) { for (x in a) return x; }; fun (b) {}(1);",
+            "var g = (a, b) => a ? b : ((c) => c), h = () => ({});",
+            "print ((a) => a)(1) + (fun () { return 2; })();",
+            "print match (a) { [x, _, _] if x => x, _ => {} }[0] + match (b) {};",
+            "(match (a) { 1 => 2 }); var f = () => match (a) { _ => (1, 2) };",
+        ];
+        for source in sources {
+            let (ast, statements) = reparse(source);
+            let printed = unparse(&ast, &statements);
+            let (reparsed, again) = reparse(&printed);
+            assert_eq!(
+                structure(&ast, &statements),
+                structure(&reparsed, &again),
+                "unparsed {} as {}",
+                source,
+                printed
+            );
+        }
+    }
+
+    #[test]
+    fn adds_only_needed_parentheses() {
+        let cases = [
+            ("print (1 + 2) * 3;", "print (1 + 2) * 3;"),
+            ("print 2 ** -1;", "print 2 ** -1;"),
+            ("print 1;", "print 1;"),
+        ];
+        for (source, expected) in cases {
+            let (ast, statements) = reparse(source);
+            assert_eq!(Unparser::stmt(&ast, statements[0]).unwrap(), expected);
+        }
+    }
+
+    #[test]
+    fn strings_with_quotes_are_an_error() {
+        let mut ast = Ast::default();
+        let string = LiteralValue::String("say \"hi\"".to_string());
+        let expr = ast.add_expr(Span::new(0, 0), ExprKind::Literal(string));
+        assert_eq!(
+            Unparser::expr(&ast, expr),
+            Err(
+                "Can't print the string \"say \\\"hi\\\"\" as Lox: strings can't contain '\"'."
+                    .to_string()
+            )
+        );
+    }
+}
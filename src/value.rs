@@ -0,0 +1,270 @@
+// value.rs
+use crate::ast::{Ast, ExprId, LiteralValue};
+use crate::map::Map;
+use crate::module::Module;
+use crate::range::Range;
+use crate::tokenizer::Span;
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::fmt;
+use std::ops::RangeInclusive;
+use std::path::PathBuf;
+use std::rc::Rc;
+
+/// A value at run time. Heap objects are reference-counted, so copying a
+/// `Value` is cheap and objects are shared rather than duplicated.
+#[derive(Debug, Clone)]
+pub enum Value {
+    Nil,
+    Bool(bool),
+    Number(f64),
+    String(Rc<str>),
+    List(Rc<RefCell<Vec<Value>>>),
+    Map(Rc<RefCell<Map>>),
+    /// Ranges are immutable, so they are copied rather than shared.
+    Range(Range),
+    /// A built-in method bound to its receiver, e.g. `xs.push`.
+    Native(Rc<NativeMethod>),
+    /// A function declared with `fun` or written as an expression.
+    Function(Rc<Function>),
+    /// A caught runtime error, e.g. `e` in `catch (e)`.
+    Error(Rc<ErrorObject>),
+    /// An imported file, e.g. `m` in `import "mod.lox" as m;`.
+    Module(Rc<Module>),
+}
+
+#[derive(Debug)]
+pub struct ErrorObject {
+    pub message: String,
+    pub line: usize,
+    /// Kept so that rethrowing reports the original location.
+    pub span: Span,
+    /// The file that `line` and `span` refer to.
+    pub file: Option<PathBuf>,
+}
+
+/// The variables of one scope. Scopes are shared so that functions can
+/// keep the ones they were created in.
+pub type Scope = Rc<RefCell<HashMap<String, Value>>>;
+
+pub struct Function {
+    /// The declared name; function expressions have none.
+    pub name: Option<String>,
+    /// The tree holding the function's `Function` node, kept alive for as
+    /// long as the function is.
+    pub ast: Rc<Ast>,
+    pub node: ExprId,
+    /// The scopes the function was created in, outermost first.
+    pub closure: Vec<Scope>,
+    /// The file the function was created in, which its body's errors refer
+    /// to.
+    pub file: Option<PathBuf>,
+}
+
+/// Closures can refer to themselves through their scopes, so this only
+/// names the function.
+impl fmt::Debug for Function {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("Function")
+            .field("name", &self.name)
+            .finish_non_exhaustive()
+    }
+}
+
+/// Gets the receiver and as many arguments as the arity allows. Errors are
+/// messages for a runtime error at the call.
+pub type NativeFn = fn(&Value, Vec<Value>) -> Result<Value, String>;
+
+#[derive(Debug)]
+pub struct NativeMethod {
+    pub name: &'static str,
+    pub arity: RangeInclusive<usize>,
+    pub receiver: Value,
+    pub function: NativeFn,
+}
+
+/// Looks up `name` in a table of `(name, arity, function)` methods and binds
+/// it to `receiver`.
+pub fn bind(
+    methods: &[(&'static str, usize, NativeFn)],
+    receiver: &Value,
+    name: &str,
+) -> Option<Value> {
+    let &(name, arity, function) = methods.iter().find(|(method, ..)| *method == name)?;
+    Some(Value::Native(Rc::new(NativeMethod {
+        name,
+        arity: arity..=arity,
+        receiver: receiver.clone(),
+        function,
+    })))
+}
+
+impl Value {
+    /// `nil` and `false` are falsey; everything else is truthy.
+    pub fn is_truthy(&self) -> bool {
+        match self {
+            Value::Nil => false,
+            Value::Bool(b) => *b,
+            _ => true,
+        }
+    }
+}
+
+/// The items a `for-in` loop visits.
+pub enum Iter {
+    /// Elements by position, so elements added during the loop are visited
+    /// too.
+    List(Rc<RefCell<Vec<Value>>>, usize),
+    /// A map's keys as they were when the loop started.
+    Keys(std::vec::IntoIter<Value>),
+    /// Characters as one-character strings, by byte offset.
+    Chars(Rc<str>, usize),
+    /// The numbers of a range, by position.
+    Range(Range, usize),
+}
+
+impl Iterator for Iter {
+    type Item = Value;
+
+    fn next(&mut self) -> Option<Value> {
+        match self {
+            Iter::List(list, next) => {
+                let item = list.borrow().get(*next).cloned()?;
+                *next += 1;
+                Some(item)
+            }
+            Iter::Keys(keys) => keys.next(),
+            Iter::Range(range, next) => {
+                let item = range.get(*next)?;
+                *next += 1;
+                Some(Value::Number(item as f64))
+            }
+            Iter::Chars(string, offset) => {
+                let c = string[*offset..].chars().next()?;
+                let item = Value::String(Rc::from(&string[*offset..*offset + c.len_utf8()]));
+                *offset += c.len_utf8();
+                Some(item)
+            }
+        }
+    }
+}
+
+impl Value {
+    /// Iterates over a list, a map's keys, a string's characters or a
+    /// range, or returns `None` for values that can't be iterated.
+    pub fn iterate(&self) -> Option<Iter> {
+        match self {
+            Value::List(list) => Some(Iter::List(list.clone(), 0)),
+            Value::Map(map) => {
+                let keys: Vec<Value> = map.borrow().iter().map(|(key, _)| key.to_value()).collect();
+                Some(Iter::Keys(keys.into_iter()))
+            }
+            Value::String(string) => Some(Iter::Chars(string.clone(), 0)),
+            Value::Range(range) => Some(Iter::Range(*range, 0)),
+            _ => None,
+        }
+    }
+}
+
+impl From<&LiteralValue> for Value {
+    fn from(literal: &LiteralValue) -> Self {
+        match literal {
+            LiteralValue::Number(n) => Value::Number(*n),
+            LiteralValue::String(s) => Value::String(Rc::from(s.as_str())),
+            LiteralValue::Bool(b) => Value::Bool(*b),
+            LiteralValue::Nil => Value::Nil,
+        }
+    }
+}
+
+/// Lox equality: values of different types are never equal, numbers
+/// compare as IEEE doubles (so `NaN != NaN`), strings and ranges by content
+/// and other objects by identity.
+impl PartialEq for Value {
+    fn eq(&self, other: &Value) -> bool {
+        match (self, other) {
+            (Value::Nil, Value::Nil) => true,
+            (Value::Bool(a), Value::Bool(b)) => a == b,
+            (Value::Number(a), Value::Number(b)) => a == b,
+            (Value::String(a), Value::String(b)) => a == b,
+            (Value::List(a), Value::List(b)) => Rc::ptr_eq(a, b),
+            (Value::Map(a), Value::Map(b)) => Rc::ptr_eq(a, b),
+            (Value::Range(a), Value::Range(b)) => a == b,
+            (Value::Native(a), Value::Native(b)) => Rc::ptr_eq(a, b),
+            (Value::Function(a), Value::Function(b)) => Rc::ptr_eq(a, b),
+            (Value::Error(a), Value::Error(b)) => Rc::ptr_eq(a, b),
+            (Value::Module(a), Value::Module(b)) => Rc::ptr_eq(a, b),
+            _ => false,
+        }
+    }
+}
+
+/// Formats values the way jlox prints them: integral numbers without a
+/// trailing `.0`, strings without quotes. A list or map that contains itself
+/// prints the inner occurrence as `[...]` or `{...}`.
+impl fmt::Display for Value {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        self.write(f, &mut Vec::new())
+    }
+}
+
+impl Value {
+    /// Writes the value; `open` holds the lists currently being written.
+    fn write(
+        &self,
+        f: &mut fmt::Formatter,
+        open: &mut Vec<*const RefCell<Vec<Value>>>,
+    ) -> fmt::Result {
+        match self {
+            Value::Nil => write!(f, "nil"),
+            Value::Bool(b) => write!(f, "{}", b),
+            Value::Number(n) if n.is_infinite() => {
+                write!(f, "{}", if *n > 0.0 { "Infinity" } else { "-Infinity" })
+            }
+            Value::Number(n) => write!(f, "{}", n),
+            Value::String(s) => write!(f, "{}", s),
+            Value::List(list) => {
+                let ptr = Rc::as_ptr(list).cast();
+                if open.contains(&ptr) {
+                    return write!(f, "[...]");
+                }
+                open.push(ptr);
+                write!(f, "[")?;
+                for (i, element) in list.borrow().iter().enumerate() {
+                    if i > 0 {
+                        write!(f, ", ")?;
+                    }
+                    element.write(f, open)?;
+                }
+                open.pop();
+                write!(f, "]")
+            }
+            Value::Map(map) => {
+                let ptr = Rc::as_ptr(map).cast();
+                if open.contains(&ptr) {
+                    return write!(f, "{{...}}");
+                }
+                open.push(ptr);
+                write!(f, "{{")?;
+                for (i, (key, value)) in map.borrow().iter().enumerate() {
+                    if i > 0 {
+                        write!(f, ", ")?;
+                    }
+                    key.to_value().write(f, open)?;
+                    write!(f, ": ")?;
+                    value.write(f, open)?;
+                }
+                open.pop();
+                write!(f, "}}")
+            }
+            Value::Range(range) => write!(f, "{}", range),
+            Value::Native(method) => write!(f, "<native fn {}>", method.name),
+            Value::Function(function) => match &function.name {
+                Some(name) => write!(f, "<fn {}>", name),
+                None => write!(f, "<fn>"),
+            },
+            Value::Error(error) => write!(f, "Error: {}", error.message),
+            Value::Module(module) => write!(f, "<module {}>", module.name),
+        }
+    }
+}
@@ -0,0 +1,125 @@
+//! Runs the golden-file suite in `tests/lox` through the `test` command,
+//! plus behaviour the annotations can't express.
+use std::fs;
+use std::path::Path;
+use std::process::{Command, Output};
+
+fn run_suite(dir: &Path) -> Output {
+    Command::new(env!("CARGO_BIN_EXE_interpreter-starter-rust"))
+        .arg("test")
+        .arg(dir)
+        .output()
+        .expect("failed to run the interpreter")
+}
+
+#[test]
+fn golden_files_pass() {
+    let dir = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/lox");
+    let output = run_suite(&dir);
+    assert!(
+        output.status.success(),
+        "{}",
+        String::from_utf8_lossy(&output.stdout)
+    );
+}
+
+#[test]
+fn mismatches_are_reported() {
+    let dir = Path::new(env!("CARGO_TARGET_TMPDIR")).join("conformance_mismatch");
+    let _ = fs::remove_dir_all(&dir);
+    fs::create_dir_all(dir.join("chapter")).unwrap();
+    fs::write(
+        dir.join("chapter/wrong.lox"),
+        "print 1 + 1; // expect: 3\nprint -nil; // expect runtime error: Oops.\n",
+    )
+    .unwrap();
+    fs::write(
+        dir.join("chapter/right.lox"),
+        "print true; // expect: true\n",
+    )
+    .unwrap();
+    fs::write(dir.join("helper.lox"), "// nontest\nprint 1;\n").unwrap();
+
+    let output = run_suite(&dir);
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert_eq!(output.status.code(), Some(1), "{}", stdout);
+    assert!(stdout.contains("FAIL chapter/wrong.lox"), "{}", stdout);
+    assert!(
+        stdout.contains("Expected output '3' on line 1 and got '2'."),
+        "{}",
+        stdout
+    );
+    assert!(
+        stdout.contains("Expected runtime error 'Oops.' and got: Operand must be a number."),
+        "{}",
+        stdout
+    );
+    assert!(!stdout.contains("right.lox"), "{}", stdout);
+    assert!(
+        stdout.contains("1 tests passed. 1 tests failed."),
+        "{}",
+        stdout
+    );
+}
+
+#[test]
+fn imports_search_lox_path() {
+    let dir = Path::new(env!("CARGO_TARGET_TMPDIR")).join("conformance_lox_path");
+    let _ = fs::remove_dir_all(&dir);
+    fs::create_dir_all(dir.join("first")).unwrap();
+    fs::create_dir_all(dir.join("second")).unwrap();
+    fs::create_dir_all(dir.join("app")).unwrap();
+    fs::write(
+        dir.join("first/shadowed.lox"),
+        "export var name = \"first\";\n",
+    )
+    .unwrap();
+    fs::write(
+        dir.join("second/shadowed.lox"),
+        "export var name = \"second\";\n",
+    )
+    .unwrap();
+    fs::write(dir.join("second/only.lox"), "export var name = \"only\";\n").unwrap();
+    fs::write(
+        dir.join("app/main.lox"),
+        "from \"shadowed.lox\" import name;\nprint name;\n\
+         import \"only.lox\" as only;\nprint only.name;\n",
+    )
+    .unwrap();
+
+    let lox_path = std::env::join_paths([dir.join("first"), dir.join("second")]).unwrap();
+    let output = Command::new(env!("CARGO_BIN_EXE_interpreter-starter-rust"))
+        .arg("run")
+        .arg(dir.join("app/main.lox"))
+        .env("LOX_PATH", lox_path)
+        .output()
+        .expect("failed to run the interpreter");
+    assert_eq!(
+        String::from_utf8_lossy(&output.stdout),
+        "first\nonly\n",
+        "{}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+}
+
+#[test]
+fn cst_reports_scan_errors_once() {
+    let dir = Path::new(env!("CARGO_TARGET_TMPDIR")).join("conformance_cst");
+    fs::create_dir_all(&dir).unwrap();
+    let file = dir.join("scan_error.lox");
+    fs::write(&file, "print 1; @\n").unwrap();
+
+    let output = Command::new(env!("CARGO_BIN_EXE_interpreter-starter-rust"))
+        .arg("cst")
+        .arg(&file)
+        .output()
+        .expect("failed to run the interpreter");
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert_eq!(output.status.code(), Some(65), "{}", stderr);
+    assert_eq!(
+        stderr.matches("Unexpected character: @").count(),
+        1,
+        "{}",
+        stderr
+    );
+}